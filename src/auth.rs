@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use crate::config::{Action, ApiToken, Kind, User};
+use crate::events::HouseEvent;
+use crate::hex;
+use crate::house::{Command, Room, State};
+
+/// sessions end after a month without being used
+const SESSION: u64 = 30 * 24 * 60 * 60 * 1000;
+
+lazy_static! {
+    /// checked when the user is unknown, so it takes as long as a wrong password
+    static ref UNKNOWN: String = hash("");
+}
+
+/// what a user or a token may control, everything else being read only
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Permission {
+    All,
+    Scenes,
+    Room(Room),
+    Device(Kind, Option<Room>),
+}
+
+/// `all`, `scenes`, a room like `BedRoom` or a device like `light BedRoom` or `screen`
+impl FromStr for Permission {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(room) = s.parse() {
+            return Ok(Permission::Room(room));
+        }
+        match s.split_once(' ') {
+            None if s == "all" => Ok(Permission::All),
+            None if s == "scenes" => Ok(Permission::Scenes),
+            None if s == "screen" => Ok(Permission::Device(Kind::Screen, None)),
+            Some(("light", room)) => Ok(Permission::Device(Kind::Light, Some(room.parse()?))),
+            Some(("blinds", room)) => Ok(Permission::Device(Kind::Blinds, Some(room.parse()?))),
+            _ => Err("Unknown permission"),
+        }
+    }
+}
+
+/// who is calling and what they may do
+#[derive(Clone, Debug, PartialEq)]
+pub struct Access {
+    pub name: Option<String>,
+    permissions: Vec<Permission>,
+}
+
+impl Access {
+    /// when no user nor token is configured
+    pub fn everything() -> Self {
+        Access { name: None, permissions: vec![Permission::All] }
+    }
+
+    /// for callers who aren't users nor tokens, like the clients of an mqtt broker
+    pub fn new(name: &str, permissions: Vec<Permission>) -> Self {
+        Access { name: Some(name.to_string()), permissions }
+    }
+
+    pub fn all(&self) -> bool {
+        self.permissions.contains(&Permission::All)
+    }
+
+    pub fn scenes(&self) -> bool {
+        self.all() || self.permissions.contains(&Permission::Scenes)
+    }
+
+    pub fn device(&self, kind: Kind, room: Option<Room>) -> bool {
+        self.all() || self.permissions.iter().any(|&p| p == Permission::Device(kind, room) || room.map_or(false, |r| p == Permission::Room(r)))
+    }
+
+    pub fn command(&self, command: Command) -> bool {
+        match command {
+            Command::Light(room, _) => self.device(Kind::Light, Some(room)),
+            Command::Blinds(room, _) => self.device(Kind::Blinds, Some(room)),
+            Command::Screen(_) => self.device(Kind::Screen, None),
+        }
+    }
+
+    /// the part of the state of the house the caller may control
+    pub fn state(&self, state: &State) -> State {
+        State {
+            lights: state.lights.iter().filter(|(&room, _)| self.device(Kind::Light, Some(room))).map(|(&r, &s)| (r, s)).collect(),
+            blinds: state.blinds.iter().filter(|(&room, _)| self.device(Kind::Blinds, Some(room))).map(|(&r, &s)| (r, s)).collect(),
+            screen: state.screen.filter(|_| self.device(Kind::Screen, None)),
+            vacation: state.vacation && self.scenes(),
+            levels: state.levels.iter().filter(|(&room, _)| self.device(Kind::Light, Some(room))).map(|(&r, &l)| (r, l)).collect(),
+            positions: state.positions.iter().filter(|(&room, _)| self.device(Kind::Blinds, Some(room))).map(|(&r, &p)| (r, p)).collect(),
+        }
+    }
+
+    /// what the caller may follow of an event, raw frames, errors and reloads are for those who may do everything
+    pub fn event(&self, event: HouseEvent) -> Option<HouseEvent> {
+        match event {
+            HouseEvent::CommandSent { command, .. } if !self.command(command) => None,
+            HouseEvent::StateChanged { state } => Some(HouseEvent::StateChanged { state: self.state(&state) }),
+            HouseEvent::SceneStarted { .. } | HouseEvent::SceneFinished { .. } if !self.scenes() => None,
+            HouseEvent::Frame { .. } | HouseEvent::Error { .. } | HouseEvent::RulesReloaded if !self.all() => None,
+            event => Some(event),
+        }
+    }
+
+    /// whether the caller may run the action, webhooks can call anything so they need every permission
+    pub fn action(&self, action: &Action) -> bool {
+        match *action {
+            Action::Scene { .. } => self.scenes(),
+            Action::Light { light, .. } => self.device(Kind::Light, Some(light)),
+            Action::Blinds { blinds, .. } => self.device(Kind::Blinds, Some(blinds)),
+            Action::Screen { .. } => self.device(Kind::Screen, None),
+            Action::Webhook { .. } => self.all(),
+        }
+    }
+}
+
+/// Users log in with a password and get a session, scripts send a bearer token.
+pub struct Auth {
+    users: Vec<User>,
+    tokens: Vec<ApiToken>,
+    /// session id to user name and last use
+    sessions: Mutex<HashMap<String, (String, u64)>>,
+    /// tokens already checked against their hash, hashing is slow on purpose
+    verified: Mutex<HashMap<String, usize>>,
+}
+
+impl Auth {
+    pub fn new(users: Vec<User>, tokens: Vec<ApiToken>) -> Self {
+        Auth { users, tokens, sessions: Mutex::new(HashMap::new()), verified: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.users.is_empty() || !self.tokens.is_empty()
+    }
+
+    /// a new session id when the password is right
+    pub fn login(&self, name: &str, password: &str, now: u64) -> Option<String> {
+        let user = self.users.iter().find(|u| u.name == name);
+        if !verify(password, user.map_or(&UNKNOWN, |u| &u.password)) {
+            return None;
+        }
+        let user = user?;
+        let mut id = [0; 32];
+        OsRng.fill_bytes(&mut id);
+        let id = hex::encode(&id);
+        self.sessions.lock().expect("Can't get lock on sessions").insert(id.clone(), (user.name.clone(), now));
+        Some(id)
+    }
+
+    pub fn logout(&self, session: &str) {
+        self.sessions.lock().expect("Can't get lock on sessions").remove(session);
+    }
+
+    pub fn session(&self, session: &str, now: u64) -> Option<Access> {
+        let mut sessions = self.sessions.lock().expect("Can't get lock on sessions");
+        sessions.retain(|_, (_, used)| now < *used + SESSION);
+        let (name, used) = sessions.get_mut(session)?;
+        *used = now;
+        let user = self.users.iter().find(|u| u.name == *name)?;
+        Some(Access { name: Some(user.name.clone()), permissions: user.permissions.clone() })
+    }
+
+    pub fn token(&self, token: &str) -> Option<Access> {
+        let mut verified = self.verified.lock().expect("Can't get lock on tokens");
+        let index = match verified.get(token) {
+            Some(&index) => index,
+            None => {
+                let index = self.tokens.iter().position(|t| verify(token, &t.token))?;
+                verified.insert(token.to_string(), index);
+                index
+            }
+        };
+        let token = &self.tokens[index];
+        Some(Access { name: Some(token.name.clone()), permissions: token.permissions.clone() })
+    }
+}
+
+/// the PHC string of a password, salted
+pub fn hash(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default().hash_password(password.as_bytes(), &salt).expect("Can't hash password").to_string()
+}
+
+pub fn valid_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok()
+}
+
+fn verify(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).map_or(false, |hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    fn auth() -> Auth {
+        let users = vec![User { name: "xavier".to_string(), password: hash("secret"), permissions: vec![Permission::All] }];
+        let tokens = vec![ApiToken { name: "guest".to_string(), token: hash("guest-token"), permissions: vec!["light BedRoom".parse().unwrap()] }];
+        Auth::new(users, tokens)
+    }
+
+    #[test]
+    fn parse_permissions() {
+        assert_that!(&"all".parse(), eq(Ok(Permission::All)));
+        assert_that!(&"scenes".parse(), eq(Ok(Permission::Scenes)));
+        assert_that!(&"Kitchen".parse(), eq(Ok(Permission::Room(Room::Kitchen))));
+        assert_that!(&"blinds LivingRoom".parse(), eq(Ok(Permission::Device(Kind::Blinds, Some(Room::LivingRoom)))));
+        assert_that!(&"screen".parse(), eq(Ok(Permission::Device(Kind::Screen, None))));
+        assert_that!(&"garage".parse::<Permission>().is_err(), eq(true));
+    }
+
+    #[test]
+    fn log_users_in_with_their_password() {
+        let auth = auth();
+        assert_that!(&auth.login("xavier", "wrong", 0), eq(None));
+        assert_that!(&auth.login("bob", "", 0), eq(None));
+        let session = auth.login("xavier", "secret", 0).unwrap();
+        assert_that!(&auth.session(&session, 1000).map(|a| a.all()), eq(Some(true)));
+        assert_that!(&auth.session(&session, 1000 + SESSION), eq(None));
+        let session = auth.login("xavier", "secret", 0).unwrap();
+        auth.logout(&session);
+        assert_that!(&auth.session(&session, 1000), eq(None));
+    }
+
+    #[test]
+    fn limit_tokens_to_their_devices() {
+        let auth = auth();
+        assert_that!(&auth.token("guess"), eq(None));
+        let guest = auth.token("guest-token").unwrap();
+        assert_that!(&guest.device(Kind::Light, Some(Room::BedRoom)), eq(true));
+        assert_that!(&guest.device(Kind::Blinds, Some(Room::BedRoom)), eq(false));
+        assert_that!(&guest.device(Kind::Light, Some(Room::Kitchen)), eq(false));
+        assert_that!(&guest.scenes(), eq(false));
+        assert_that!(&auth.token("guest-token"), eq(Some(guest)));
+    }
+
+    #[test]
+    fn allow_whole_rooms() {
+        let access = Access { name: None, permissions: vec![Permission::Room(Room::Kitchen), Permission::Scenes] };
+        assert_that!(&access.device(Kind::Blinds, Some(Room::Kitchen)), eq(true));
+        assert_that!(&access.device(Kind::Screen, None), eq(false));
+        assert_that!(&access.scenes(), eq(true));
+        assert_that!(&access.all(), eq(false));
+    }
+}
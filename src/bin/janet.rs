@@ -0,0 +1,93 @@
+use std::env;
+use std::path::Path;
+use std::process;
+use janet::capture::{self, Format};
+use janet::cli::{self, Order, Server};
+use janet::config::Configuration;
+use janet::hex;
+use janet::house::{house, House};
+use janet::inference;
+use janet::radio_protocol::{self, RadioProtocol};
+use janet::transmission::{Transmission, Transmitted};
+
+/// drives the house from a shell, through a running server or with the local radios
+fn main() {
+    let options = cli::parse(env::args().skip(1)).unwrap_or_else(|e| exit(e));
+    let server = options.server.or_else(|| env::var("JANET_SERVER").ok());
+    let token = options.token.or_else(|| env::var("JANET_TOKEN").ok());
+    let certificate = options.certificate.or_else(|| env::var("JANET_CERTIFICATE").ok());
+    let result = match (server, &options.order) {
+        (Some(url), order) if !matches!(order, Order::Import { .. } | Order::Export { .. }) => remote(&url, token, certificate.as_deref(), order),
+        _ => local(&options.configuration, &options.order),
+    };
+    if let Err(e) = result {
+        exit(e);
+    }
+}
+
+fn remote(url: &str, token: Option<String>, certificate: Option<&str>, order: &Order) -> Result<(), String> {
+    let answer = Server::new(url, token, certificate)?.order(order).map_err(|e| e.to_string())?;
+    match order {
+        Order::State => {
+            let state: serde_json::Value = serde_json::from_str(&answer).map_err(|e| format!("Invalid state {}: {}", answer, e))?;
+            println!("{}", serde_json::to_string_pretty(&state).expect("State is json"));
+        }
+        Order::Transmit { .. } => {
+            print(&serde_json::from_str(&answer).map_err(|e| format!("Invalid transmission {}: {}", answer, e))?);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn local(configuration: &str, order: &Order) -> Result<(), String> {
+    let configuration = Configuration::load(configuration)?;
+    let house = || house(&configuration);
+    match order {
+        Order::Light { room, status } => house().light(*room, *status),
+        Order::Blinds { room, status } => house().blinds(*room, *status),
+        Order::Screen { status } => house().screen(*status),
+        Order::Scene { scene } => house().scene(*scene),
+        Order::State => return Err("Only a running server knows the state of the house, give it with --server".to_string()),
+        Order::Send { code, button, radio } => {
+            Transmission::frame(*code, *button, radio.clone()).transmit(&house())?;
+        }
+        Order::Transmit { transmission } => print(&transmission.transmit(&house())?),
+        Order::Import { file, format, sample_rate, name } => import(&protocols(&configuration), file, *format, *sample_rate, name)?,
+        Order::Export { file, format, sample_rate, transmission } => {
+            let protocols = protocols(&configuration);
+            let (protocol, payload) = transmission.on_air(|name| protocols.iter().find(|p| p.name() == name).cloned())?;
+            capture::export(Path::new(file), *format, &protocol.timings_for_bytes(&payload), *sample_rate).map_err(|e| format!("Can't write {}: {}", file, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// the configured protocols and the bundled ones
+fn protocols(configuration: &Configuration) -> Vec<RadioProtocol<Vec<u8>>> {
+    configuration.protocols.iter().chain(radio_protocol::BUNDLED.iter()).cloned().collect()
+}
+
+/// prints the definition of the protocol of the capture, and how to send it again
+fn import(known: &[RadioProtocol<Vec<u8>>], file: &str, format: Format, sample_rate: Option<u32>, name: &str) -> Result<(), String> {
+    let signals = capture::import(Path::new(file), format, sample_rate).map_err(|e| format!("Can't read {}: {}", file, e))?;
+    let (protocol, payload) = inference::infer(name, &signals, known)?;
+    for protocol in known {
+        if let Some(payload) = protocol.decode(&signals).first() {
+            println!("# already sent like the {} protocol: janet transmit --protocol {} --payload {}", protocol.name(), protocol.name(), hex::encode(payload));
+        }
+    }
+    print!("{}", radio_protocol::definitions(vec![protocol]));
+    println!("# janet transmit --protocol {} --payload {}", name, hex::encode(&payload));
+    Ok(())
+}
+
+fn print(transmitted: &Transmitted) {
+    println!("{}", transmitted.protocol);
+    println!("{}", transmitted.signals.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" "));
+}
+
+fn exit(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
@@ -0,0 +1,10 @@
+use std::io::{self, BufRead};
+use janet::auth;
+
+/// reads a password or a token on stdin and prints the hash to put in janet.toml
+fn main() -> io::Result<()> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    println!("{}", auth::hash(password.trim_end_matches(['\r', '\n'])));
+    Ok(())
+}
@@ -4,23 +4,51 @@ extern crate sysfs_gpio;
 
 use std::thread;
 use std::sync::Arc;
-use rocket::State;
+use rocket::{Config, Rocket, State};
+use rocket::config::LoggingLevel;
 use rocket_contrib::templates::Template;
 use rocket_contrib::serve::StaticFiles;
-use rocket::response::Redirect;
-use rocket::request::Form;
+use rocket_contrib::json::Json;
+use rocket::response::{status, Redirect};
+use rocket::request::{self, Form, FromRequest, Request};
+use rocket::outcome::Outcome;
 use janet::house::*;
+use janet::config::Configuration;
+use janet::receiver;
+use janet::trigger::{self, Triggers};
+use janet::weather::Weather;
+use janet::sensor::Sensors;
+use janet::config::BinarySensor;
+use janet::clock;
+use janet::schedule::{self, Scheduler};
+use janet::vacation::{self, Vacation};
+use janet::timer::{self, Timers};
+use janet::config::{Action, Device, Kind, Tls};
+use janet::page::Page;
+use janet::rule::{self, Event, Rules};
+use rocket::response::content;
+use rocket::http::{ContentType, Cookie, Cookies, Method, SameSite, Status};
+use rocket::http::uri::Origin;
+use rocket::fairing::AdHoc;
+use rocket::response::Stream;
+use janet::events;
+use janet::auth::{Access, Auth};
+use janet::tls;
+use janet::mqtt::{self, Bridge};
+use janet::hermes;
+use janet::transmission::{self, Transmission};
+use janet::limit::{RateLimit, Refusal};
 
 
 #[macro_use]
 extern crate rocket;
 
 pub struct SafeHouse {
-    house: Arc<House + Send + Sync>
+    house: Arc<dyn House + Send + Sync>
 }
 
 impl SafeHouse {
-    fn execute<F>(&self, f: F) where F: FnOnce(Arc<House>) + Send + 'static {
+    fn execute<F>(&self, f: F) where F: FnOnce(Arc<dyn House>) + Send + 'static {
         let h = self.house.clone();
         thread::spawn(move || {
             f(h);
@@ -28,45 +56,298 @@ impl SafeHouse {
     }
 }
 
+const SESSION: &str = "session";
+
+/// a refused request with the reason
+type Refused = status::Custom<String>;
+
+/// who calls, from a bearer token or the session cookie, anyone when there are no users nor tokens
+pub struct Caller(Access);
+
+impl Caller {
+    fn device(&self, kind: Kind, room: Option<Room>) -> Result<(), Refused> {
+        if self.0.device(kind, room) { Ok(()) } else { Err(self.forbidden()) }
+    }
+
+    fn scenes(&self) -> Result<(), Refused> {
+        if self.0.scenes() { Ok(()) } else { Err(self.forbidden()) }
+    }
+
+    fn all(&self) -> Result<(), Refused> {
+        if self.0.all() { Ok(()) } else { Err(self.forbidden()) }
+    }
+
+    fn forbidden(&self) -> Refused {
+        status::Custom(Status::Forbidden, format!("Not allowed for {}", self.0.name.as_deref().unwrap_or("anyone")))
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Caller {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let auth = match request.guard::<State<Arc<Auth>>>() {
+            Outcome::Success(auth) => auth,
+            _ => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        if !auth.enabled() {
+            return Outcome::Success(Caller(Access::everything()));
+        }
+        let token = request.headers().get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .and_then(|token| auth.token(token));
+        let session = || request.cookies().get(SESSION).and_then(|cookie| auth.session(cookie.value(), clock::now()));
+        match token.or_else(session) {
+            Some(access) => Outcome::Success(Caller(access)),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// every request of a client over its limit, whatever route it was for, login and static files included
+#[get("/limited")]
+fn limited() -> Status {
+    Status::TooManyRequests
+}
+
+/// sends the requests of clients over their limit to `limited` before any route sees them
+fn rate_limit() -> AdHoc {
+    AdHoc::on_request("Rate limit", |request, _| {
+        let allowed = match request.guard::<State<RateLimit>>().succeeded() {
+            Some(limit) => match limit.client(request.remote().map(|a| a.ip()), request.real_ip()) {
+                Some(client) => limit.allow(client, clock::now()),
+                None => true,
+            },
+            None => true,
+        };
+        if !allowed {
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse("/limited").expect("Valid uri"));
+        }
+    })
+}
+
+/// every device the caller may control with its known state, the login form for strangers
+#[get("/")]
+fn index(caller: Option<Caller>, house: State<SafeHouse>, devices: State<Vec<Device>>) -> Template {
+    let access = match caller {
+        Some(caller) => caller.0,
+        None => return login_page(None),
+    };
+    let devices: Vec<Device> = devices.iter().filter(|d| access.device(d.kind, d.room)).cloned().collect();
+    let mut page = Page::new(&devices, &house.house.state());
+    if !access.scenes() {
+        page.scenes.clear();
+    }
+    Template::render("index", page)
+}
+
+#[derive(FromForm)]
+struct Login {
+    name: String,
+    password: String,
+}
+
+#[get("/login?<failed>")]
+fn login_page(failed: Option<bool>) -> Template {
+    Template::render("login", serde_json::json!({ "failed": failed.unwrap_or(false) }))
+}
+
+#[post("/login", data = "<login>")]
+fn login(auth: State<Arc<Auth>>, tls: State<Option<Tls>>, mut cookies: Cookies, login: Form<Login>) -> Redirect {
+    match auth.login(&login.name, &login.password, clock::now()) {
+        Some(session) => {
+            let cookie = Cookie::build(SESSION, session).path("/").http_only(true).same_site(SameSite::Strict).secure(tls.is_some());
+            cookies.add(cookie.finish());
+            Redirect::to("/")
+        }
+        None => Redirect::to("/login?failed=true"),
+    }
+}
+
+#[post("/logout")]
+fn logout(auth: State<Arc<Auth>>, mut cookies: Cookies) -> Redirect {
+    if let Some(cookie) = cookies.get(SESSION) {
+        auth.logout(cookie.value());
+    }
+    cookies.remove(Cookie::build(SESSION, "").path("/").finish());
+    Redirect::to("/")
+}
+
 #[derive(FromForm)]
 struct NewStatus {
-    status: String
+    status: String,
+    after: Option<String>,
+    #[form(field = "for")]
+    during: Option<String>,
 }
 
 #[derive(FromForm)]
 struct Order {
     status: String,
     room: String,
+    after: Option<String>,
+    #[form(field = "for")]
+    during: Option<String>,
+}
+
+/// runs the action now or later, things are put back after the duration given `for`,
+/// motors going back and forth too quickly are refused and repeated commands sent once
+fn order(house: &SafeHouse, timers: &Timers, action: Action, after: &Option<String>, during: &Option<String>) -> Result<(), Refused> {
+    let parse = |d: &Option<String>| d.as_ref().map(|d| timer::parse_duration(d).map_err(|e| status::Custom(Status::BadRequest, format!("{} {}", e, d)))).transpose();
+    let (after, during) = (parse(after)?, parse(during)?);
+    if after.is_none() {
+        match action.command().map_or(Ok(()), |command| house.house.check(command)) {
+            Ok(()) => {}
+            Err(Refusal::Duplicate) => return Ok(()),
+            Err(refusal) => return Err(status::Custom(Status::Conflict, format!("{:?} refused, {}", action, refusal))),
+        }
+    }
+    match timers.order(clock::now(), action, after, during) {
+        Ok(Some(action)) => house.execute(move |h| {
+            if let Err(e) = trigger::run(&*h, &action, &serde_json::json!({})) {
+                events::error(format!("Can't run {:?}: {}", action, e));
+            }
+        }),
+        Ok(None) => {}
+        Err(e) => events::error(format!("Can't save timers: {}", e)),
+    }
+    Ok(())
 }
 
 #[post("/light", data = "<status>")]
-fn light(house: State<SafeHouse>, status: Form<Order>) -> Redirect {
-    if let (Ok(room), Ok(status)) = (status.room.parse(), status.status.parse()) {
-        house.execute(move |h| {
-            h.light(room, status);
-        })
+fn light(caller: Caller, house: State<SafeHouse>, timers: State<Arc<Timers>>, status: Form<Order>) -> Result<Redirect, Refused> {
+    if let (Ok(light), Ok(s)) = (status.room.parse(), status.status.parse()) {
+        caller.device(Kind::Light, Some(light))?;
+        order(&house, &timers, Action::Light { light, status: s }, &status.after, &status.during)?;
     }
-    Redirect::to("/")
+    Ok(Redirect::to("/"))
 }
 
 #[post("/blinds", data = "<status>")]
-fn blinds(house: State<SafeHouse>, status: Form<Order>) -> Redirect {
-    if let (Ok(room), Ok(status)) = (status.room.parse(), status.status.parse()) {
-        house.execute(move |h| {
-            h.blinds(room, status);
-        });
+fn blinds(caller: Caller, house: State<SafeHouse>, timers: State<Arc<Timers>>, status: Form<Order>) -> Result<Redirect, Refused> {
+    if let (Ok(blinds), Ok(s)) = (status.room.parse(), status.status.parse()) {
+        caller.device(Kind::Blinds, Some(blinds))?;
+        order(&house, &timers, Action::Blinds { blinds, status: s }, &status.after, &status.during)?;
     }
-    Redirect::to("/")
+    Ok(Redirect::to("/"))
+}
+
+#[derive(FromForm)]
+struct Level {
+    room: String,
+    level: u8,
+}
+
+/// dimmers of the room to a level from 0 to 15
+#[post("/light/level", data = "<level>")]
+fn light_level(caller: Caller, house: State<SafeHouse>, level: Form<Level>) -> Result<Redirect, Refused> {
+    let room = level.room.parse().map_err(|e: &str| status::Custom(Status::BadRequest, e.to_string()))?;
+    caller.device(Kind::Light, Some(room))?;
+    if level.level > 15 {
+        return Err(status::Custom(Status::BadRequest, format!("Dimmers go from 0 to 15, not {}", level.level)));
+    }
+    let level = level.level;
+    house.execute(move |h| h.dim(room, level));
+    Ok(Redirect::to("/"))
+}
+
+#[derive(FromForm)]
+struct Position {
+    room: String,
+    position: u8,
+}
+
+/// blinds of the room moved on the way and stopped after part of their travel time, 100 is all the way up
+#[post("/blinds/position", data = "<position>")]
+fn blinds_position(caller: Caller, house: State<SafeHouse>, timers: State<Arc<Timers>>, devices: State<Vec<Device>>, position: Form<Position>) -> Result<Redirect, Refused> {
+    let invalid = |e: String| status::Custom(Status::BadRequest, e);
+    let room = position.room.parse().map_err(|e: &str| invalid(e.to_string()))?;
+    caller.device(Kind::Blinds, Some(room))?;
+    let to = position.position;
+    match to {
+        0 => order(&house, &timers, Action::Blinds { blinds: room, status: BlindStatus::DOWN }, &None, &None)?,
+        100 => order(&house, &timers, Action::Blinds { blinds: room, status: BlindStatus::UP }, &None, &None)?,
+        _ if to > 100 => return Err(invalid(format!("Positions go from 0 to 100, not {}", to))),
+        _ => {
+            let travel = devices.iter()
+                .find(|d| d.kind == Kind::Blinds && d.room == Some(room))
+                .and_then(|d| d.travel)
+                .ok_or_else(|| invalid(format!("The blinds of the {:?} can't stop on the way without a travel time", room)))?;
+            let from = *house.house.state().positions.get(&room)
+                .ok_or_else(|| invalid(format!("Where the blinds of the {:?} are is unknown, move them up or down first", room)))?;
+            let (status, duration) = course(from, to, travel.0);
+            match house.house.check(Command::Blinds(room, status)) {
+                Ok(()) | Err(Refusal::Duplicate) => {}
+                Err(refusal) => return Err(status::Custom(Status::Conflict, format!("{:?} refused, {}", Command::Blinds(room, status), refusal))),
+            }
+            house.execute(move |h| {
+                h.blinds(room, status);
+                thread::sleep(duration);
+                h.stop(room, to);
+            });
+        }
+    }
+    Ok(Redirect::to("/"))
 }
 
 #[post("/screen", data = "<status>")]
-fn screen(house: State<SafeHouse>, status: Form<NewStatus>) -> Redirect {
-    if let Ok(s) = status.status.parse() {
-        house.execute(move |h| {
-            h.screen(s);
-        });
+fn screen(caller: Caller, house: State<SafeHouse>, timers: State<Arc<Timers>>, status: Form<NewStatus>) -> Result<Redirect, Refused> {
+    if let Ok(screen) = status.status.parse() {
+        caller.device(Kind::Screen, None)?;
+        order(&house, &timers, Action::Screen { screen }, &status.after, &status.during)?;
+    }
+    Ok(Redirect::to("/"))
+}
+
+#[get("/state")]
+fn state(caller: Caller, house: State<SafeHouse>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&caller.0.state(&house.house.state())).expect("State is json"))
+}
+
+#[derive(FromForm)]
+struct Frame {
+    protocol: String,
+    address: Option<String>,
+    status: String,
+    radio: Option<String>,
+}
+
+/// a frame to any device, configured or not
+#[post("/send", data = "<frame>")]
+fn send(caller: Caller, house: State<SafeHouse>, frame: Form<Frame>) -> Result<Status, Refused> {
+    caller.all()?;
+    let invalid = |e: &str| status::Custom(Status::BadRequest, e.to_string());
+    let code = transmission::code(&frame.protocol, frame.address.as_deref()).map_err(invalid)?;
+    let button = transmission::button(&frame.status).map_err(invalid)?;
+    Transmission::frame(code, button, frame.radio.clone()).transmit(&*house.house).map_err(|e| invalid(&e))?;
+    Ok(Status::NoContent)
+}
+
+/// any payload in any protocol, with the signals it was sent as
+#[post("/transmit", format = "json", data = "<transmission>")]
+fn transmit(caller: Caller, house: State<SafeHouse>, transmission: Json<Transmission>) -> Result<content::Json<String>, Refused> {
+    caller.all()?;
+    let transmitted = transmission.transmit(&*house.house).map_err(|e| status::Custom(Status::BadRequest, e))?;
+    Ok(content::Json(serde_json::to_string(&transmitted).expect("Transmissions are json")))
+}
+
+#[get("/timers")]
+fn timers(_caller: Caller, timers: State<Arc<Timers>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&timers.list()).expect("Timers are json"))
+}
+
+#[delete("/timers/<id>")]
+fn cancel_timer(caller: Caller, timers: State<Arc<Timers>>, id: u64) -> Result<Option<content::Json<String>>, Refused> {
+    caller.all()?;
+    match timers.cancel(id) {
+        Ok(true) => Ok(Some(self::timers(caller, timers))),
+        Ok(false) => Ok(None),
+        Err(e) => {
+            events::error(format!("Can't save timers: {}", e));
+            Ok(Some(self::timers(caller, timers)))
+        }
     }
-    Redirect::to("/")
 }
 
 #[derive(FromForm)]
@@ -75,24 +356,184 @@ struct Mode {
 }
 
 #[post("/mode", data = "<mode>")]
-fn mode(house: State<SafeHouse>, mode: Form<Mode>) -> Redirect {
-    match mode.mode.as_str() {
-        "cinema" => house.execute(|h| h.cinema()),
-        "goodmorning" => house.execute(|h| h.goodmorning()),
-        "goodnight" => house.execute(|h| h.goodnight()),
-        _ => {}
-    };
-    Redirect::to("/")
+fn mode(caller: Caller, house: State<SafeHouse>, mode: Form<Mode>) -> Result<Redirect, Refused> {
+    caller.scenes()?;
+    if let Ok(scene) = mode.mode.parse() {
+        house.execute(move |h| h.scene(scene));
+    }
+    Ok(Redirect::to("/"))
+}
+
+#[get("/weather")]
+fn weather(_caller: Caller, weather: State<Arc<Weather>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&weather.series()).expect("Series are json"))
+}
+
+#[get("/sensors")]
+fn sensors(_caller: Caller, sensors: State<Arc<Sensors<BinarySensor>>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&sensors.states(clock::now())).expect("States are json"))
 }
 
+#[get("/sensors/events")]
+fn sensor_events(_caller: Caller, sensors: State<Arc<Sensors<BinarySensor>>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&sensors.events()).expect("Events are json"))
+}
+
+#[get("/sensors/unknown")]
+fn unknown_sensors(_caller: Caller, sensors: State<Arc<Sensors<BinarySensor>>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&sensors.unknown()).expect("Codes are json"))
+}
+
+#[get("/schedules")]
+fn schedules(_caller: Caller, scheduler: State<Arc<Scheduler>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&scheduler.planned(clock::now())).expect("Schedules are json"))
+}
+
+#[post("/schedules/<name>/enable")]
+fn enable_schedule(caller: Caller, scheduler: State<Arc<Scheduler>>, name: String) -> Result<Option<content::Json<String>>, Refused> {
+    caller.all()?;
+    Ok(if scheduler.enable(&name, true) { Some(schedules(caller, scheduler)) } else { None })
+}
+
+#[post("/schedules/<name>/disable")]
+fn disable_schedule(caller: Caller, scheduler: State<Arc<Scheduler>>, name: String) -> Result<Option<content::Json<String>>, Refused> {
+    caller.all()?;
+    Ok(if scheduler.enable(&name, false) { Some(schedules(caller, scheduler)) } else { None })
+}
+
+#[get("/vacation")]
+fn vacation_plan(_caller: Caller, house: State<SafeHouse>, vacation: State<Arc<Vacation>>) -> content::Json<String> {
+    let plan = serde_json::json!({ "enabled": house.house.state().vacation, "tonight": vacation.tonight(clock::now()) });
+    content::Json(plan.to_string())
+}
+
+#[post("/vacation/enable")]
+fn enable_vacation(caller: Caller, house: State<SafeHouse>, vacation: State<Arc<Vacation>>) -> Result<content::Json<String>, Refused> {
+    caller.all()?;
+    house.house.vacation(true);
+    Ok(vacation_plan(caller, house, vacation))
+}
+
+#[post("/vacation/disable")]
+fn disable_vacation(caller: Caller, house: State<SafeHouse>, vacation: State<Arc<Vacation>>) -> Result<content::Json<String>, Refused> {
+    caller.all()?;
+    house.house.vacation(false);
+    Ok(vacation_plan(caller, house, vacation))
+}
+
+#[get("/rules")]
+fn rules(_caller: Caller, rules: State<Arc<Rules>>) -> content::Json<String> {
+    content::Json(serde_json::to_string(&rules.names()).expect("Names are json"))
+}
+
+#[post("/fire/<name>")]
+fn fire(caller: Caller, house: State<SafeHouse>, rules: State<Arc<Rules>>, name: String) -> Result<Status, Refused> {
+    caller.all()?;
+    let rules = rules.inner().clone();
+    house.execute(move |h| rules.fire(&*h, &Event::Api { name }, clock::now()));
+    Ok(Status::Accepted)
+}
+
+/// server-sent events of what happens to the devices the caller may control
+#[get("/events")]
+fn events(caller: Caller) -> content::Content<Stream<events::Stream>> {
+    content::Content(ContentType::new("text", "event-stream"), Stream::from(events::Stream::new(events::subscribe(), caller.0)))
+}
+
+struct HttpsPort(u16);
+
+/// every plain http request is sent to https
+#[catch(404)]
+fn to_https(request: &Request) -> Redirect {
+    let port = request.guard::<State<HttpsPort>>().map(|p| p.0).succeeded().unwrap_or(443);
+    let host = request.headers().get_one("Host").unwrap_or("janet");
+    Redirect::permanent(tls::https_location(host, port, &request.uri().to_string()))
+}
+
+/// serves https with the configured certificate, generated when missing, and redirects plain http to it when asked
+fn https(rocket: Rocket, tls: &Tls) -> Rocket {
+    if tls::ensure(tls).expect("Can't write a self-signed certificate") {
+        eprintln!("Generated a self-signed certificate for {}", tls.hostnames.join(", "));
+    }
+    let mut config = rocket.config().clone();
+    config.set_tls(&tls.certificate, &tls.key).expect("Invalid certificate");
+    if let Some(port) = tls.redirect {
+        let redirect = Config::build(config.environment).address(config.address.clone()).port(port).log_level(LoggingLevel::Critical).finalize().expect("Invalid redirect port");
+        let https = HttpsPort(config.port);
+        thread::spawn(move || {
+            let error = rocket::custom(redirect).manage(https).register(catchers![to_https]).launch();
+            events::error(format!("Can't redirect http to https: {}", error));
+        });
+    }
+    rocket::custom(config)
+}
+
+const CONFIGURATION: &str = "janet.toml";
+const TIMERS: &str = "timers.json";
+
 fn main() {
-    let house = house();
+    let configuration = Configuration::load(CONFIGURATION).expect("Invalid configuration");
+    let house = Arc::new(house(&configuration));
+    let timers = Arc::new(Timers::load(TIMERS).expect("Can't load timers"));
+    let (h, t) = (house.clone(), timers.clone());
+    thread::spawn(move || timer::run(&*h, &t));
+    let rules = Arc::new(Rules::new(configuration.rules.clone(), timers.clone()));
+    let r = rules.clone();
+    thread::spawn(move || rule::watch(CONFIGURATION, &r));
+    let triggers = Arc::new(Triggers::new(configuration.triggers.clone()));
+    let weather = Arc::new(Weather::new(configuration.thermometers.clone(), configuration.automations.clone()));
+    let sensors = Arc::new(Sensors::new(configuration.sensors.clone(), configuration.automations.clone()));
+    for r in &configuration.receivers {
+        match receiver::open(r) {
+            Ok(frames) => {
+                let (h, s, t, w, r) = (house.clone(), sensors.clone(), triggers.clone(), weather.clone(), rules.clone());
+                thread::spawn(move || {
+                    if let Err(e) = receiver::listen(&*h, &s, &t, &w, &r, frames) {
+                        events::error(format!("Receiver stopped: {}", e));
+                    }
+                });
+            }
+            Err(e) => eprintln!("Can't open receiver {:?}: {}", r, e),
+        }
+    }
+    let scheduler = Arc::new(Scheduler::new(configuration.schedules.clone(), configuration.location));
+    let (h, s, r) = (house.clone(), scheduler.clone(), rules.clone());
+    thread::spawn(move || schedule::run(&*h, &s, &r));
+    let vacation = Arc::new(Vacation::new(configuration.vacation.clone(), clock::now()));
+    let (h, v) = (house.clone(), vacation.clone());
+    thread::spawn(move || vacation::run(&*h, &v));
+    let auth = Arc::new(Auth::new(configuration.users.clone(), configuration.tokens.clone()));
+    if let Some(broker) = configuration.mqtt.clone() {
+        let bridge = Bridge::new(&broker, &configuration.devices);
+        let h = house.clone();
+        thread::spawn(move || mqtt::run(&*h, &bridge, &broker));
+    }
+    if let Some(broker) = configuration.hermes.clone() {
+        let (h, devices) = (house.clone(), configuration.devices.clone());
+        thread::spawn(move || hermes::run(&*h, &devices, &broker));
+    }
     let resolver = SafeHouse {
-        house: Arc::new(house)
+        house
+    };
+    let rocket = match &configuration.tls {
+        Some(tls) => https(rocket::ignite(), tls),
+        None => rocket::ignite(),
     };
-    rocket::ignite()
+    rocket
         .attach(Template::fairing())
+        .attach(rate_limit())
         .manage(resolver)
+        .manage(weather)
+        .manage(sensors)
+        .manage(scheduler)
+        .manage(vacation)
+        .manage(timers)
+        .manage(rules)
+        .manage(auth)
+        .manage(configuration.devices.clone())
+        .manage(configuration.tls.clone())
+        .manage(RateLimit::new(&configuration.limits))
         .mount("/", StaticFiles::from("static"))
-        .mount("/api", routes![light,blinds,mode,screen]).launch();
+        .mount("/", routes![index,login_page,login,logout,limited])
+        .mount("/api", routes![light,light_level,blinds,blinds_position,mode,screen,state,send,transmit,weather,sensors,sensor_events,unknown_sensors,schedules,enable_schedule,disable_schedule,vacation_plan,enable_vacation,disable_vacation,timers,cancel_timer,rules,fire,events]).launch();
 }
\ No newline at end of file
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::radio::Signal;
+
+pub mod wav;
+pub mod sigrok;
+pub mod urh;
+pub mod flipper;
+pub mod rtl433;
+
+/// the files captures are shared as
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    Flipper,
+    Rtl433,
+    Wav,
+    Sigrok,
+    /// a complex file, or the project opening it
+    Urh,
+}
+
+impl FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flipper" => Ok(Format::Flipper),
+            "rtl433" | "rtl_433" => Ok(Format::Rtl433),
+            "wav" => Ok(Format::Wav),
+            "sigrok" => Ok(Format::Sigrok),
+            "urh" => Ok(Format::Urh),
+            _ => Err("Unknown format"),
+        }
+    }
+}
+
+impl Format {
+    /// guessed from the extension of the file
+    pub fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "sub" => Some(Format::Flipper),
+            "ook" => Some(Format::Rtl433),
+            "wav" => Some(Format::Wav),
+            "sr" => Some(Format::Sigrok),
+            "complex" | "xml" => Some(Format::Urh),
+            _ => None,
+        }
+    }
+}
+
+/// the signals of a capture file, complex files need their sample rate unless opened through their URH project
+pub fn import(path: &Path, format: Format, sample_rate: Option<u32>) -> io::Result<Vec<Signal>> {
+    let file = BufReader::new(File::open(path)?);
+    match format {
+        Format::Flipper => flipper::read(file),
+        Format::Rtl433 => rtl433::read(file),
+        Format::Wav => wav::read(file),
+        Format::Sigrok => sigrok::read(file),
+        Format::Urh if path.extension().map_or(false, |e| e == "xml") => {
+            let (signal, sample_rate) = urh::read_project(file)?.into_iter().next().ok_or_else(|| invalid("No signal in the project"))?;
+            let signal = path.parent().unwrap_or_else(|| Path::new("")).join(signal);
+            urh::read(BufReader::new(File::open(signal)?), sample_rate)
+        }
+        Format::Urh => urh::read(file, sample_rate.ok_or_else(|| invalid("Complex files need their sample rate"))?),
+    }
+}
+
+/// writes the signals as a capture file, complex files come with a URH project, the same file with an xml extension
+pub fn export(path: &Path, format: Format, signals: &[Signal], sample_rate: u32) -> io::Result<()> {
+    match format {
+        Format::Wav => wav::write(BufWriter::new(File::create(path)?), signals, sample_rate),
+        Format::Sigrok => sigrok::write(BufWriter::new(File::create(path)?), signals, sample_rate),
+        Format::Urh => {
+            urh::write(BufWriter::new(File::create(path)?), signals, sample_rate)?;
+            let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| invalid("Invalid file name"))?;
+            urh::write_project(BufWriter::new(File::create(path.with_extension("xml"))?), &[(name, sample_rate)])
+        }
+        Format::Flipper | Format::Rtl433 => Err(invalid("Only wav, sigrok and urh captures can be written")),
+    }
+}
+
+/// levels of the signal sampled at sample_rate, true for high
+pub fn to_samples(signals: &[Signal], sample_rate: u32) -> Vec<bool> {
+    let mut samples = vec![];
+    let mut elapsed = Duration::from_micros(0);
+    for signal in signals {
+        let (high, duration) = match *signal {
+            Signal::HIGH(d) => (true, d),
+            Signal::LOW(d) => (false, d),
+        };
+        elapsed += duration;
+        let end = (elapsed.as_micros() * sample_rate as u128 + 500_000) / 1_000_000;
+        samples.resize(end as usize, high);
+    }
+    samples
+}
+
+/// signals from levels sampled at sample_rate, consecutive samples at the same level are merged
+pub fn from_samples<I>(samples: I, sample_rate: u32) -> io::Result<Vec<Signal>> where I: IntoIterator<Item=bool> {
+    if sample_rate == 0 {
+        return Err(invalid("Invalid sample rate 0"));
+    }
+    let mut runs: Vec<(bool, u64)> = vec![];
+    for high in samples {
+        match runs.last_mut() {
+            Some((level, count)) if *level == high => *count += 1,
+            _ => runs.push((high, 1)),
+        }
+    }
+    Ok(runs.into_iter().map(|(high, count)| {
+        let duration = Duration::from_micros((count * 1_000_000 + sample_rate as u64 / 2) / sample_rate as u64);
+        if high { Signal::HIGH(duration) } else { Signal::LOW(duration) }
+    }).collect())
+}
+
+/// high when above half of the loudest amplitude
+fn threshold(amplitudes: Vec<f32>) -> Vec<bool> {
+    let peak = amplitudes.iter().cloned().fold(0.0, f32::max);
+    amplitudes.into_iter().map(|a| peak > 0.0 && a > peak / 2.0).collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    #[test]
+    fn sample_signals() {
+        let samples = to_samples(&[Signal::HIGH(Duration::from_micros(3)), Signal::LOW(Duration::from_micros(2))], 1_000_000);
+        assert_that!(&samples, eq(vec![true, true, true, false, false]));
+    }
+
+    #[test]
+    fn sample_without_drifting() {
+        let signals = [Signal::HIGH(Duration::from_micros(15)), Signal::LOW(Duration::from_micros(15))].repeat(10);
+        let samples = to_samples(&signals, 100_000);
+        assert_that!(&samples.len(), eq(30));
+    }
+
+    #[test]
+    fn merge_samples_into_signals() {
+        let signals = from_samples(vec![true, true, false, false, false, true], 1_000_000).unwrap();
+        assert_that!(&signals, eq(vec![
+            Signal::HIGH(Duration::from_micros(2)),
+            Signal::LOW(Duration::from_micros(3)),
+            Signal::HIGH(Duration::from_micros(1)),
+        ]));
+        assert_that!(&from_samples(vec![true], 0).is_err(), eq(true));
+    }
+
+    #[test]
+    fn export_and_import_back_every_format() {
+        let signals = vec![
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(2793)),
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(1355)),
+        ];
+        let directory = std::env::temp_dir().join(format!("janet-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in &["remote.wav", "remote.sr", "remote.complex"] {
+            let path = directory.join(name);
+            let format = Format::of(&path).unwrap();
+            export(&path, format, &signals, 1_000_000).unwrap();
+            assert_that!(&import(&path, format, Some(1_000_000)).unwrap(), eq(signals.clone()));
+        }
+        assert_that!(&import(&directory.join("remote.xml"), Format::Urh, None).unwrap(), eq(signals.clone()));
+        assert_that!(&import(&directory.join("remote.complex"), Format::Urh, None).is_err(), eq(true));
+        assert_that!(&export(&directory.join("remote.sub"), Format::Flipper, &signals, 1_000_000).is_err(), eq(true));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn guess_formats_from_extensions() {
+        assert_that!(&Format::of(Path::new("captures/remote.sub")), eq(Some(Format::Flipper)));
+        assert_that!(&Format::of(Path::new("remote.ook")), eq(Some(Format::Rtl433)));
+        assert_that!(&Format::of(Path::new("URHProject.xml")), eq(Some(Format::Urh)));
+        assert_that!(&Format::of(Path::new("remote")), eq(None));
+        assert_that!(&"RTL_433".parse::<Format>(), eq(Ok(Format::Rtl433)));
+    }
+}
@@ -0,0 +1,63 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::time::Duration;
+use crate::radio::Signal;
+use super::invalid;
+
+/// reads the RAW_Data timings of a Flipper Zero SubGhz RAW file,
+/// positive values are high and negative values low durations in microseconds
+pub fn read<R: Read>(reader: R) -> io::Result<Vec<Signal>> {
+    let mut signals = vec![];
+    let mut raw = false;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        match key {
+            "Filetype" if !value.starts_with("Flipper SubGhz") => return Err(invalid("Not a Flipper SubGhz file")),
+            "Protocol" if value != "RAW" => return Err(invalid("Only RAW captures are supported")),
+            "Protocol" => raw = true,
+            "RAW_Data" => for timing in value.split_whitespace() {
+                let timing = timing.parse::<i64>().map_err(|_| invalid("Invalid timing"))?;
+                let duration = Duration::from_micros(timing.unsigned_abs());
+                signals.push(if timing > 0 { Signal::HIGH(duration) } else { Signal::LOW(duration) });
+            },
+            _ => {}
+        }
+    }
+    if !raw {
+        return Err(invalid("Missing protocol"));
+    }
+    Ok(signals)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    #[test]
+    fn read_raw_data() {
+        let sub = "Filetype: Flipper SubGhz RAW File\nVersion: 1\nFrequency: 433920000\nPreset: FuriHalSubGhzPresetOok650Async\nProtocol: RAW\nRAW_Data: 283 -2793 283\nRAW_Data: -1355\n";
+        assert_that!(&read(sub.as_bytes()).unwrap(), eq(vec![
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(2793)),
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(1355)),
+        ]));
+    }
+
+    #[test]
+    fn reject_decoded_captures() {
+        let sub = "Filetype: Flipper SubGhz Key File\nVersion: 1\nProtocol: Princeton\nKey: 00 00 00 00 00 95 D5 D4\n";
+        assert_that!(&read(sub.as_bytes()).is_err(), eq(true));
+    }
+
+    #[test]
+    fn reject_invalid_timings() {
+        let sub = "Filetype: Flipper SubGhz RAW File\nProtocol: RAW\nRAW_Data: 283 -plop\n";
+        assert_that!(&read(sub.as_bytes()).is_err(), eq(true));
+    }
+}
@@ -0,0 +1,65 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::time::Duration;
+use crate::radio::Signal;
+use super::invalid;
+
+/// reads rtl_433 pulse data (`rtl_433 -w capture.ook`), one pulse and gap width per line
+pub fn read<R: Read>(reader: R) -> io::Result<Vec<Signal>> {
+    let mut signals = vec![];
+    let mut timescale = 1;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix(';') {
+            if let Some(scale) = comment.trim().strip_prefix("timescale") {
+                timescale = scale.trim().trim_end_matches("us").parse().map_err(|_| invalid("Unsupported timescale"))?;
+            }
+            continue;
+        }
+        let widths = line.split_whitespace().map(|w| w.parse::<u64>()).collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("Invalid pulse"))?;
+        match widths.as_slice() {
+            [] => {}
+            [pulse, gap] => {
+                signals.push(Signal::HIGH(Duration::from_micros(pulse * timescale)));
+                if *gap > 0 {
+                    signals.push(Signal::LOW(Duration::from_micros(gap * timescale)));
+                }
+            }
+            _ => return Err(invalid("Invalid pulse")),
+        }
+    }
+    Ok(signals)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    #[test]
+    fn read_pulse_data() {
+        let ook = ";pulse data\n;version 1\n;timescale 1us\n;ook 2 pulses\n;freq1 433920000\n283 2793\n283 0\n;end\n";
+        assert_that!(&read(ook.as_bytes()).unwrap(), eq(vec![
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(2793)),
+            Signal::HIGH(Duration::from_micros(283)),
+        ]));
+    }
+
+    #[test]
+    fn scale_pulses() {
+        let ook = ";timescale 4us\n70 700\n";
+        assert_that!(&read(ook.as_bytes()).unwrap(), eq(vec![
+            Signal::HIGH(Duration::from_micros(280)),
+            Signal::LOW(Duration::from_micros(2800)),
+        ]));
+    }
+
+    #[test]
+    fn reject_invalid_pulses() {
+        assert_that!(&read(&b"283 2793 12\n"[..]).is_err(), eq(true));
+        assert_that!(&read(&b";timescale 1ms\n"[..]).is_err(), eq(true));
+    }
+}
@@ -0,0 +1,127 @@
+use std::io;
+use std::io::{Read, Seek, Write};
+use zip::{ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+use crate::radio::Signal;
+use super::{to_samples, from_samples, invalid};
+
+/// writes a sigrok session with a single logic probe named RF
+pub fn write<W: Write + Seek>(writer: W, signals: &[Signal], sample_rate: u32) -> io::Result<()> {
+    let samples = to_samples(signals, sample_rate).into_iter().map(|high| high as u8).collect::<Vec<_>>();
+    let metadata = format!("[global]\nsigrok version=0.5.1\n\n[device 1]\ncapturefile=logic-1\ntotal probes=1\nsamplerate={}\ntotal analog=0\nprobe1=RF\nunitsize=1\n",
+                           format_rate(sample_rate));
+    let mut zip = ZipWriter::new(writer);
+    for (name, content) in &[("version", &b"2"[..]), ("metadata", metadata.as_bytes()), ("logic-1-1", &samples)] {
+        zip.start_file(*name, FileOptions::default()).map_err(io::Error::from)?;
+        zip.write_all(content)?;
+    }
+    zip.finish().map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// reads the first probe of the first device of a sigrok session
+pub fn read<R: Read + Seek>(reader: R) -> io::Result<Vec<Signal>> {
+    let mut zip = ZipArchive::new(reader).map_err(io::Error::from)?;
+    let mut metadata = String::new();
+    zip.by_name("metadata").map_err(io::Error::from)?.read_to_string(&mut metadata)?;
+    let value = |key: &str| metadata.lines()
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().to_string())
+        .ok_or_else(|| invalid(&format!("Missing {}", key)));
+    let capture = value("capturefile")?;
+    let sample_rate = parse_rate(&value("samplerate")?).ok_or_else(|| invalid("Invalid samplerate"))?;
+    let unit = value("unitsize")?.parse::<usize>().map_err(|_| invalid("Invalid unitsize"))?;
+    let mut chunks = zip.file_names()
+        .filter_map(|n| n.strip_prefix(&format!("{}-", capture)).and_then(|i| i.parse::<u32>().ok()).map(|i| (i, n.to_string())))
+        .collect::<Vec<_>>();
+    chunks.sort();
+    if chunks.is_empty() {
+        // older sessions store everything in a single file
+        chunks.push((1, capture));
+    }
+    let mut samples = vec![];
+    for (_, name) in chunks {
+        let mut chunk = vec![];
+        zip.by_name(&name).map_err(io::Error::from)?.read_to_end(&mut chunk)?;
+        samples.extend(chunk.chunks_exact(unit.max(1)).map(|s| s[0] & 1 == 1));
+    }
+    from_samples(samples, sample_rate)
+}
+
+fn format_rate(sample_rate: u32) -> String {
+    match sample_rate {
+        r if r % 1_000_000 == 0 => format!("{} MHz", r / 1_000_000),
+        r if r % 1_000 == 0 => format!("{} kHz", r / 1_000),
+        r => format!("{} Hz", r),
+    }
+}
+
+fn parse_rate(rate: &str) -> Option<u32> {
+    let (value, unit) = match rate.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => (rate[..i].trim(), rate[i..].trim()),
+        None => (rate.trim(), "Hz"),
+    };
+    let multiplier = match unit {
+        "Hz" => 1.0,
+        "kHz" => 1e3,
+        "MHz" => 1e6,
+        "GHz" => 1e9,
+        _ => return None,
+    };
+    value.parse::<f64>().ok().map(|v| (v * multiplier).round() as u32).filter(|&r| r > 0)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    #[test]
+    fn write_and_read_back_signals() {
+        let signals = vec![
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(2793)),
+            Signal::HIGH(Duration::from_micros(283)),
+            Signal::LOW(Duration::from_micros(1355)),
+        ];
+        let mut session = Cursor::new(vec![]);
+        write(&mut session, &signals, 1_000_000).unwrap();
+        session.set_position(0);
+        assert_that!(&read(session).unwrap(), eq(signals));
+    }
+
+    #[test]
+    fn read_chunks_in_order_and_other_probes() {
+        let mut session = Cursor::new(vec![]);
+        {
+            let mut zip = ZipWriter::new(&mut session);
+            zip.start_file("metadata", FileOptions::default()).unwrap();
+            zip.write_all(b"[device 1]\ncapturefile=logic-1\nsamplerate=250 kHz\nunitsize=2\n").unwrap();
+            zip.start_file("logic-1-2", FileOptions::default()).unwrap();
+            zip.write_all(&[0, 0, 0, 0]).unwrap();
+            zip.start_file("logic-1-1", FileOptions::default()).unwrap();
+            zip.write_all(&[1, 0, 3, 0, 2, 0]).unwrap();
+            zip.finish().unwrap();
+        }
+        session.set_position(0);
+        assert_that!(&read(session).unwrap(), eq(vec![
+            Signal::HIGH(Duration::from_micros(8)),
+            Signal::LOW(Duration::from_micros(12)),
+        ]));
+    }
+
+    #[test]
+    fn format_and_parse_rates() {
+        assert_that!(&format_rate(1_000_000), eq("1 MHz".to_string()));
+        assert_that!(&format_rate(250_000), eq("250 kHz".to_string()));
+        assert_that!(&format_rate(44_100), eq("44100 Hz".to_string()));
+        assert_that!(&parse_rate("1 MHz"), eq(Some(1_000_000)));
+        assert_that!(&parse_rate("2.5MHz"), eq(Some(2_500_000)));
+        assert_that!(&parse_rate("44100"), eq(Some(44_100)));
+        assert_that!(&parse_rate("3 parsecs"), eq(None));
+        assert_that!(&parse_rate("0 Hz"), eq(None));
+    }
+}
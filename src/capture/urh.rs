@@ -0,0 +1,105 @@
+use std::io;
+use std::io::{Read, Write};
+use crate::radio::Signal;
+use super::{to_samples, from_samples, threshold, invalid};
+
+/// writes the OOK envelope as a complex file (interleaved little endian f32 I/Q) URH can open
+pub fn write<W: Write>(mut writer: W, signals: &[Signal], sample_rate: u32) -> io::Result<()> {
+    let samples = to_samples(signals, sample_rate);
+    let mut bytes = Vec::with_capacity(samples.len() * 8);
+    for high in samples {
+        let i: f32 = if high { 1.0 } else { 0.0 };
+        bytes.extend_from_slice(&i.to_le_bytes());
+        bytes.extend_from_slice(&0f32.to_le_bytes());
+    }
+    writer.write_all(&bytes)
+}
+
+pub fn read<R: Read>(mut reader: R, sample_rate: u32) -> io::Result<Vec<Signal>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() % 8 != 0 {
+        return Err(invalid("Truncated complex file"));
+    }
+    let amplitudes = bytes.chunks_exact(8).map(|s| {
+        let i = f32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+        let q = f32::from_le_bytes([s[4], s[5], s[6], s[7]]);
+        (i * i + q * q).sqrt()
+    }).collect();
+    from_samples(threshold(amplitudes), sample_rate)
+}
+
+/// writes a URH project opening the given complex files as ASK signals
+pub fn write_project<W: Write>(mut writer: W, signals: &[(&str, u32)]) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" ?>"#)?;
+    writeln!(writer, r#"<UniversalRadioHackerProject collapse_project_tabs="0" modulation_was_edited="0" description="exported by janet">"#)?;
+    for (file, sample_rate) in signals {
+        let name = escape(file.rsplit('/').next().unwrap_or(file));
+        writeln!(writer, r#"  <signal name="{}" filename="{}" samples_per_symbol="100" center="0.5" tolerance="5" noise_threshold="0.1" modulation_type="ASK" sample_rate="{}" bits_per_symbol="1">"#,
+                 name, escape(file), sample_rate)?;
+        writeln!(writer, "    <messages/>")?;
+        writeln!(writer, "  </signal>")?;
+        writeln!(writer, r#"  <open_file name="{}"/>"#, escape(file))?;
+    }
+    writeln!(writer, "</UniversalRadioHackerProject>")
+}
+
+/// the signal files of a URH project with their sample rate
+pub fn read_project<R: Read>(mut reader: R) -> io::Result<Vec<(String, u32)>> {
+    let mut xml = String::new();
+    reader.read_to_string(&mut xml)?;
+    xml.split("<signal ").skip(1).map(|signal| {
+        let tag = signal.split('>').next().unwrap_or("");
+        let file = attribute(tag, "filename").ok_or_else(|| invalid("Missing filename"))?;
+        let sample_rate = attribute(tag, "sample_rate")
+            .and_then(|r| r.parse::<f64>().ok())
+            .ok_or_else(|| invalid("Missing sample_rate"))?;
+        Ok((file, sample_rate as u32))
+    }).collect()
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = start + tag[start..].find('"')?;
+    Some(tag[start..end].replace("&quot;", "\"").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::time::Duration;
+
+    #[test]
+    fn write_and_read_back_signals() {
+        let signals = vec![
+            Signal::HIGH(Duration::from_micros(4800)),
+            Signal::LOW(Duration::from_micros(1500)),
+            Signal::HIGH(Duration::from_micros(350)),
+            Signal::LOW(Duration::from_micros(750)),
+        ];
+        let mut bytes = vec![];
+        write(&mut bytes, &signals, 2_000_000).unwrap();
+        assert_that!(&bytes.len(), eq(7400 * 2 * 8));
+        assert_that!(&read(bytes.as_slice(), 2_000_000).unwrap(), eq(signals));
+    }
+
+    #[test]
+    fn reject_truncated_files() {
+        assert_that!(&read(&[0u8; 12][..], 1_000_000).is_err(), eq(true));
+    }
+
+    #[test]
+    fn write_and_read_back_projects() {
+        let mut xml = vec![];
+        write_project(&mut xml, &[("captures/screen & co.complex", 2_000_000), ("dio.complex", 1_000_000)]).unwrap();
+        assert_that!(&read_project(xml.as_slice()).unwrap(), eq(vec![
+            ("captures/screen & co.complex".to_string(), 2_000_000),
+            ("dio.complex".to_string(), 1_000_000),
+        ]));
+    }
+}
@@ -0,0 +1,131 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::{Read, Write};
+use crate::radio::Signal;
+use super::{to_samples, from_samples, threshold, invalid};
+
+/// writes the OOK envelope as a mono 16 bits PCM wav file
+pub fn write<W: Write>(mut writer: W, signals: &[Signal], sample_rate: u32) -> io::Result<()> {
+    let byte_rate = sample_rate.checked_mul(2).filter(|&r| r > 0).ok_or_else(|| invalid("Invalid sample rate"))?;
+    let samples = to_samples(signals, sample_rate);
+    let data_length = u32::try_from(samples.len()).ok()
+        .and_then(|l| l.checked_mul(2))
+        .filter(|&l| l <= u32::MAX - 36)
+        .ok_or_else(|| invalid("Too long for a wav file"))?;
+    let mut bytes = Vec::with_capacity(44 + data_length as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_length).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&byte_rate.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_length.to_le_bytes());
+    for high in samples {
+        let amplitude: i16 = if high { i16::MAX } else { 0 };
+        bytes.extend_from_slice(&amplitude.to_le_bytes());
+    }
+    writer.write_all(&bytes)
+}
+
+/// reads 8 or 16 bits PCM wav files, only the first channel is used
+pub fn read<R: Read>(mut reader: R) -> io::Result<Vec<Signal>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("Not a wav file"));
+    }
+    let mut format = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let length = u32::from_le_bytes([bytes[position + 4], bytes[position + 5], bytes[position + 6], bytes[position + 7]]) as usize;
+        let start = position + 8;
+        let chunk = bytes.get(start..start + length).ok_or_else(|| invalid("Truncated chunk"))?;
+        match id {
+            b"fmt " if chunk.len() >= 16 => {
+                let u16_at = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+                if u16_at(0) != 1 {
+                    return Err(invalid("Only PCM is supported"));
+                }
+                let sample_rate = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                format = Some((u16_at(2) as usize, sample_rate, u16_at(14)));
+            }
+            b"data" => {
+                let (channels, sample_rate, bits) = format.ok_or_else(|| invalid("Missing format"))?;
+                if channels == 0 {
+                    return Err(invalid("No channel"));
+                }
+                let amplitudes = match bits {
+                    8 => chunk.chunks_exact(channels).map(|s| (s[0] as f32 - 128.0).abs()).collect(),
+                    16 => chunk.chunks_exact(2 * channels).map(|s| (i16::from_le_bytes([s[0], s[1]]) as f32).abs()).collect(),
+                    _ => return Err(invalid("Only 8 or 16 bits samples are supported")),
+                };
+                return from_samples(threshold(amplitudes), sample_rate);
+            }
+            _ => {}
+        }
+        position = start + length + length % 2;
+    }
+    Err(invalid("Missing data"))
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::time::Duration;
+
+    #[test]
+    fn write_and_read_back_signals() {
+        let signals = vec![
+            Signal::HIGH(Duration::from_micros(300)),
+            Signal::LOW(Duration::from_micros(2800)),
+            Signal::HIGH(Duration::from_micros(300)),
+            Signal::LOW(Duration::from_micros(1400)),
+        ];
+        let mut bytes = vec![];
+        write(&mut bytes, &signals, 100_000).unwrap();
+        assert_that!(&bytes.len(), eq(44 + 480 * 2));
+        assert_that!(&read(bytes.as_slice()).unwrap(), eq(signals));
+    }
+
+    #[test]
+    fn read_noisy_8_bits_stereo() {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(b"RIFF\x00\x00\x00\x00WAVEfmt ");
+        bytes.extend_from_slice(&[16, 0, 0, 0, 1, 0, 2, 0]);
+        bytes.extend_from_slice(&1_000_000u32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0, 2, 0, 8, 0]);
+        bytes.extend_from_slice(b"data\x0c\x00\x00\x00");
+        bytes.extend_from_slice(&[250, 128, 240, 128, 130, 128, 126, 128, 20, 128, 128, 128]);
+        assert_that!(&read(bytes.as_slice()).unwrap(), eq(vec![
+            Signal::HIGH(Duration::from_micros(2)),
+            Signal::LOW(Duration::from_micros(2)),
+            Signal::HIGH(Duration::from_micros(1)),
+            Signal::LOW(Duration::from_micros(1)),
+        ]));
+    }
+
+    #[test]
+    fn reject_other_files() {
+        assert_that!(&read(&b"PK\x03\x04"[..]).is_err(), eq(true));
+    }
+
+    #[test]
+    fn reject_formats_without_channels_or_samples() {
+        let mut bytes = vec![];
+        write(&mut bytes, &[Signal::HIGH(Duration::from_micros(300))], 1_000_000).unwrap();
+        let mut without_channels = bytes.clone();
+        without_channels[22..24].copy_from_slice(&0u16.to_le_bytes());
+        assert_that!(&read(&without_channels[..]).unwrap_err().to_string(), eq("No channel".to_string()));
+        let mut without_rate = bytes;
+        without_rate[24..28].copy_from_slice(&0u32.to_le_bytes());
+        assert_that!(&read(&without_rate[..]).unwrap_err().to_string(), eq("Invalid sample rate 0".to_string()));
+        assert_that!(&write(&mut vec![], &[], u32::MAX).is_err(), eq(true));
+    }
+}
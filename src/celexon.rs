@@ -1,6 +1,4 @@
 use crate::radio_protocol::*;
-use std::time::Duration;
-use crate::radio::Signal;
 
 pub enum Status {
     UP,
@@ -20,14 +18,7 @@ impl IntoIterator for Status {
 }
 
 lazy_static! {
-    pub static ref CELEXON_PROTOCOL: RadioProtocol<Status> = {
-        RadioProtocol::<Status>::new(
-            Header(vec![ Signal::HIGH(Duration::from_micros(5164)),Signal::LOW(Duration::from_micros(581))]),
-            Footer(vec![Signal::HIGH(Duration::from_micros(581)),Signal::LOW(Duration::from_micros(248))]),
-            Zero(vec![Signal::HIGH(Duration::from_micros(581)), Signal::LOW(Duration::from_micros(199))]),
-            One(vec![Signal::HIGH(Duration::from_micros(199)), Signal::LOW(Duration::from_micros(581))]),
-            10)
-    };
+    pub static ref CELEXON_PROTOCOL: RadioProtocol<Status> = bundled("celexon");
 }
 
 
@@ -0,0 +1,395 @@
+use std::io;
+use std::path::Path;
+use crate::capture::Format;
+use crate::config::{Button, Code};
+use crate::hermes::room;
+use crate::http;
+use crate::house::{BlindStatus, LightStatus, Room, Scene};
+use crate::radio::Signal;
+use crate::radio_protocol::{self, Footer, Header, One, RadioProtocol, Zero};
+use crate::transmission::{button, code, Protocol, Transmission};
+
+pub const USAGE: &str = "Usage: janet [--server URL] [--token TOKEN] [--certificate FILE] [--config FILE] ORDER
+
+Orders:
+  light ROOM on|off
+  blinds ROOM up|down
+  screen up|down
+  scene cinema|goodmorning|goodnight|vacation
+  state
+  send --protocol dio|dooya|celexon [--address ADDRESS] --status on|off|up|down [--radio NAME]
+  transmit --protocol dio|dooya|celexon --payload HEX [--radio NAME]
+  transmit --header SIGNALS --footer SIGNALS --zero SIGNALS --one SIGNALS --repetition COUNT --payload HEX [--radio NAME]
+  import FILE [--format flipper|rtl433|wav|sigrok|urh] [--sample-rate HZ] [--name NAME]
+  export FILE [--format wav|sigrok|urh] [--sample-rate HZ] --protocol NAME --payload HEX
+  export FILE [--format wav|sigrok|urh] [--sample-rate HZ] --header SIGNALS --footer SIGNALS --zero SIGNALS --one SIGNALS --repetition COUNT --payload HEX
+
+Signals are durations in microseconds, + for high and - for low, like +283,-2793.
+Captures are imported as a [[protocols]] definition for the configuration, and exported at 1MHz by default.
+Their format is guessed from the extension of the file: sub, ook, wav, sr, complex or the xml of a URH project.
+
+The local radios of the configuration, janet.toml by default, are used unless a server is given,
+like the server the default configuration is used when the file is missing.
+A server speaking https is trusted with its certificate, like the self-signed janet.crt it wrote,
+or with the certificate authorities of the system when none is given.
+The server, the token and the certificate can also be given with JANET_SERVER, JANET_TOKEN and JANET_CERTIFICATE.";
+
+/// what the `janet` command is asked to do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Order {
+    Light { room: Room, status: LightStatus },
+    Blinds { room: Room, status: BlindStatus },
+    Screen { status: BlindStatus },
+    Scene { scene: Scene },
+    State,
+    /// a frame to a device that may not be configured
+    Send { code: Code, button: Button, radio: Option<String> },
+    /// any bytes, in any protocol
+    Transmit { transmission: Transmission },
+    /// a capture, and the protocol it was sent with
+    Import { file: String, format: Format, sample_rate: Option<u32>, name: String },
+    /// a transmission written as a capture instead of being sent
+    Export { file: String, format: Format, sample_rate: u32, transmission: Transmission },
+}
+
+/// of exported captures, enough for the shortest signals janet sends
+const SAMPLE_RATE: u32 = 1_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub server: Option<String>,
+    pub token: Option<String>,
+    pub certificate: Option<String>,
+    pub configuration: String,
+    pub order: Order,
+}
+
+/// options of the orders
+const OPTIONS: [&str; 13] = ["--protocol", "--address", "--status", "--radio", "--payload", "--header", "--footer", "--zero", "--one", "--repetition",
+                             "--format", "--sample-rate", "--name"];
+
+/// the arguments, without the name of the program
+pub fn parse<I>(args: I) -> Result<Options, String> where I: IntoIterator<Item=String> {
+    let mut args = args.into_iter();
+    let mut options = (None, None, None, "janet.toml".to_string());
+    let mut words = vec![];
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--server" => options.0 = Some(value()?),
+            "--token" => options.1 = Some(value()?),
+            "--certificate" => options.2 = Some(value()?),
+            "--config" => options.3 = value()?,
+            _ if OPTIONS.contains(&arg.as_str()) => words.push((arg.clone(), Some(value()?))),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => words.push((arg, None)),
+        }
+    }
+    let (server, token, certificate, configuration) = options;
+    Ok(Options { server, token, certificate, configuration, order: order(words)? })
+}
+
+fn order(words: Vec<(String, Option<String>)>) -> Result<Order, String> {
+    let positional: Vec<&str> = words.iter().filter(|(_, v)| v.is_none()).map(|(w, _)| w.as_str()).collect();
+    let option = |name: &str| words.iter().find(|(w, _)| w == name).and_then(|(_, v)| v.as_deref());
+    let room = |text: &str| room(&text.to_lowercase()).ok_or_else(|| format!("Unknown room {}", text));
+    let order = match positional.as_slice() {
+        ["light", r, s] => Order::Light { room: room(r)?, status: s.parse()? },
+        ["blinds", r, s] => Order::Blinds { room: room(r)?, status: s.parse()? },
+        ["screen", s] => Order::Screen { status: s.parse()? },
+        ["scene", s] => Order::Scene { scene: s.replace(' ', "").to_lowercase().parse()? },
+        ["state"] => Order::State,
+        ["send"] => Order::Send {
+            code: code(option("--protocol").ok_or("send needs a --protocol")?, option("--address"))?,
+            button: button(option("--status").ok_or("send needs a --status")?)?,
+            radio: option("--radio").map(str::to_string),
+        },
+        ["transmit"] => Order::Transmit { transmission: transmission("transmit", &option)? },
+        ["import", file] => Order::Import {
+            file: file.to_string(),
+            format: capture_format(file, option("--format"))?,
+            sample_rate: option("--sample-rate").map(sample_rate).transpose()?,
+            name: option("--name").unwrap_or("captured").to_string(),
+        },
+        ["export", file] => Order::Export {
+            file: file.to_string(),
+            format: capture_format(file, option("--format"))?,
+            sample_rate: option("--sample-rate").map(sample_rate).transpose()?.unwrap_or(SAMPLE_RATE),
+            transmission: transmission("export", &option)?,
+        },
+        [] => return Err(USAGE.to_string()),
+        _ => return Err(format!("Unknown order {}\n\n{}", positional.join(" "), USAGE)),
+    };
+    let allowed: &[&str] = match order {
+        Order::Send { .. } => &OPTIONS[..4],
+        Order::Transmit { .. } => &["--protocol", "--radio", "--payload", "--header", "--footer", "--zero", "--one", "--repetition"],
+        Order::Import { .. } => &OPTIONS[10..],
+        Order::Export { .. } => &["--protocol", "--payload", "--header", "--footer", "--zero", "--one", "--repetition", "--format", "--sample-rate"],
+        _ => &[],
+    };
+    match words.iter().find(|(w, v)| v.is_some() && !allowed.contains(&w.as_str())) {
+        Some((option, _)) => Err(format!("{} isn't an option of {}", option, positional[0])),
+        None => Ok(order),
+    }
+}
+
+/// bytes in a protocol given by its name or with its timings
+fn transmission<'a, F>(order: &str, option: &F) -> Result<Transmission, String> where F: Fn(&str) -> Option<&'a str> {
+    Ok(Transmission {
+        protocol: match option("--protocol") {
+            Some(name) => Protocol::Named(name.to_lowercase()),
+            None => Protocol::Defined(definition(order, option)?),
+        },
+        payload: option("--payload").ok_or_else(|| format!("{} needs a --payload", order))?.to_string(),
+        radio: option("--radio").map(str::to_string),
+    })
+}
+
+/// a protocol given with its timings
+fn definition<'a, F>(order: &str, option: &F) -> Result<RadioProtocol<Vec<u8>>, String> where F: Fn(&str) -> Option<&'a str> {
+    let signals = |name: &str| -> Result<Vec<Signal>, String> {
+        let signals = option(name).ok_or_else(|| format!("{} needs a --protocol or a {}", order, name))?;
+        signals.split(',').map(|s| s.trim().parse().map_err(|e| format!("{} {}", e, s))).collect()
+    };
+    let repetition = option("--repetition").ok_or_else(|| format!("{} needs a --protocol or a --repetition", order))?;
+    let repetition = repetition.parse().map_err(|_| format!("Invalid repetition {}", repetition))?;
+    Ok(RadioProtocol::new(radio_protocol::RAW, Header(signals("--header")?), Footer(signals("--footer")?), Zero(signals("--zero")?), One(signals("--one")?), repetition))
+}
+
+/// the given format, or the one of the extension of the file
+fn capture_format(file: &str, format: Option<&str>) -> Result<Format, String> {
+    match format {
+        Some(format) => Ok(format.parse()?),
+        None => Format::of(Path::new(file)).ok_or_else(|| format!("Unknown format of {}, give it with --format", file)),
+    }
+}
+
+fn sample_rate(rate: &str) -> Result<u32, String> {
+    rate.parse().ok().filter(|&r| r > 0).ok_or_else(|| format!("Invalid sample rate {}", rate))
+}
+
+/// the api call for the order, with its content type and body, captures don't need a server
+fn call(order: &Order) -> Option<(&'static str, &'static str, &'static str, String)> {
+    let (method, path, form) = match order {
+        Order::Light { room, status } => ("POST", "/api/light", vec![("room", format!("{:?}", room)), ("status", format!("{:?}", status))]),
+        Order::Blinds { room, status } => ("POST", "/api/blinds", vec![("room", format!("{:?}", room)), ("status", format!("{:?}", status))]),
+        Order::Screen { status } => ("POST", "/api/screen", vec![("status", format!("{:?}", status))]),
+        Order::Scene { scene } => ("POST", "/api/mode", vec![("mode", format!("{:?}", scene))]),
+        Order::State => ("GET", "/api/state", vec![]),
+        Order::Send { code, button, radio } => {
+            let mut form = match code {
+                Code::Dio { address } => vec![("protocol", "dio".to_string()), ("address", format!("0x{:04x}", address))],
+                Code::Dooya => vec![("protocol", "dooya".to_string())],
+                Code::Celexon => vec![("protocol", "celexon".to_string())],
+            };
+            form.push(("status", format!("{:?}", button).to_lowercase()));
+            form.extend(radio.iter().map(|r| ("radio", r.clone())));
+            ("POST", "/api/send", form)
+        }
+        Order::Transmit { transmission } => {
+            return Some(("POST", "/api/transmit", "application/json", serde_json::to_string(transmission).expect("Transmissions are json")));
+        }
+        Order::Import { .. } | Order::Export { .. } => return None,
+    };
+    let body = form.iter().map(|(k, v)| format!("{}={}", k, encode(v))).collect::<Vec<_>>().join("&");
+    Some((method, path, "application/x-www-form-urlencoded", body))
+}
+
+/// a running janet, spoken to in http or https
+pub struct Server {
+    client: http::Client,
+    token: Option<String>,
+}
+
+impl Server {
+    /// `http://janet:8000`, the scheme can be left out, `https://janet` with the certificate to trust
+    pub fn new(url: &str, token: Option<String>, certificate: Option<&str>) -> Result<Self, String> {
+        let url = if url.contains("://") { url.to_string() } else { format!("http://{}", url) };
+        Ok(Server { client: http::Client::new(&url, certificate.map(Path::new)).map_err(|e| e.to_string())?, token })
+    }
+
+    /// what the server answered, forms are answered with a redirection to the home page,
+    /// any other redirection, like the one of plain http to https, is an error
+    pub fn order(&self, order: &Order) -> io::Result<String> {
+        let (method, path, content_type, body) = call(order)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Captures are imported and exported without a server"))?;
+        let authorization = self.token.as_ref().map(|t| format!("Bearer {}", t));
+        let headers: Vec<(&str, &str)> = authorization.iter().map(|a| ("Authorization", a.as_str())).collect();
+        let response = self.client.request(method, path, &headers, content_type, &body)?;
+        match (response.code(), method) {
+            (Some(200..=299), _) | (Some(303), "POST") => Ok(response.body),
+            _ => Err(io::Error::new(io::ErrorKind::Other, format!("Janet answered {} {}", response.status, response.body.trim()).trim().to_string())),
+        }
+    }
+}
+
+/// form values are percent encoded
+fn encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use std::thread;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn order(line: &str) -> Result<Order, String> {
+        parse(args(line)).map(|o| o.order)
+    }
+
+    #[test]
+    fn parse_orders() {
+        assert_that!(&order("light kitchen on"), eq(Ok(Order::Light { room: Room::Kitchen, status: LightStatus::ON })));
+        assert_that!(&order("blinds bedroom down"), eq(Ok(Order::Blinds { room: Room::BedRoom, status: BlindStatus::DOWN })));
+        assert_that!(&order("blinds living_room up"), eq(Ok(Order::Blinds { room: Room::LivingRoom, status: BlindStatus::UP })));
+        assert_that!(&order("screen up"), eq(Ok(Order::Screen { status: BlindStatus::UP })));
+        assert_that!(&order("scene cinema"), eq(Ok(Order::Scene { scene: Scene::Cinema })));
+        assert_that!(&order("state"), eq(Ok(Order::State)));
+    }
+
+    #[test]
+    fn parse_frames_to_send() {
+        assert_that!(&order("send --protocol dio --address 0x1337 --status on"),
+            eq(Ok(Order::Send { code: Code::Dio { address: 0x1337 }, button: Button::On, radio: None })));
+        assert_that!(&order("send --protocol dooya --status down --radio upstairs"),
+            eq(Ok(Order::Send { code: Code::Dooya, button: Button::Down, radio: Some("upstairs".to_string()) })));
+        assert_that!(&order("send --protocol dio --status on"), eq(Err("Dio needs an address".to_string())));
+        assert_that!(&order("send --protocol ev1527 --status on"), eq(Err("Unknown protocol".to_string())));
+    }
+
+    #[test]
+    fn parse_transmissions() {
+        assert_that!(&order("transmit --protocol DIO --payload 27133790 --radio upstairs"), eq(Ok(Order::Transmit {
+            transmission: Transmission { protocol: Protocol::Named("dio".to_string()), payload: "27133790".to_string(), radio: Some("upstairs".to_string()) }
+        })));
+        let defined = order("transmit --header +4800,-1500 --footer -8600 --zero +350,-750 --one +700,-400 --repetition 10 --payload 0641DFD133");
+        let expected = RadioProtocol::new("raw",
+                                          Header(vec![Signal::HIGH(Duration::from_micros(4800)), Signal::LOW(Duration::from_micros(1500))]),
+                                          Footer(vec![Signal::LOW(Duration::from_micros(8600))]),
+                                          Zero(vec![Signal::HIGH(Duration::from_micros(350)), Signal::LOW(Duration::from_micros(750))]),
+                                          One(vec![Signal::HIGH(Duration::from_micros(700)), Signal::LOW(Duration::from_micros(400))]),
+                                          10);
+        assert_that!(&defined, eq(Ok(Order::Transmit {
+            transmission: Transmission { protocol: Protocol::Defined(expected), payload: "0641DFD133".to_string(), radio: None }
+        })));
+        assert_that!(&order("transmit --header +4800 --payload 42"), eq(Err("transmit needs a --protocol or a --repetition".to_string())));
+        assert_that!(&order("transmit --header 4800 --footer -1 --zero +1 --one -1 --repetition 1 --payload 42"), eq(Err("Unknown signal 4800".to_string())));
+    }
+
+    #[test]
+    fn parse_captures() {
+        assert_that!(&order("import remote.sub"), eq(Ok(Order::Import {
+            file: "remote.sub".to_string(), format: Format::Flipper, sample_rate: None, name: "captured".to_string()
+        })));
+        assert_that!(&order("import capture.complex --sample-rate 2000000 --name somfy"), eq(Ok(Order::Import {
+            file: "capture.complex".to_string(), format: Format::Urh, sample_rate: Some(2_000_000), name: "somfy".to_string()
+        })));
+        assert_that!(&order("export remote.data --format sigrok --protocol dio --payload 27133790"), eq(Ok(Order::Export {
+            file: "remote.data".to_string(),
+            format: Format::Sigrok,
+            sample_rate: 1_000_000,
+            transmission: Transmission { protocol: Protocol::Named("dio".to_string()), payload: "27133790".to_string(), radio: None },
+        })));
+        assert_that!(&order("import remote"), eq(Err("Unknown format of remote, give it with --format".to_string())));
+        assert_that!(&order("import remote.wav --sample-rate 0"), eq(Err("Invalid sample rate 0".to_string())));
+        assert_that!(&order("export remote.wav --header +4800 --payload 42"), eq(Err("export needs a --protocol or a --repetition".to_string())));
+        assert_that!(&order("export remote.wav --protocol dio --payload 42 --radio upstairs"), eq(Err("--radio isn't an option of export".to_string())));
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = parse(args("--server https://janet:8000 --token secret --certificate janet.crt --config /etc/janet.toml state")).unwrap();
+        assert_that!(&options, eq(Options {
+            server: Some("https://janet:8000".to_string()),
+            token: Some("secret".to_string()),
+            certificate: Some("janet.crt".to_string()),
+            configuration: "/etc/janet.toml".to_string(),
+            order: Order::State,
+        }));
+    }
+
+    #[test]
+    fn reject_what_it_does_not_understand() {
+        assert_that!(&order("light garage on"), eq(Err("Unknown room garage".to_string())));
+        assert_that!(&order("light kitchen up"), eq(Err("Unknown status".to_string())));
+        assert_that!(&order("--verbose state"), eq(Err("Unknown option --verbose".to_string())));
+        assert_that!(&order("state --radio upstairs"), eq(Err("--radio isn't an option of state".to_string())));
+        assert_that!(&order("send --protocol dooya --status up --payload 42"), eq(Err("--payload isn't an option of send".to_string())));
+        assert_that!(&order("--server"), eq(Err("--server needs a value".to_string())));
+        assert_that!(&order(""), eq(Err(USAGE.to_string())));
+    }
+
+    #[test]
+    fn call_the_api_of_a_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Server::new(&format!("http://{}/", listener.local_addr().unwrap()), Some("secret".to_string()), None).unwrap();
+        let api = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut chunk = [0; 1024];
+            while !request.ends_with(b"status=on") {
+                let length = stream.read(&mut chunk).unwrap();
+                request.extend_from_slice(&chunk[..length]);
+            }
+            stream.write_all(b"HTTP/1.1 303 See Other\r\nLocation: /\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        server.order(&Order::Send { code: Code::Dio { address: 0x1337 }, button: Button::On, radio: None }).unwrap();
+        let request = api.join().unwrap();
+        assert_that!(&request.starts_with("POST /api/send HTTP/1.1\r\n"), eq(true));
+        assert_that!(&request.contains("\r\nAuthorization: Bearer secret\r\n"), eq(true));
+        assert_that!(&request.ends_with("\r\n\r\nprotocol=dio&address=0x1337&status=on"), eq(true));
+    }
+
+    #[test]
+    fn report_refusals_of_the_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Server::new(&listener.local_addr().unwrap().to_string(), None, None).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut chunk = [0; 1024];
+            while !request.ends_with(b"mode=Cinema") {
+                let length = stream.read(&mut chunk).unwrap();
+                request.extend_from_slice(&chunk[..length]);
+            }
+            stream.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 19\r\n\r\nNot allowed for bob").unwrap();
+        });
+        let refusal = server.order(&Order::Scene { scene: Scene::Cinema }).unwrap_err();
+        assert_that!(&refusal.to_string(), eq("Janet answered HTTP/1.1 403 Forbidden Not allowed for bob".to_string()));
+    }
+
+    #[test]
+    fn only_succeed_when_the_server_did() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Server::new(&listener.local_addr().unwrap().to_string(), Some("secret".to_string()), None).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut chunk = [0; 1024];
+            while !request.ends_with(b"status=UP") {
+                let length = stream.read(&mut chunk).unwrap();
+                request.extend_from_slice(&chunk[..length]);
+            }
+            stream.write_all(b"HTTP/1.1 308 Permanent Redirect\r\nLocation: https://janet/api/screen\r\n\r\n").unwrap();
+        });
+        let refusal = server.order(&Order::Screen { status: BlindStatus::UP }).unwrap_err();
+        assert_that!(&refusal.to_string(), eq("Janet answered HTTP/1.1 308 Permanent Redirect".to_string()));
+    }
+
+    #[test]
+    fn trust_https_servers_with_the_given_certificate() {
+        assert_that!(&Server::new("https://janet", None, Some("missing.crt")).is_err(), eq(true));
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// milliseconds since epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl TimeOfDay {
+    /// in a window starting at after and ending at before, going past midnight when before comes first
+    pub fn between(self, after: Option<TimeOfDay>, before: Option<TimeOfDay>) -> bool {
+        match (after, before) {
+            (Some(after), Some(before)) if before < after => self >= after || self < before,
+            (after, before) => after.map_or(true, |a| self >= a) && before.map_or(true, |b| self < b),
+        }
+    }
+}
+
+/// `HH:MM`
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hour, minute) = s.split_once(':').ok_or("Invalid time")?;
+        match (hour.parse(), minute.parse()) {
+            (Ok(hour), Ok(minute)) if hour < 24 && minute < 60 => Ok(TimeOfDay { hour, minute }),
+            _ => Err("Invalid time"),
+        }
+    }
+}
+
+/// a minute of the wall clock, counted from 1970-01-01 00:00 whatever the time zone and its changes
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Minute(pub i64);
+
+impl Minute {
+    pub fn new(year: i64, month: u8, day: u8, time: TimeOfDay) -> Self {
+        // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+        let (y, m, d) = (if month <= 2 { year - 1 } else { year }, month as i64, day as i64);
+        let era = y.div_euclid(400);
+        let year_of_era = y - era * 400;
+        let day_of_year = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        Minute(days * 1440 + time.hour as i64 * 60 + time.minute as i64)
+    }
+
+    /// year, month and day
+    pub fn date(self) -> (i64, u8, u8) {
+        let days = self.0.div_euclid(1440) + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    pub fn time(self) -> TimeOfDay {
+        let minutes = self.0.rem_euclid(1440);
+        TimeOfDay { hour: (minutes / 60) as u8, minute: (minutes % 60) as u8 }
+    }
+
+    /// 0 for sunday
+    pub fn weekday(self) -> u8 {
+        (self.0.div_euclid(1440) + 4).rem_euclid(7) as u8
+    }
+
+    pub fn midnight(self) -> Self {
+        Minute(self.0 - self.0.rem_euclid(1440))
+    }
+}
+
+/// the wall clock minute of a timestamp, in the time zone of the system
+pub fn local(timestamp: u64) -> Minute {
+    let time = (timestamp / 1000) as libc::time_t;
+    unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        libc::localtime_r(&time, &mut tm);
+        Minute::new(tm.tm_year as i64 + 1900, tm.tm_mon as u8 + 1, tm.tm_mday as u8, TimeOfDay { hour: tm.tm_hour as u8, minute: tm.tm_min as u8 })
+    }
+}
+
+/// the timestamp of a wall clock minute, minutes skipped when moving to summer time are pushed forward
+pub fn timestamp(minute: Minute) -> u64 {
+    let (year, month, day) = minute.date();
+    let time = minute.time();
+    unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        tm.tm_year = (year - 1900) as libc::c_int;
+        tm.tm_mon = month as libc::c_int - 1;
+        tm.tm_mday = day as libc::c_int;
+        tm.tm_hour = time.hour as libc::c_int;
+        tm.tm_min = time.minute as libc::c_int;
+        tm.tm_isdst = -1;
+        libc::mktime(&mut tm).max(0) as u64 * 1000
+    }
+}
+
+/// the local time of day of a timestamp, in the time zone of the system
+pub fn local_time(timestamp: u64) -> TimeOfDay {
+    local(timestamp).time()
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    fn at(time: &str) -> TimeOfDay {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_times_of_day() {
+        assert_that!(&"07:05".parse::<TimeOfDay>(), eq(Ok(TimeOfDay { hour: 7, minute: 5 })));
+        assert_that!(&at("23:59").to_string(), eq("23:59".to_string()));
+        assert_that!(&"24:00".parse::<TimeOfDay>().is_err(), eq(true));
+        assert_that!(&"noon".parse::<TimeOfDay>().is_err(), eq(true));
+    }
+
+    #[test]
+    fn count_wall_clock_minutes() {
+        assert_that!(&Minute::new(1970, 1, 1, at("00:01")), eq(Minute(1)));
+        let leap = Minute::new(2024, 2, 29, at("07:30"));
+        assert_that!(&leap.date(), eq((2024, 2, 29)));
+        assert_that!(&leap.time(), eq(at("07:30")));
+        assert_that!(&leap.weekday(), eq(4));
+        assert_that!(&Minute(leap.0 + 1440).date(), eq((2024, 3, 1)));
+        assert_that!(&Minute::new(1969, 12, 31, at("23:59")), eq(Minute(-1)));
+    }
+
+    #[test]
+    fn go_back_and_forth_between_timestamps_and_local_minutes() {
+        let now = 1_550_000_000_000;
+        let minute = local(now);
+        assert_that!(&timestamp(minute), eq(now - now % 60_000));
+    }
+
+    #[test]
+    fn check_windows_going_past_midnight() {
+        let night = (Some(at("22:00")), Some(at("06:30")));
+        assert_that!(&at("23:00").between(night.0, night.1), eq(true));
+        assert_that!(&at("03:00").between(night.0, night.1), eq(true));
+        assert_that!(&at("12:00").between(night.0, night.1), eq(false));
+        assert_that!(&at("12:00").between(Some(at("08:00")), None), eq(true));
+        assert_that!(&at("12:00").between(None, Some(at("08:00"))), eq(false));
+    }
+}
@@ -0,0 +1,1178 @@
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
+use crate::house::{Room, LightStatus, BlindStatus, Scene, Command};
+use crate::record::Format;
+use crate::weather::Reading;
+use crate::clock::TimeOfDay;
+use crate::schedule::{Cron, Weekdays};
+use crate::sun::SunEvent;
+use crate::timer::Delay;
+use crate::auth::{self, Permission};
+use crate::radio_protocol::{self, RadioProtocol};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Configuration {
+    pub radios: Vec<RadioConfiguration>,
+    pub devices: Vec<Device>,
+    #[serde(default)]
+    pub receivers: Vec<ReceiverConfiguration>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    #[serde(default)]
+    pub thermometers: Vec<Thermometer>,
+    #[serde(default)]
+    pub automations: Vec<Automation>,
+    #[serde(default)]
+    pub sensors: Vec<BinarySensor>,
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+    #[serde(default)]
+    pub location: Option<Location>,
+    #[serde(default)]
+    pub vacation: Vec<Presence>,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub users: Vec<User>,
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+    #[serde(default)]
+    pub tls: Option<Tls>,
+    #[serde(default)]
+    pub limits: Limits,
+    #[serde(default)]
+    pub mqtt: Option<Broker>,
+    /// the broker snips publishes its intents to
+    #[serde(default)]
+    pub hermes: Option<Broker>,
+    /// more protocols to transmit with, defined like the bundled ones of protocols.toml
+    #[serde(default)]
+    pub protocols: Vec<RadioProtocol<Vec<u8>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct RadioConfiguration {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: RadioKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum RadioKind {
+    Gpio { pin: u64 },
+    Serial {
+        path: String,
+        #[serde(default = "default_baud")]
+        baud: u32,
+        #[serde(default)]
+        protocols: Vec<String>,
+    },
+    Record {
+        record: String,
+        #[serde(default)]
+        format: Format,
+    },
+}
+
+/// where frames sent by physical remotes are heard
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ReceiverConfiguration {
+    Gpio { pin: u64 },
+    Serial {
+        path: String,
+        #[serde(default = "default_baud")]
+        baud: u32,
+    },
+}
+
+fn default_baud() -> u32 {
+    57600
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Light,
+    Blinds,
+    Screen,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum Code {
+    Dio { address: u16 },
+    Celexon,
+    Dooya,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Device {
+    pub kind: Kind,
+    pub room: Option<Room>,
+    #[serde(flatten)]
+    pub code: Code,
+    pub radio: String,
+    /// dio lights taking a level, from 0 to 15
+    #[serde(default)]
+    pub dimmer: bool,
+    /// how long dooya motors take to go all the way, to stop them on the way
+    #[serde(default)]
+    pub travel: Option<Delay>,
+}
+
+/// runs an action when a remote button is pressed
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Trigger {
+    #[serde(flatten)]
+    pub code: Code,
+    pub button: Button,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Button {
+    On,
+    Off,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Action {
+    Scene { scene: Scene },
+    Light { light: Room, status: LightStatus },
+    Blinds { blinds: Room, status: BlindStatus },
+    Screen { screen: BlindStatus },
+    Webhook { webhook: String },
+}
+
+impl Action {
+    /// what the action sends to devices of a room, scenes and webhooks are more than one command
+    pub fn command(&self) -> Option<Command> {
+        match *self {
+            Action::Light { light, status } => Some(Command::Light(light, status)),
+            Action::Blinds { blinds, status } => Some(Command::Blinds(blinds, status)),
+            Action::Screen { screen } => Some(Command::Screen(screen)),
+            Action::Scene { .. } | Action::Webhook { .. } => None,
+        }
+    }
+}
+
+/// the room a weather sensor is in, any id is accepted when none is given
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Thermometer {
+    pub room: Room,
+    pub protocol: String,
+    #[serde(default)]
+    pub channel: u8,
+    pub id: Option<u8>,
+}
+
+impl Thermometer {
+    pub fn reads(&self, reading: &Reading) -> bool {
+        self.protocol == reading.protocol && self.channel == reading.channel && self.id.map_or(true, |id| id == reading.id)
+    }
+}
+
+/// runs an action when its condition becomes true
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Automation {
+    #[serde(flatten)]
+    pub condition: Condition,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Condition {
+    /// the temperature of a room goes past a threshold
+    Temperature {
+        room: Room,
+        #[serde(default)]
+        above: Option<f32>,
+        #[serde(default)]
+        below: Option<f32>,
+    },
+    /// a sensor becomes active, or inactive, during a time of the day
+    Sensor {
+        sensor: String,
+        #[serde(default = "active")]
+        active: bool,
+        #[serde(default)]
+        after: Option<TimeOfDay>,
+        #[serde(default)]
+        before: Option<TimeOfDay>,
+    },
+}
+
+fn active() -> bool {
+    true
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorKind {
+    Door,
+    Window,
+    Motion,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum SensorCode {
+    Ev1527 { address: u32 },
+}
+
+/// a sensor sending a code when it opens or detects motion, some also send one when closing
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct BinarySensor {
+    pub name: String,
+    pub kind: SensorKind,
+    pub room: Option<Room>,
+    #[serde(flatten)]
+    pub code: SensorCode,
+    pub on: u8,
+    pub off: Option<u8>,
+}
+
+/// what is done while on vacation, at random times within a window of the day
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct Presence {
+    #[serde(flatten)]
+    pub device: Simulated,
+    pub after: TimeOfDay,
+    pub before: TimeOfDay,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Simulated {
+    /// switched on then off
+    Light { light: Room },
+    /// moved once
+    Blinds { blinds: Room, status: BlindStatus },
+}
+
+/// when something happens and the conditions hold, then do the steps in order
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub on: Pattern,
+    #[serde(rename = "if", default)]
+    pub conditions: Vec<Check>,
+    pub then: Vec<Step>,
+}
+
+/// the events a rule reacts to
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Pattern {
+    /// a frame heard by a receiver, any payload when none is given
+    Frame {
+        protocol: String,
+        #[serde(default)]
+        payload: Option<String>,
+    },
+    /// a call to `/api/fire/<name>`
+    Api { api: String },
+    /// a schedule being due
+    Schedule { schedule: String },
+    /// the condition of an automation becoming true
+    Condition(Condition),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check {
+    Light { light: Room, is: LightStatus },
+    Blinds { blinds: Room, is: BlindStatus },
+    Screen { screen: BlindStatus },
+    Vacation { vacation: bool },
+    Days { days: Weekdays },
+    /// a window of the day, going past midnight when before comes first
+    Time { after: Option<TimeOfDay>, before: Option<TimeOfDay> },
+}
+
+/// every key a condition can have, the ones present tell which condition it is
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckFields {
+    light: Option<Room>,
+    blinds: Option<Room>,
+    is: Option<String>,
+    screen: Option<BlindStatus>,
+    vacation: Option<bool>,
+    days: Option<Weekdays>,
+    after: Option<TimeOfDay>,
+    before: Option<TimeOfDay>,
+}
+
+impl<'de> Deserialize<'de> for Check {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let fields = CheckFields::deserialize(deserializer)?;
+        let is = || fields.is.as_deref().ok_or_else(|| D::Error::missing_field("is"));
+        let check = match fields {
+            CheckFields { light: Some(light), blinds: None, screen: None, vacation: None, days: None, after: None, before: None, .. } =>
+                Check::Light { light, is: is()?.parse().map_err(D::Error::custom)? },
+            CheckFields { light: None, blinds: Some(blinds), screen: None, vacation: None, days: None, after: None, before: None, .. } =>
+                Check::Blinds { blinds, is: is()?.parse().map_err(D::Error::custom)? },
+            CheckFields { light: None, blinds: None, is: None, screen: Some(screen), vacation: None, days: None, after: None, before: None } =>
+                Check::Screen { screen },
+            CheckFields { light: None, blinds: None, is: None, screen: None, vacation: Some(vacation), days: None, after: None, before: None } =>
+                Check::Vacation { vacation },
+            CheckFields { light: None, blinds: None, is: None, screen: None, vacation: None, days: Some(days), after: None, before: None } =>
+                Check::Days { days },
+            CheckFields { light: None, blinds: None, is: None, screen: None, vacation: None, days: None, after, before } if after.is_some() || before.is_some() =>
+                Check::Time { after, before },
+            _ => return Err(D::Error::custom("A condition checks one of light, blinds, screen, vacation, days or after and before")),
+        };
+        Ok(check)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Step {
+    /// the next steps are run this much later
+    Delay { delay: Delay },
+    Action(Action),
+}
+
+/// someone logging in the web page, the password is an argon2 hash
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub password: String,
+    pub permissions: Vec<Permission>,
+}
+
+/// a script sending `Authorization: Bearer <token>`, the token is an argon2 hash
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ApiToken {
+    pub name: String,
+    pub token: String,
+    pub permissions: Vec<Permission>,
+}
+
+/// serves https, a self-signed certificate and its key are written at these paths when missing
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Tls {
+    pub certificate: String,
+    pub key: String,
+    /// names the house answers to, put in generated certificates
+    #[serde(default = "default_hostnames")]
+    pub hostnames: Vec<String>,
+    /// a port answering plain http with a redirection to https
+    pub redirect: Option<u16>,
+}
+
+fn default_hostnames() -> Vec<String> {
+    vec!["janet".to_string(), "localhost".to_string()]
+}
+
+/// protects the motors and the api from repeated clicks
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Limits {
+    /// before a motor may go the other way
+    #[serde(default = "default_reversal")]
+    pub reversal: Delay,
+    /// the same command sent again within this is sent once
+    #[serde(default = "default_coalesce")]
+    pub coalesce: Delay,
+    /// requests per minute and client
+    #[serde(default = "default_requests")]
+    pub requests: u32,
+    /// the reverse proxy janet is behind, only its X-Real-IP header is trusted to tell the client
+    #[serde(default)]
+    pub proxy: Option<IpAddr>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { reversal: default_reversal(), coalesce: default_coalesce(), requests: default_requests(), proxy: None }
+    }
+}
+
+fn default_reversal() -> Delay {
+    Delay(Duration::from_secs(2))
+}
+
+fn default_coalesce() -> Delay {
+    Delay(Duration::from_secs(1))
+}
+
+fn default_requests() -> u32 {
+    120
+}
+
+/// an mqtt broker janet publishes its state to and takes commands from
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Broker {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_janet")]
+    pub client_id: String,
+    /// prefix of the topics of janet
+    #[serde(default = "default_janet")]
+    pub topic: String,
+    /// prefix of the home assistant discovery topics
+    #[serde(default = "default_discovery")]
+    pub discovery: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// what anyone publishing to the broker may command, like the permissions of tokens
+    #[serde(default = "everything")]
+    pub permissions: Vec<Permission>,
+}
+
+fn everything() -> Vec<Permission> {
+    vec![Permission::All]
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_janet() -> String {
+    "janet".to_string()
+}
+
+fn default_discovery() -> String {
+    "homeassistant".to_string()
+}
+
+/// where the house is, in degrees, east and north being positive
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// runs an action every time it is due
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    #[serde(flatten)]
+    pub when: When,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum When {
+    /// the wall clock matches a cron expression
+    Cron { cron: Cron },
+    /// minutes after, or before when negative, an event of the sun at the location of the house,
+    /// moved inside a window of the day and only on some days of the week
+    Sun {
+        sun: SunEvent,
+        #[serde(default)]
+        offset: i64,
+        #[serde(default)]
+        not_before: Option<TimeOfDay>,
+        #[serde(default)]
+        not_after: Option<TimeOfDay>,
+        #[serde(default)]
+        days: Option<Weekdays>,
+    },
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = format!("{:?}", self.kind).to_lowercase();
+        match self.room {
+            Some(room) => write!(f, "{} {:?}", kind, room),
+            None => write!(f, "{}", kind),
+        }
+    }
+}
+
+macro_rules! deserialize_from_str {
+    ($($t:ty),*) => {$(
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+                String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+            }
+        }
+    )*};
+}
+
+deserialize_from_str!(Room, LightStatus, BlindStatus, Scene, TimeOfDay, Cron, Weekdays, Delay, Permission);
+
+macro_rules! serialize_as_debug {
+    ($($t:ty),*) => {$(
+        impl Serialize for $t {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+                serializer.collect_str(&format_args!("{:?}", self))
+            }
+        }
+    )*};
+}
+
+serialize_as_debug!(Room, LightStatus, BlindStatus, Scene);
+
+impl Serialize for TimeOfDay {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl Configuration {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        fs::read_to_string(path).map_err(|e| e.to_string())?.parse()
+    }
+
+    /// the configuration in the file, the default one when there is no such file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        if path.as_ref().exists() {
+            Configuration::from_file(path)
+        } else {
+            Ok(Configuration::default())
+        }
+    }
+
+    fn check(self) -> Result<Self, String> {
+        for device in &self.devices {
+            if !self.radios.iter().any(|r| r.name == device.radio) {
+                return Err(format!("Unknown radio {}", device.radio));
+            }
+            match (device.kind, device.room) {
+                (Kind::Screen, _) | (_, Some(_)) => {}
+                (kind, None) => return Err(format!("A {:?} needs a room", kind)),
+            }
+            if let (Kind::Light, Code::Celexon) | (Kind::Light, Code::Dooya) = (device.kind, device.code) {
+                return Err(format!("Lights can't use {:?}", device.code));
+            }
+            if device.dimmer && device.kind != Kind::Light {
+                return Err(format!("The {} can't be a dimmer, only lights can", device));
+            }
+            if device.travel.is_some() && (device.kind, device.code) != (Kind::Blinds, Code::Dooya) {
+                return Err(format!("The {} can't stop on the way, only dooya blinds can", device));
+            }
+        }
+        let rules = self.rules.iter().filter_map(|r| match &r.on {
+            Pattern::Condition(condition) => Some(condition),
+            _ => None,
+        });
+        for condition in self.automations.iter().map(|a| &a.condition).chain(rules) {
+            match condition {
+                Condition::Temperature { room, .. } if !self.thermometers.iter().any(|t| t.room == *room) =>
+                    return Err(format!("No thermometer in {:?}", room)),
+                Condition::Sensor { sensor, .. } if !self.sensors.iter().any(|s| s.name == *sensor) =>
+                    return Err(format!("Unknown sensor {}", sensor)),
+                _ => {}
+            }
+        }
+        for trigger in &self.triggers {
+            if let (Code::Celexon, Button::On) | (Code::Celexon, Button::Off) | (Code::Dooya, Button::On) | (Code::Dooya, Button::Off) = (trigger.code, trigger.button) {
+                return Err(format!("{:?} remotes have no {:?} button", trigger.code, trigger.button));
+            }
+        }
+        for (i, schedule) in self.schedules.iter().enumerate() {
+            if self.schedules[..i].iter().any(|s| s.name == schedule.name) {
+                return Err(format!("Duplicate schedule {}", schedule.name));
+            }
+            if let (When::Sun { .. }, None) = (&schedule.when, self.location) {
+                return Err(format!("The {} schedule needs the location of the house", schedule.name));
+            }
+        }
+        for (name, hash) in self.users.iter().map(|u| (&u.name, &u.password)).chain(self.tokens.iter().map(|t| (&t.name, &t.token))) {
+            if !auth::valid_hash(hash) {
+                return Err(format!("The secret of {} isn't a password hash", name));
+            }
+        }
+        for rule in &self.rules {
+            if let Pattern::Schedule { schedule } = &rule.on {
+                if !self.schedules.iter().any(|s| s.name == *schedule) {
+                    return Err(format!("Unknown schedule {}", schedule));
+                }
+            }
+        }
+        if let (Some(mqtt), Some(hermes)) = (&self.mqtt, &self.hermes) {
+            if (&mqtt.host, mqtt.port, &mqtt.client_id) == (&hermes.host, hermes.port, &hermes.client_id) {
+                return Err(format!("The mqtt and hermes connections both use the client id {}", mqtt.client_id));
+            }
+        }
+        for (i, protocol) in self.protocols.iter().enumerate() {
+            if protocol.name().is_empty() || protocol.name() == radio_protocol::RAW {
+                return Err("Configured protocols need a name".to_string());
+            }
+            protocol.check()?;
+            if self.protocols[..i].iter().chain(radio_protocol::BUNDLED.iter()).any(|p| p.name() == protocol.name()) {
+                return Err(format!("The {} protocol is already defined", protocol.name()));
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for Configuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str::<Configuration>(s).map_err(|e| e.to_string())?.check()
+    }
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        include_str!("../janet.toml").parse().expect("Invalid default configuration")
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    #[test]
+    fn parse_devices() {
+        let configuration: Configuration = r#"
+            [[radios]]
+            name = "upstairs"
+            pin = 24
+
+            [[devices]]
+            kind = "light"
+            room = "bed_room"
+            protocol = "dio"
+            address = 0x985c
+            radio = "upstairs"
+
+            [[devices]]
+            kind = "screen"
+            protocol = "dooya"
+            radio = "upstairs"
+        "#.parse().unwrap();
+        assert_that!(&configuration.radios, eq(vec![RadioConfiguration { name: "upstairs".to_string(), kind: RadioKind::Gpio { pin: 24 } }]));
+        assert_that!(&configuration.devices, eq(vec![
+            Device { kind: Kind::Light, room: Some(Room::BedRoom), code: Code::Dio { address: 0x985c }, radio: "upstairs".to_string(), dimmer: false, travel: None },
+            Device { kind: Kind::Screen, room: None, code: Code::Dooya, radio: "upstairs".to_string(), dimmer: false, travel: None },
+        ]));
+    }
+
+    #[test]
+    fn parse_serial_radios() {
+        let configuration: Configuration = r#"
+            devices = []
+
+            [[radios]]
+            name = "rflink"
+            path = "/dev/ttyACM0"
+            protocols = ["dio"]
+        "#.parse().unwrap();
+        assert_that!(&configuration.radios, eq(vec![RadioConfiguration {
+            name: "rflink".to_string(),
+            kind: RadioKind::Serial { path: "/dev/ttyACM0".to_string(), baud: 57600, protocols: vec!["dio".to_string()] },
+        }]));
+    }
+
+    #[test]
+    fn parse_recording_radios() {
+        let configuration: Configuration = r#"
+            devices = []
+
+            [[radios]]
+            name = "recorder"
+            record = "transmissions.bin"
+            format = "binary"
+        "#.parse().unwrap();
+        assert_that!(&configuration.radios, eq(vec![RadioConfiguration {
+            name: "recorder".to_string(),
+            kind: RadioKind::Record { record: "transmissions.bin".to_string(), format: Format::Binary },
+        }]));
+    }
+
+    #[test]
+    fn parse_receivers() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[receivers]]
+            pin = 27
+
+            [[receivers]]
+            path = "/dev/ttyACM0"
+        "#.parse().unwrap();
+        assert_that!(&configuration.receivers, eq(vec![
+            ReceiverConfiguration::Gpio { pin: 27 },
+            ReceiverConfiguration::Serial { path: "/dev/ttyACM0".to_string(), baud: 57600 },
+        ]));
+    }
+
+    #[test]
+    fn parse_triggers() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[triggers]]
+            protocol = "dio"
+            address = 0xbeef
+            button = "on"
+            scene = "cinema"
+
+            [[triggers]]
+            protocol = "dio"
+            address = 0xbeef
+            button = "off"
+            light = "Kitchen"
+            status = "off"
+
+            [[triggers]]
+            protocol = "dooya"
+            button = "down"
+            webhook = "http://localhost:1880/screen"
+        "#.parse().unwrap();
+        assert_that!(&configuration.triggers, eq(vec![
+            Trigger { code: Code::Dio { address: 0xbeef }, button: Button::On, action: Action::Scene { scene: Scene::Cinema } },
+            Trigger { code: Code::Dio { address: 0xbeef }, button: Button::Off, action: Action::Light { light: Room::Kitchen, status: LightStatus::OFF } },
+            Trigger { code: Code::Dooya, button: Button::Down, action: Action::Webhook { webhook: "http://localhost:1880/screen".to_string() } },
+        ]));
+    }
+
+    #[test]
+    fn reject_missing_buttons() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[triggers]]
+            protocol = "celexon"
+            button = "on"
+            scene = "goodnight"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("Celexon remotes have no On button".to_string())));
+    }
+
+    #[test]
+    fn parse_thermometers_and_automations() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[thermometers]]
+            room = "LivingRoom"
+            protocol = "oregon"
+            channel = 1
+
+            [[automations]]
+            room = "LivingRoom"
+            above = 26
+            blinds = "LivingRoom"
+            status = "down"
+        "#.parse().unwrap();
+        assert_that!(&configuration.thermometers, eq(vec![Thermometer { room: Room::LivingRoom, protocol: "oregon".to_string(), channel: 1, id: None }]));
+        assert_that!(&configuration.automations, eq(vec![Automation {
+            condition: Condition::Temperature { room: Room::LivingRoom, above: Some(26.0), below: None },
+            action: Action::Blinds { blinds: Room::LivingRoom, status: BlindStatus::DOWN },
+        }]));
+    }
+
+    #[test]
+    fn reject_automations_without_thermometer() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[automations]]
+            room = "Kitchen"
+            below = 18.5
+            scene = "goodnight"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("No thermometer in Kitchen".to_string())));
+    }
+
+    #[test]
+    fn parse_sensors_and_their_automations() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[sensors]]
+            name = "kitchen motion"
+            kind = "motion"
+            room = "Kitchen"
+            protocol = "ev1527"
+            address = 0x5a3c1
+            on = 0xa
+
+            [[automations]]
+            sensor = "kitchen motion"
+            after = "22:00"
+            before = "06:00"
+            light = "Kitchen"
+            status = "on"
+        "#.parse().unwrap();
+        assert_that!(&configuration.sensors, eq(vec![BinarySensor {
+            name: "kitchen motion".to_string(),
+            kind: SensorKind::Motion,
+            room: Some(Room::Kitchen),
+            code: SensorCode::Ev1527 { address: 0x5a3c1 },
+            on: 0xa,
+            off: None,
+        }]));
+        assert_that!(&configuration.automations, eq(vec![Automation {
+            condition: Condition::Sensor {
+                sensor: "kitchen motion".to_string(),
+                active: true,
+                after: Some(TimeOfDay { hour: 22, minute: 0 }),
+                before: Some(TimeOfDay { hour: 6, minute: 0 }),
+            },
+            action: Action::Light { light: Room::Kitchen, status: LightStatus::ON },
+        }]));
+    }
+
+    #[test]
+    fn reject_automations_on_unknown_sensors() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[automations]]
+            sensor = "front door"
+            scene = "goodmorning"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("Unknown sensor front door".to_string())));
+    }
+
+    #[test]
+    fn reject_duplicate_schedules() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[schedules]]
+            name = "wake up"
+            cron = "30 7 * * mon-fri"
+            scene = "goodmorning"
+
+            [[schedules]]
+            name = "wake up"
+            cron = "0 9 * * sat,sun"
+            scene = "goodmorning"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("Duplicate schedule wake up".to_string())));
+    }
+
+    #[test]
+    fn parse_sun_schedules() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [location]
+            latitude = 48.8566
+            longitude = 2.3522
+
+            [[schedules]]
+            name = "blinds up"
+            sun = "sunrise"
+            not_before = "07:00"
+            days = "mon-fri"
+            blinds = "LivingRoom"
+            status = "up"
+        "#.parse().unwrap();
+        assert_that!(&configuration.schedules[0].when, eq(When::Sun {
+            sun: SunEvent::Sunrise,
+            offset: 0,
+            not_before: Some(TimeOfDay { hour: 7, minute: 0 }),
+            not_after: None,
+            days: Some("mon-fri".parse().unwrap()),
+        }));
+    }
+
+    #[test]
+    fn parse_tls() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [tls]
+            certificate = "janet.crt"
+            key = "janet.key"
+            redirect = 80
+        "#.parse().unwrap();
+        assert_that!(&configuration.tls, eq(Some(Tls {
+            certificate: "janet.crt".to_string(),
+            key: "janet.key".to_string(),
+            hostnames: vec!["janet".to_string(), "localhost".to_string()],
+            redirect: Some(80),
+        })));
+    }
+
+    #[test]
+    fn parse_limits() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [limits]
+            reversal = "5s"
+        "#.parse().unwrap();
+        assert_that!(&configuration.limits, eq(Limits { reversal: Delay(Duration::from_secs(5)), ..Limits::default() }));
+    }
+
+    #[test]
+    fn reject_two_connections_with_the_same_client_id() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [mqtt]
+            host = "janet"
+
+            [hermes]
+            host = "janet"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("The mqtt and hermes connections both use the client id janet".to_string())));
+    }
+
+    #[test]
+    fn parse_protocols() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[protocols]]
+            name = "somfy"
+            header = ["+2416", "-2416"]
+            footer = ["-30415"]
+            zero = ["-604", "+604"]
+            one = ["+604", "-604"]
+            bit_order = "lsb_first"
+            repetition = 4
+        "#.parse().unwrap();
+        let zero = vec!["-604".parse().unwrap(), "+604".parse().unwrap()];
+        assert_that!(&configuration.protocols[0].name(), eq("somfy"));
+        assert_that!(&configuration.protocols[0].timings_for_bytes(&[0xfe])[2..4].to_vec(), eq(zero));
+    }
+
+    #[test]
+    fn reject_protocols_defined_twice() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[protocols]]
+            name = "dio"
+            header = []
+            footer = []
+            zero = ["+250"]
+            one = ["-250"]
+            repetition = 1
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("The dio protocol is already defined".to_string())));
+    }
+
+    #[test]
+    fn reject_protocols_without_a_name_or_which_can_not_be_sent() {
+        let protocol = |definition: &str| format!("radios = []\ndevices = []\n[[protocols]]\nheader = []\nfooter = []\n{}", definition).parse::<Configuration>();
+        assert_that!(&protocol("zero = [\"+250\"]\none = [\"-250\"]\nrepetition = 1"), eq(Err("Configured protocols need a name".to_string())));
+        assert_that!(&protocol("name = \"somfy\"\nzero = [\"+250\"]\none = [\"-250\"]\nrepetition = 0"), eq(Err("The somfy protocol should be sent at least once".to_string())));
+        assert_that!(&protocol("name = \"somfy\"\nzero = [\"+250\"]\none = [\"+250\"]\nrepetition = 1"), eq(Err("The somfy protocol can't tell zero from one".to_string())));
+        assert_that!(&protocol("name = \"somfy\"\nzero = [\"+50\"]\none = [\"-250\"]\nrepetition = 1"), eq(Err("The somfy protocol has signals shorter than 100µs".to_string())));
+    }
+
+    #[test]
+    fn parse_rules() {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[rules]]
+            name = "doorbell"
+            on = { protocol = "dio", payload = "27BEEF90" }
+            if = [{ light = "LivingRoom", is = "off" }, { after = "19:00", before = "07:00" }, { days = "mon-fri" }]
+            then = [{ light = "LivingRoom", status = "on" }, { delay = "5m" }, { light = "LivingRoom", status = "off" }]
+        "#.parse().unwrap();
+        assert_that!(&configuration.rules, eq(vec![Rule {
+            name: "doorbell".to_string(),
+            on: Pattern::Frame { protocol: "dio".to_string(), payload: Some("27BEEF90".to_string()) },
+            conditions: vec![
+                Check::Light { light: Room::LivingRoom, is: LightStatus::OFF },
+                Check::Time { after: "19:00".parse().ok(), before: "07:00".parse().ok() },
+                Check::Days { days: "mon-fri".parse().unwrap() },
+            ],
+            then: vec![
+                Step::Action(Action::Light { light: Room::LivingRoom, status: LightStatus::ON }),
+                Step::Delay { delay: "5m".parse().unwrap() },
+                Step::Action(Action::Light { light: Room::LivingRoom, status: LightStatus::OFF }),
+            ],
+        }]));
+    }
+
+    #[test]
+    fn check_rules_like_automations() {
+        let rule = |on: &str| format!(r#"
+            radios = []
+            devices = []
+
+            [[thermometers]]
+            room = "LivingRoom"
+            protocol = "oregon"
+
+            [[rules]]
+            name = "hot"
+            on = {}
+            then = [{{ scene = "goodnight" }}]
+        "#, on).parse::<Configuration>().map(|c| c.rules[0].on.clone());
+        assert_that!(&rule(r#"{ room = "LivingRoom", above = 26 }"#), eq(Ok(Pattern::Condition(Condition::Temperature { room: Room::LivingRoom, above: Some(26.0), below: None }))));
+        assert_that!(&rule(r#"{ room = "BedRoom", above = 26 }"#), eq(Err("No thermometer in BedRoom".to_string())));
+        assert_that!(&rule(r#"{ sensor = "front door" }"#), eq(Err("Unknown sensor front door".to_string())));
+        assert_that!(&rule(r#"{ schedule = "morning" }"#), eq(Err("Unknown schedule morning".to_string())));
+    }
+
+    #[test]
+    fn reject_unknown_conditions() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[rules]]
+            name = "doorbell"
+            on = { api = "doorbell" }
+            if = [{ sunny = true }]
+            then = [{ scene = "cinema" }]
+        "#.parse::<Configuration>().unwrap_err();
+        assert_that!(&configuration.contains("unknown field `sunny`"), eq(true));
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[rules]]
+            name = "doorbell"
+            on = { api = "doorbell" }
+            if = [{ light = "LivingRoom" }]
+            then = [{ scene = "cinema" }]
+        "#.parse::<Configuration>().unwrap_err();
+        assert_that!(&configuration.contains("missing field `is`"), eq(true));
+    }
+
+    #[test]
+    fn reject_clear_passwords() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[users]]
+            name = "xavier"
+            password = "secret"
+            permissions = ["all"]
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("The secret of xavier isn't a password hash".to_string())));
+    }
+
+    #[test]
+    fn reject_sun_schedules_without_location() {
+        let configuration = r#"
+            radios = []
+            devices = []
+
+            [[schedules]]
+            name = "dusk"
+            sun = "dusk"
+            offset = -15
+            light = "LivingRoom"
+            status = "on"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("The dusk schedule needs the location of the house".to_string())));
+    }
+
+    #[test]
+    fn name_devices() {
+        let configuration = Configuration::default();
+        let names = configuration.devices.iter().map(Device::to_string).collect::<Vec<_>>();
+        assert_that!(&names[0], eq("light LivingRoom".to_string()));
+        assert_that!(&names[6], eq("screen".to_string()));
+    }
+
+    #[test]
+    fn reject_unknown_radio() {
+        let configuration = r#"
+            radios = []
+
+            [[devices]]
+            kind = "screen"
+            protocol = "dooya"
+            radio = "upstairs"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("Unknown radio upstairs".to_string())));
+    }
+
+    #[test]
+    fn reject_device_without_room() {
+        let configuration = r#"
+            [[radios]]
+            name = "433"
+            pin = 23
+
+            [[devices]]
+            kind = "blinds"
+            protocol = "celexon"
+            radio = "433"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("A Blinds needs a room".to_string())));
+    }
+
+    #[test]
+    fn reject_lights_without_dio() {
+        let configuration = r#"
+            [[radios]]
+            name = "433"
+            pin = 23
+
+            [[devices]]
+            kind = "light"
+            room = "Kitchen"
+            protocol = "celexon"
+            radio = "433"
+        "#.parse::<Configuration>();
+        assert_that!(&configuration, eq(Err("Lights can't use Celexon".to_string())));
+    }
+
+    #[test]
+    fn only_dim_lights_and_stop_dooya_blinds_on_the_way() {
+        let devices = |device: &str| format!(r#"
+            [[radios]]
+            name = "433"
+            pin = 23
+
+            [[devices]]
+            room = "Kitchen"
+            radio = "433"
+            {}
+        "#, device).parse::<Configuration>().map(|c| c.devices.len());
+        assert_that!(&devices("kind = \"light\"\nprotocol = \"dio\"\naddress = 1\ndimmer = true"), eq(Ok(1)));
+        assert_that!(&devices("kind = \"blinds\"\nprotocol = \"dooya\"\ntravel = \"30s\""), eq(Ok(1)));
+        assert_that!(&devices("kind = \"blinds\"\nprotocol = \"dio\"\naddress = 1\ndimmer = true").is_err(), eq(true));
+        assert_that!(&devices("kind = \"blinds\"\nprotocol = \"celexon\"\ntravel = \"30s\"").is_err(), eq(true));
+    }
+
+    #[test]
+    fn load_default_configuration() {
+        let configuration = Configuration::default();
+        assert_that!(&configuration.radios.len(), eq(1));
+        assert_that!(&configuration.devices.len(), eq(7));
+    }
+}
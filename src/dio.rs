@@ -1,6 +1,4 @@
 use crate::radio_protocol::*;
-use std::time::Duration;
-use crate::radio::Signal;
 
 pub enum Status {
     ON,
@@ -38,22 +36,53 @@ impl IntoIterator for DioMessage {
     }
 }
 
+/// a level from 0 to 15 for dimmers, a dim bit instead of the on/off bit and the level after the unit,
+/// the frame is given as the symbols put on air since the dim bit isn't manchester encoded
+pub struct DioDim {
+    address: u16,
+    level: u8,
+}
+
+impl DioDim {
+    pub fn new(address: u16, level: u8) -> Self {
+        DioDim { address, level: level.min(15) }
+    }
+}
+
+/// the bit telling dimmers a level follows
+const DIM_BIT: usize = 27;
+
+impl IntoIterator for DioDim {
+    type Item = u8;
+    type IntoIter = <Vec<u8> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let bytes = DioMessage::new(self.address, Status::OFF).into_iter().collect::<Vec<u8>>();
+        let bits = (0..32).map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0).map(Some)
+            .chain((0..4).rev().map(|i| Some(self.level & (1 << i) != 0)));
+        let symbols = bits.enumerate().flat_map(|(i, bit)| match if i == DIM_BIT { None } else { bit } {
+            Some(false) => vec![false, true],
+            Some(true) => vec![true, false],
+            None => vec![false, false],
+        }).collect::<Vec<bool>>();
+        symbols.chunks(8).map(|byte| byte.iter().fold(0, |b, &symbol| (b << 1) | symbol as u8)).collect::<Vec<u8>>().into_iter()
+    }
+}
+
 lazy_static! {
-    pub static ref DIO_PROTOCOL: RadioProtocol<DioMessage> = {
-        RadioProtocol::<DioMessage>::new(
-            Header(vec![Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(2793))]),
-            Footer(vec![Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(10540))]),
-            Zero(vec![Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(283)), Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(1355))]),
-            One(vec![Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(1355)), Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(283))]),
-            10)
-    };
+    pub static ref DIO_PROTOCOL: RadioProtocol<DioMessage> = bundled("dio");
+    /// dio symbols as they are, gateways don't know dim frames and send them as pulses
+    pub static ref DIO_DIM_PROTOCOL: RadioProtocol<DioDim> = bundled::<DioDim>("dio").with(BitOrder::MsbFirst, Encoding::Plain).named(RAW);
 }
 
 
 #[cfg(test)]
 mod should {
     use super::*;
+    use galvanic_assert::matchers::*;
     use galvanic_assert::matchers::collection::*;
+    use crate::radio::Signal::*;
+    use std::time::Duration;
 
     #[test]
     fn transforms_to_bytes_down() {
@@ -69,5 +98,25 @@ mod should {
         assert_that!(&bytes, contains_in_order(vec![0x27,0x12,0x34,0x80]));
     }
 
+    #[test]
+    fn dim_with_the_dim_bit_and_the_level_after_the_unit() {
+        let on = DIO_PROTOCOL.timings_for(DioMessage::new(0x1234, Status::ON));
+        let dim = DIO_DIM_PROTOCOL.timings_for(DioDim::new(0x1234, 8));
+        let short = vec![HIGH(Duration::from_micros(283)), LOW(Duration::from_micros(283))];
+        let long = vec![HIGH(Duration::from_micros(283)), LOW(Duration::from_micros(1355))];
+        assert_that!(&dim.len(), eq(148 * 10));
+        assert_that!(&dim[..110].to_vec(), eq(on[..110].to_vec()));
+        assert_that!(&dim[110..114].to_vec(), eq([short.clone(), short.clone()].concat()));
+        assert_that!(&dim[114..130].to_vec(), eq(on[114..130].to_vec()));
+        assert_that!(&dim[130..134].to_vec(), eq([long, short].concat()));
+        assert_that!(&dim[146..148].to_vec(), eq(on[130..132].to_vec()));
+    }
+
+    #[test]
+    fn dim_at_most_to_fifteen() {
+        let bytes = DioDim::new(0x1234, 200).into_iter().collect::<Vec<u8>>();
+        assert_that!(&bytes, eq(DioDim::new(0x1234, 15).into_iter().collect::<Vec<u8>>()));
+    }
+
 
 }
\ No newline at end of file
@@ -1,10 +1,9 @@
 use crate::radio_protocol::*;
-use std::time::Duration;
-use crate::radio::Signal;
 
 pub enum Status {
     UP,
     DOWN,
+    STOP,
 }
 
 impl IntoIterator for Status {
@@ -15,19 +14,13 @@ impl IntoIterator for Status {
         match self {
             Status::DOWN => vec![0x06, 0x41, 0xdf, 0xd1, 0x33].into_iter(),
             Status::UP => vec![0x06, 0x41, 0xdf, 0xd1, 0x11].into_iter(),
+            Status::STOP => vec![0x06, 0x41, 0xdf, 0xd1, 0x55].into_iter(),
         }
     }
 }
 
 lazy_static! {
-    pub static ref DOOYA_PROTOCOL: RadioProtocol<Status> = {
-        RadioProtocol::<Status>::new(
-            Header(vec![Signal::HIGH(Duration::from_micros(4800)), Signal::LOW(Duration::from_micros(1500))]),
-            Footer(vec![Signal::LOW(Duration::from_micros(8600))]),
-            Zero(vec![Signal::HIGH(Duration::from_micros(350)), Signal::LOW(Duration::from_micros(750))]),
-            One(vec![Signal::HIGH(Duration::from_micros(700)), Signal::LOW(Duration::from_micros(400))]),
-            10)
-    };
+    pub static ref DOOYA_PROTOCOL: RadioProtocol<Status> = bundled("dooya");
 }
 
 
@@ -49,4 +42,11 @@ mod should {
         let bytes = m.into_iter().collect::<Vec<u8>>();
         assert_that!(&bytes, contains_in_order(vec![0x06,0x41,0xdf,0xd1,0x11]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn transforms_to_bytes_stop() {
+        let m = Status::STOP;
+        let bytes = m.into_iter().collect::<Vec<u8>>();
+        assert_that!(&bytes, contains_in_order(vec![0x06,0x41,0xdf,0xd1,0x55]));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::radio_protocol::*;
+
+/// a 20 bits address followed by 4 bits of data, as sent by door contacts and motion sensors
+pub struct Ev1527Message {
+    address: u32,
+    data: u8,
+}
+
+impl Ev1527Message {
+    pub fn new(address: u32, data: u8) -> Self {
+        Ev1527Message { address: address & 0xfffff, data: data & 0xf }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [a, b, c] => Some(Ev1527Message::new((a as u32) << 12 | (b as u32) << 4 | (c as u32) >> 4, c & 0xf)),
+            _ => None,
+        }
+    }
+
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    pub fn data(&self) -> u8 {
+        self.data
+    }
+}
+
+impl IntoIterator for Ev1527Message {
+    type Item = u8;
+    type IntoIter = <Vec<u8> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        vec![(self.address >> 12) as u8, (self.address >> 4) as u8, ((self.address & 0xf) as u8) << 4 | self.data].into_iter()
+    }
+}
+
+lazy_static! {
+    pub static ref EV1527_PROTOCOL: RadioProtocol<Ev1527Message> = bundled("ev1527");
+}
+
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use galvanic_assert::matchers::collection::*;
+
+    #[test]
+    fn transforms_to_bytes() {
+        let bytes = Ev1527Message::new(0x5a3c1, 0xa).into_iter().collect::<Vec<u8>>();
+        assert_that!(&bytes, contains_in_order(vec![0x5a, 0x3c, 0x1a]));
+    }
+
+    #[test]
+    fn decode_every_repetition() {
+        let signals = EV1527_PROTOCOL.timings_for(Ev1527Message::new(0x5a3c1, 0xa));
+        let decoded = EV1527_PROTOCOL.decode(&signals);
+        assert_that!(&decoded.len(), eq(10));
+        let message = Ev1527Message::parse(&decoded[0]).unwrap();
+        assert_that!(&(message.address(), message.data()), eq((0x5a3c1, 0xa)));
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::min;
+use std::io;
+use std::io::Read;
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::auth::Access;
+use crate::house::{Command, Scene, State};
+
+/// idle streams send a comment this often, to keep proxies from closing them and notice gone clients
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<Sender<HouseEvent>>> = Mutex::new(vec![]);
+}
+
+/// what happens in the house, for the web page and dashboards to follow it live
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum HouseEvent {
+    CommandSent { device: String, command: Command },
+    StateChanged { state: State },
+    SceneStarted { scene: Scene },
+    SceneFinished { scene: Scene },
+    /// payload in hexadecimal
+    Frame { protocol: String, payload: String },
+    /// the configuration changed on disk and its rules replaced the running ones
+    RulesReloaded,
+    Error { message: String },
+}
+
+pub fn publish(event: HouseEvent) {
+    SUBSCRIBERS.lock().expect("Can't get lock on subscribers").retain(|s| s.send(event.clone()).is_ok());
+}
+
+/// every event published from now on, until the receiver is dropped
+pub fn subscribe() -> Receiver<HouseEvent> {
+    let (sender, receiver) = channel();
+    SUBSCRIBERS.lock().expect("Can't get lock on subscribers").push(sender);
+    receiver
+}
+
+/// logs the error and tells the subscribers about it
+pub fn error(message: String) {
+    eprintln!("{}", message);
+    publish(HouseEvent::Error { message });
+}
+
+/// Events as a server-sent events stream, each event being flushed on its own.
+pub struct Stream {
+    events: Receiver<HouseEvent>,
+    /// who follows the stream, only told what they may see
+    access: Access,
+    buffer: Vec<u8>,
+    position: usize,
+    flush: bool,
+}
+
+impl Stream {
+    pub fn new(events: Receiver<HouseEvent>, access: Access) -> Self {
+        Stream { events, access, buffer: vec![], position: 0, flush: false }
+    }
+}
+
+impl Read for Stream {
+    /// would block once an event is read, to have it flushed before waiting for the next one
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.buffer.len() {
+            if self.flush {
+                self.flush = false;
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "Waiting for events"));
+            }
+            let waiting = Instant::now();
+            let text = loop {
+                match self.events.recv_timeout(KEEP_ALIVE.saturating_sub(waiting.elapsed())) {
+                    Ok(event) => if let Some(event) = self.access.event(event) {
+                        break format!("data: {}\n\n", serde_json::to_string(&event).expect("Events are json"));
+                    },
+                    Err(RecvTimeoutError::Timeout) => break ": keep-alive\n\n".to_string(),
+                    Err(RecvTimeoutError::Disconnected) => return Ok(0),
+                }
+            };
+            self.buffer = text.into_bytes();
+            self.position = 0;
+        }
+        let length = min(buf.len(), self.buffer.len() - self.position);
+        buf[..length].copy_from_slice(&self.buffer[self.position..self.position + length]);
+        self.position += length;
+        // a full buffer is written anyway, asking for a flush would end the stream
+        self.flush = self.position == self.buffer.len() && length < buf.len();
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::sync::mpsc::channel;
+    use crate::house::{BlindStatus, LightStatus, Room};
+
+    #[test]
+    fn publish_to_every_subscriber() {
+        let (first, second) = (subscribe(), subscribe());
+        let event = HouseEvent::Error { message: "publish_to_every_subscriber".to_string() };
+        publish(event.clone());
+        assert_that!(&first.try_iter().any(|e| e == event), eq(true));
+        assert_that!(&second.try_iter().any(|e| e == event), eq(true));
+    }
+
+    #[test]
+    fn stream_events_one_by_one() {
+        let (sender, receiver) = channel();
+        let mut stream = Stream::new(receiver, Access::everything());
+        sender.send(HouseEvent::SceneStarted { scene: Scene::Cinema }).unwrap();
+        sender.send(HouseEvent::Frame { protocol: "dio".to_string(), payload: "27133790".to_string() }).unwrap();
+        drop(sender);
+        let mut buffer = [0; 256];
+        let length = stream.read(&mut buffer).unwrap();
+        assert_that!(&String::from_utf8_lossy(&buffer[..length]).to_string(), eq("data: {\"type\":\"scene-started\",\"scene\":\"Cinema\"}\n\n".to_string()));
+        assert_that!(&stream.read(&mut buffer).map_err(|e| e.kind()), eq(Err(io::ErrorKind::WouldBlock)));
+        let length = stream.read(&mut buffer).unwrap();
+        assert_that!(&String::from_utf8_lossy(&buffer[..length]).to_string(), eq("data: {\"type\":\"frame\",\"protocol\":\"dio\",\"payload\":\"27133790\"}\n\n".to_string()));
+        stream.read(&mut buffer).unwrap_err();
+        assert_that!(&stream.read(&mut buffer).unwrap(), eq(0));
+    }
+
+    #[test]
+    fn stream_only_what_the_caller_may_see() {
+        let (sender, receiver) = channel();
+        let mut stream = Stream::new(receiver, Access::new("guest", vec!["light Kitchen".parse().unwrap()]));
+        let mut state = State::default();
+        state.apply(Command::Light(Room::Kitchen, LightStatus::ON));
+        state.apply(Command::Screen(BlindStatus::DOWN));
+        sender.send(HouseEvent::SceneStarted { scene: Scene::Cinema }).unwrap();
+        sender.send(HouseEvent::Frame { protocol: "dio".to_string(), payload: "27133790".to_string() }).unwrap();
+        sender.send(HouseEvent::CommandSent { device: "screen".to_string(), command: Command::Screen(BlindStatus::DOWN) }).unwrap();
+        sender.send(HouseEvent::StateChanged { state }).unwrap();
+        drop(sender);
+        let mut buffer = [0; 256];
+        let length = stream.read(&mut buffer).unwrap();
+        assert_that!(&String::from_utf8_lossy(&buffer[..length]).to_string(),
+            eq("data: {\"type\":\"state-changed\",\"state\":{\"lights\":{\"Kitchen\":\"ON\"},\"blinds\":{},\"screen\":null,\"vacation\":false,\"levels\":{},\"positions\":{}}}\n\n".to_string()));
+    }
+}
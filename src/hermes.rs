@@ -0,0 +1,319 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::auth::Access;
+use crate::config::{Action, Broker, Device, Kind};
+use crate::events;
+use crate::house::{BlindStatus, House, LightStatus, Room, Scene};
+use crate::limit::Refusal;
+use crate::mqtt::{Client, Packet};
+use crate::page::words;
+use crate::trigger;
+
+/// before connecting again to the broker
+const RETRY: Duration = Duration::from_secs(10);
+
+/// An intent recognized by snips, as published on `hermes/intent/<name>`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Intent {
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub site_id: String,
+    pub intent: Recognized,
+    #[serde(default)]
+    pub slots: Vec<Slot>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recognized {
+    /// `<user>:<intent>` like `xadet:turnlightson`
+    pub intent_name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Slot {
+    pub slot_name: String,
+    pub value: SlotValue,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct SlotValue {
+    /// a string for custom slots, a number for percentages
+    pub value: Value,
+}
+
+impl Intent {
+    fn slot(&self, name: &str) -> Option<&Value> {
+        self.slots.iter().find(|s| s.slot_name == name).map(|s| &s.value.value)
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.slot(name).and_then(Value::as_str).map(|s| s.trim().to_lowercase())
+    }
+
+    /// the name without the user it belongs to
+    fn name(&self) -> String {
+        let name = &self.intent.intent_name;
+        name.rsplit(':').next().unwrap_or(name).to_lowercase()
+    }
+
+    /// the actions asked for on the configured devices, or what to answer when it doesn't make sense
+    pub fn understand(&self, devices: &[Device]) -> Result<Vec<Action>, String> {
+        let name = self.name();
+        let scene = self.text("scene").map(|s| s.replace(' ', "")).unwrap_or_else(|| name.clone());
+        if let Ok(scene) = scene.parse::<Scene>() {
+            return Ok(vec![Action::Scene { scene }]);
+        }
+        let device = self.text("device").unwrap_or_else(|| name.clone());
+        let kind = if device.contains("light") || device.contains("lamp") {
+            Kind::Light
+        } else if device.contains("blind") || device.contains("shutter") {
+            Kind::Blinds
+        } else if device.contains("screen") {
+            Kind::Screen
+        } else {
+            return Err("Sorry, I don't know which device you mean".to_string());
+        };
+        let rooms: Vec<Option<Room>> = match self.text("room") {
+            Some(text) => vec![Some(room(&text).ok_or_else(|| format!("Sorry, I don't know the {}", text))?)],
+            None => devices.iter().filter(|d| d.kind == kind).map(|d| d.room).collect(),
+        };
+        let mut actions = vec![];
+        for room in rooms {
+            if !devices.iter().any(|d| d.kind == kind && d.room == room) {
+                return Err(format!("Sorry, there is no {} there", words(kind).to_lowercase()));
+            }
+            let action = match (kind, room) {
+                (Kind::Light, Some(light)) => Action::Light { light, status: self.light()? },
+                (Kind::Blinds, Some(blinds)) => Action::Blinds { blinds, status: self.direction()? },
+                (Kind::Screen, _) => Action::Screen { screen: self.direction()? },
+                _ => continue,
+            };
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        Ok(actions)
+    }
+
+    fn status(&self) -> String {
+        self.text("status").unwrap_or_else(|| self.name())
+    }
+
+    fn light(&self) -> Result<LightStatus, String> {
+        let status = self.status();
+        match self.slot("percentage").and_then(Value::as_f64) {
+            Some(percentage) => Ok(if percentage > 0.0 { LightStatus::ON } else { LightStatus::OFF }),
+            None if status.ends_with("off") => Ok(LightStatus::OFF),
+            None if status.ends_with("on") => Ok(LightStatus::ON),
+            None => Err("Sorry, should I switch it on or off?".to_string()),
+        }
+    }
+
+    /// there is no frame to stop the motors, so there is no position other than open or closed
+    fn direction(&self) -> Result<BlindStatus, String> {
+        let status = self.status();
+        match self.slot("percentage").and_then(Value::as_f64) {
+            Some(percentage) if percentage >= 100.0 => Ok(BlindStatus::UP),
+            Some(percentage) if percentage <= 0.0 => Ok(BlindStatus::DOWN),
+            Some(_) => Err("Sorry, I can only open or close it".to_string()),
+            None if status.ends_with("up") || status.ends_with("open") => Ok(BlindStatus::UP),
+            None if status.ends_with("down") || status.ends_with("close") || status.ends_with("closed") => Ok(BlindStatus::DOWN),
+            None => Err("Sorry, should I open or close it?".to_string()),
+        }
+    }
+}
+
+/// `living room`, `bedroom` or `kitchen`
+pub(crate) fn room(text: &str) -> Option<Room> {
+    let snake = text.replace(' ', "_");
+    let capitalized = snake.chars().take(1).flat_map(char::to_uppercase).chain(snake.chars().skip(1)).collect::<String>();
+    snake.parse().or_else(|_| capitalized.parse()).ok()
+}
+
+/// what janet says once the actions are done, and the ones which failed
+pub fn answer(done: &[Action], failed: &[Action]) -> String {
+    let done: Vec<String> = done.iter().map(|action| match action {
+        Action::Scene { scene } => format!("{} started", words(scene)),
+        Action::Light { light, status } => format!("light of the {} switched {}", words(light).to_lowercase(), if *status == LightStatus::ON { "on" } else { "off" }),
+        Action::Blinds { blinds, status } => format!("blinds of the {} going {}", words(blinds).to_lowercase(), if *status == BlindStatus::UP { "up" } else { "down" }),
+        Action::Screen { screen } => format!("screen going {}", if *screen == BlindStatus::UP { "up" } else { "down" }),
+        Action::Webhook { .. } => "webhook called".to_string(),
+    }).collect();
+    let failed: Vec<String> = failed.iter().map(|action| match action {
+        Action::Scene { scene } => format!("start {}", words(scene).to_lowercase()),
+        Action::Light { light, status } => format!("switch {} the light of the {}", if *status == LightStatus::ON { "on" } else { "off" }, words(light).to_lowercase()),
+        Action::Blinds { blinds, status } => format!("move the blinds of the {} {}", words(blinds).to_lowercase(), if *status == BlindStatus::UP { "up" } else { "down" }),
+        Action::Screen { screen } => format!("move the screen {}", if *screen == BlindStatus::UP { "up" } else { "down" }),
+        Action::Webhook { .. } => "call the webhook".to_string(),
+    }).collect();
+    let sentence = match (done.is_empty(), failed.is_empty()) {
+        (true, true) => return "There is nothing to do".to_string(),
+        (false, true) => done.join(", "),
+        (true, false) => format!("sorry, I couldn't {}", failed.join(", nor ")),
+        (false, false) => format!("{}, but I couldn't {}", done.join(", "), failed.join(", nor ")),
+    };
+    sentence[..1].to_uppercase() + &sentence[1..]
+}
+
+/// obeys the intents snips publishes on its broker and answers out loud
+pub fn run<H>(house: &H, devices: &[Device], broker: &Broker) where H: House + Sync + ?Sized {
+    loop {
+        if let Err(e) = listen(house, devices, broker) {
+            events::error(format!("Lost connection to the hermes broker {}: {}", broker.host, e));
+        }
+        thread::sleep(RETRY);
+    }
+}
+
+fn listen<H>(house: &H, devices: &[Device], broker: &Broker) -> io::Result<()> where H: House + Sync + ?Sized {
+    let access = Access::new("hermes", broker.permissions.clone());
+    let client = Client::connect(broker, None)?;
+    client.subscribe(vec!["hermes/intent/#".to_string()])?;
+    thread::scope(|scope| {
+        scope.spawn(|| client.keep_alive());
+        let result = loop {
+            match client.receive() {
+                Ok(Packet::Publish { topic, payload, .. }) if topic.starts_with("hermes/intent/") => {
+                    if let Err(e) = obey(house, devices, &access, &client, &payload) {
+                        break Err(e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        client.close();
+        result
+    })
+}
+
+/// runs the permitted actions the house accepts and says which ones were done
+fn obey<H>(house: &H, devices: &[Device], access: &Access, client: &Client, payload: &[u8]) -> io::Result<()> where H: House + ?Sized {
+    let intent: Intent = match serde_json::from_slice(payload) {
+        Ok(intent) => intent,
+        Err(e) => {
+            events::error(format!("Invalid intent {}: {}", String::from_utf8_lossy(payload), e));
+            return Ok(());
+        }
+    };
+    let text = match intent.understand(devices) {
+        Ok(actions) => {
+            let (mut done, mut failed) = (vec![], vec![]);
+            for action in actions {
+                let result = if !access.action(&action) {
+                    Err("it isn't allowed through hermes".to_string())
+                } else {
+                    match action.command().map_or(Ok(()), |command| house.check(command)) {
+                        Ok(()) => trigger::run(house, &action, &json!({ "intent": intent.intent.intent_name })).map_err(|e| e.to_string()),
+                        Err(Refusal::Duplicate) => Ok(()),
+                        Err(refusal) => Err(refusal.to_string()),
+                    }
+                };
+                match result {
+                    Ok(()) => done.push(action),
+                    Err(e) => {
+                        events::error(format!("Can't run {:?}: {}", action, e));
+                        failed.push(action);
+                    }
+                }
+            }
+            answer(&done, &failed)
+        }
+        Err(answer) => answer,
+    };
+    let say = json!({ "text": text, "siteId": intent.site_id, "sessionId": intent.session_id });
+    client.publish("hermes/tts/say", say.to_string().as_bytes(), false)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Code;
+
+    fn devices() -> Vec<Device> {
+        let device = |kind, room, code| Device { kind, room, code, radio: "upstairs".to_string(), dimmer: false, travel: None };
+        vec![
+            device(Kind::Light, Some(Room::LivingRoom), Code::Dio { address: 1 }),
+            device(Kind::Light, Some(Room::Kitchen), Code::Dio { address: 2 }),
+            device(Kind::Blinds, Some(Room::BedRoom), Code::Dooya),
+            device(Kind::Screen, None, Code::Celexon),
+        ]
+    }
+
+    fn intent(name: &str, slots: Value) -> Intent {
+        serde_json::from_value(json!({
+            "sessionId": "42",
+            "siteId": "default",
+            "input": "whatever was said",
+            "intent": { "intentName": name, "confidenceScore": 0.9 },
+            "slots": slots,
+        })).unwrap()
+    }
+
+    fn slot(name: &str, value: Value) -> Value {
+        json!({ "slotName": name, "rawValue": value.to_string(), "entity": name, "value": { "kind": "Custom", "value": value } })
+    }
+
+    #[test]
+    fn switch_lights_of_a_room() {
+        let intent = intent("xadet:turnlightson", json!([slot("room", json!("living room"))]));
+        assert_that!(&intent.understand(&devices()), eq(Ok(vec![Action::Light { light: Room::LivingRoom, status: LightStatus::ON }])));
+    }
+
+    #[test]
+    fn switch_every_light_without_a_room() {
+        let intent = intent("xadet:turnlightsoff", json!([]));
+        assert_that!(&intent.understand(&devices()), eq(Ok(vec![
+            Action::Light { light: Room::LivingRoom, status: LightStatus::OFF },
+            Action::Light { light: Room::Kitchen, status: LightStatus::OFF },
+        ])));
+    }
+
+    #[test]
+    fn read_devices_statuses_and_percentages_from_slots() {
+        let blinds = intent("xadet:control", json!([slot("device", json!("blinds")), slot("room", json!("bedroom")), slot("status", json!("close"))]));
+        assert_that!(&blinds.understand(&devices()), eq(Ok(vec![Action::Blinds { blinds: Room::BedRoom, status: BlindStatus::DOWN }])));
+        let screen = intent("xadet:control", json!([slot("device", json!("screen")), slot("percentage", json!(100.0))]));
+        assert_that!(&screen.understand(&devices()), eq(Ok(vec![Action::Screen { screen: BlindStatus::UP }])));
+        let half = intent("xadet:control", json!([slot("device", json!("screen")), slot("percentage", json!(50.0))]));
+        assert_that!(&half.understand(&devices()), eq(Err("Sorry, I can only open or close it".to_string())));
+    }
+
+    #[test]
+    fn start_scenes() {
+        let intent = intent("xadet:startscene", json!([slot("scene", json!("good night"))]));
+        assert_that!(&intent.understand(&devices()), eq(Ok(vec![Action::Scene { scene: Scene::GoodNight }])));
+    }
+
+    #[test]
+    fn explain_what_it_does_not_understand() {
+        let unknown = intent("xadet:turnlightson", json!([slot("room", json!("garage"))]));
+        assert_that!(&unknown.understand(&devices()), eq(Err("Sorry, I don't know the garage".to_string())));
+        let missing = intent("xadet:turnlightson", json!([slot("room", json!("bedroom"))]));
+        assert_that!(&missing.understand(&devices()), eq(Err("Sorry, there is no light there".to_string())));
+        assert_that!(&intent("xadet:hello", json!([])).understand(&devices()), eq(Err("Sorry, I don't know which device you mean".to_string())));
+    }
+
+    #[test]
+    fn answer_what_was_done() {
+        assert_that!(&answer(&[Action::Light { light: Room::LivingRoom, status: LightStatus::ON }, Action::Screen { screen: BlindStatus::DOWN }], &[]),
+            eq("Light of the living room switched on, screen going down".to_string()));
+        assert_that!(&answer(&[Action::Scene { scene: Scene::Cinema }], &[]), eq("Cinema started".to_string()));
+    }
+
+    #[test]
+    fn say_which_actions_failed() {
+        let blinds = Action::Blinds { blinds: Room::BedRoom, status: BlindStatus::DOWN };
+        assert_that!(&answer(&[Action::Screen { screen: BlindStatus::UP }], &[blinds.clone()]),
+            eq("Screen going up, but I couldn't move the blinds of the bed room down".to_string()));
+        assert_that!(&answer(&[], &[blinds, Action::Scene { scene: Scene::GoodNight }]),
+            eq("Sorry, I couldn't move the blinds of the bed room down, nor start good night".to_string()));
+    }
+}
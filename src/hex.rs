@@ -0,0 +1,35 @@
+use std::fmt::Write;
+
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{:02X}", b);
+        hex
+    })
+}
+
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    #[test]
+    fn encode_bytes() {
+        assert_that!(&encode(&[0x27, 0x13, 0x37, 0x0a]), eq("2713370A".to_string()));
+    }
+
+    #[test]
+    fn decode_bytes() {
+        assert_that!(&decode("2713370a"), eq(Some(vec![0x27, 0x13, 0x37, 0x0a])));
+        assert_that!(&decode("271"), eq(None));
+        assert_that!(&decode("zz"), eq(None));
+    }
+}
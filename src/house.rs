@@ -1,21 +1,39 @@
 use std::str::FromStr;
-use crate::dio::DioMessage;
-use crate::dio::DIO_PROTOCOL;
+use std::collections::HashMap;
+use crate::dio::{DioDim, DioMessage};
+use crate::dio::{DIO_DIM_PROTOCOL, DIO_PROTOCOL};
 use crate::dio;
 use crate::dooya::DOOYA_PROTOCOL;
 use crate::dooya;
 use crate::celexon;
+use crate::config::{Configuration, Device, Kind, Code, Limits, RadioKind};
+use crate::pin::DigitalOutput;
 use crate::radio::Radio;
-use std::time::Duration;
+use crate::serial::SerialGateway;
+use crate::record::Recorder;
+use std::time::{Duration, Instant};
 use std::sync::Mutex;
-use crate::radio_protocol::RadioProtocol;
+use std::thread;
+use crate::radio_protocol::{self, RadioProtocol};
+use crate::events::{self, HouseEvent};
+use crate::limit::{Motors, Refusal};
+use crate::clock;
+use serde::Serialize;
 
 pub struct MyHouse<R>
     where R: Radio
 {
-    radio: Mutex<R>
+    radios: HashMap<String, Mutex<R>>,
+    devices: Vec<Device>,
+    state: Mutex<State>,
+    sent: Mutex<Vec<(String, Vec<u8>, Instant)>>,
+    protocols: Vec<RadioProtocol<Vec<u8>>>,
+    motors: Motors,
 }
 
+/// frames heard within this delay after janet sent them are its own echo
+const ECHO: Duration = Duration::from_secs(3);
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Room {
     Kitchen,
@@ -72,6 +90,32 @@ impl FromStr for LightStatus {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Scene {
+    Cinema,
+    GoodMorning,
+    GoodNight,
+    Vacation,
+}
+
+impl Scene {
+    pub const ALL: [Scene; 4] = [Scene::GoodMorning, Scene::GoodNight, Scene::Cinema, Scene::Vacation];
+}
+
+impl FromStr for Scene {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Cinema" | "CINEMA" | "cinema" => Ok(Scene::Cinema),
+            "GoodMorning" | "GOODMORNING" | "goodmorning" => Ok(Scene::GoodMorning),
+            "GoodNight" | "GOODNIGHT" | "goodnight" => Ok(Scene::GoodNight),
+            "Vacation" | "VACATION" | "vacation" => Ok(Scene::Vacation),
+            _ => Err("Unknown scene")
+        }
+    }
+}
+
 pub trait House {
     fn light(&self, room: Room, status: LightStatus);
     fn blinds(&self, room: Room, status: BlindStatus);
@@ -79,146 +123,487 @@ pub trait House {
     fn cinema(&self);
     fn goodmorning(&self);
     fn goodnight(&self);
+    fn scene(&self, scene: Scene) {
+        events::publish(HouseEvent::SceneStarted { scene });
+        match scene {
+            Scene::Cinema => self.cinema(),
+            Scene::GoodMorning => self.goodmorning(),
+            Scene::GoodNight => self.goodnight(),
+            Scene::Vacation => self.vacation(true),
+        }
+        events::publish(HouseEvent::SceneFinished { scene });
+    }
+    /// sets the dimmers of the room to a level from 0 to 15, turning them on
+    fn dim(&self, _room: Room, _level: u8) {}
+    /// stops the blinds of the room on their way, at the position in percent they reached
+    fn stop(&self, _room: Room, _position: u8) {}
+    /// while on vacation, the presence of someone is simulated
+    fn vacation(&self, enabled: bool);
+    fn state(&self) -> State;
+    /// updates the state from a frame sent by someone else, returns what it meant,
+    /// none when it's the echo of a frame janet just sent
+    fn received(&self, protocol: &str, payload: &[u8]) -> Option<Vec<Command>>;
+    /// whether the command would be sent now, motors aren't reversed too quickly nor sent the same command twice
+    fn check(&self, _command: Command) -> Result<(), Refusal> {
+        Ok(())
+    }
+    /// the protocols janet sends and hears, the bundled ones unless more are configured
+    fn protocols(&self) -> Vec<RadioProtocol<Vec<u8>>> {
+        radio_protocol::BUNDLED.clone()
+    }
+    /// one of the protocols janet sends and hears, by its name
+    fn protocol(&self, name: &str) -> Option<RadioProtocol<Vec<u8>>> {
+        self.protocols().into_iter().find(|p| p.name() == name)
+    }
+    /// sends a frame to no device in particular, through the given radio or the only one there is
+    fn transmit(&self, radio: Option<&str>, protocol: &RadioProtocol<Vec<u8>>, payload: &[u8]) -> Result<(), &'static str>;
 }
 
-impl<R> MyHouse<R>
-    where R: Radio {
-    pub fn new(radio: R) -> Self {
-        MyHouse { radio: Mutex::new(radio) }
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub enum Command {
+    Light(Room, LightStatus),
+    Blinds(Room, BlindStatus),
+    Screen(BlindStatus),
+}
+
+impl Command {
+    fn concerns(&self, device: &Device) -> bool {
+        match *self {
+            Command::Light(room, _) => device.kind == Kind::Light && device.room == Some(room),
+            Command::Blinds(room, _) => device.kind == Kind::Blinds && device.room == Some(room),
+            Command::Screen(_) => device.kind == Kind::Screen,
+        }
     }
-    fn send<M>(&self, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
-        let r = self.radio.lock().expect("Can't get lock on radio");
-        unsafe {
-            r.send(message, protocol);
+}
+
+/// what janet knows of the house, unknown until commanded or heard
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct State {
+    pub lights: HashMap<Room, LightStatus>,
+    pub blinds: HashMap<Room, BlindStatus>,
+    pub screen: Option<BlindStatus>,
+    pub vacation: bool,
+    /// levels of the dimmers that are on, from 0 to 15
+    pub levels: HashMap<Room, u8>,
+    /// how open the blinds are in percent, 100 when up
+    pub positions: HashMap<Room, u8>,
+}
+
+impl State {
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::Light(room, status) => {
+                self.lights.insert(room, status);
+                if status == LightStatus::OFF {
+                    self.levels.remove(&room);
+                }
+            }
+            Command::Blinds(room, status) => {
+                self.blinds.insert(room, status);
+                self.positions.insert(room, if status == BlindStatus::UP { 100 } else { 0 });
+            }
+            Command::Screen(status) => self.screen = Some(status),
         }
     }
 }
 
-impl<R> House for MyHouse<R>
-    where R: Radio {
-    fn light(&self, room: Room, status: LightStatus) {
-        let a = match room {
-            Room::LivingRoom => 0x1337,
-            Room::Kitchen => 0x0042,
-            Room::BedRoom => 0x985c,
-        };
-        let s = match status {
-            LightStatus::ON => dio::Status::ON,
-            LightStatus::OFF => dio::Status::OFF
-        };
-        let message = DioMessage::new(a, s);
-        self.send(message, &DIO_PROTOCOL);
+enum Message {
+    Dio(DioMessage),
+    Celexon(celexon::Status),
+    Dooya(dooya::Status),
+}
+
+impl Message {
+    fn protocol(&self) -> &'static str {
+        match self {
+            Message::Dio(_) => DIO_PROTOCOL.name(),
+            Message::Celexon(_) => celexon::CELEXON_PROTOCOL.name(),
+            Message::Dooya(_) => DOOYA_PROTOCOL.name(),
+        }
     }
 
-    fn blinds(&self, room: Room, status: BlindStatus) {
-        if room == Room::BedRoom {
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Message::Dio(m) => m.into_iter().collect(),
+            Message::Celexon(m) => m.into_iter().collect(),
+            Message::Dooya(m) => m.into_iter().collect(),
+        }
+    }
+}
+
+/// the protocols janet knows how to send
+pub fn protocol(name: &str) -> Option<RadioProtocol<Vec<u8>>> {
+    radio_protocol::BUNDLED.iter().find(|p| p.name() == name).cloned()
+}
+
+fn encode(device: &Device, command: Command) -> Option<Message> {
+    match (device.code, command) {
+        (Code::Dio { address }, Command::Light(_, status)) => {
             let s = match status {
-                BlindStatus::DOWN => celexon::Status::DOWN,
-                BlindStatus::UP => celexon::Status::UP
-            };
-            self.send(s, &celexon::CELEXON_PROTOCOL)
-        } else {
-            let a = match room {
-                Room::LivingRoom => 0x0932,
-                Room::Kitchen => 0x2600,
-                Room::BedRoom => 0x0000,
+                LightStatus::ON => dio::Status::ON,
+                LightStatus::OFF => dio::Status::OFF
             };
+            Some(Message::Dio(DioMessage::new(address, s)))
+        }
+        (Code::Dio { address }, Command::Blinds(_, status)) | (Code::Dio { address }, Command::Screen(status)) => {
             let s = match status {
                 BlindStatus::DOWN => dio::Status::DOWN,
                 BlindStatus::UP => dio::Status::UP
             };
-            let message = DioMessage::new(a, s);
-            self.send(message, &DIO_PROTOCOL)
+            Some(Message::Dio(DioMessage::new(address, s)))
+        }
+        (Code::Celexon, Command::Blinds(_, status)) | (Code::Celexon, Command::Screen(status)) => {
+            Some(Message::Celexon(match status {
+                BlindStatus::DOWN => celexon::Status::DOWN,
+                BlindStatus::UP => celexon::Status::UP
+            }))
+        }
+        (Code::Dooya, Command::Blinds(_, status)) | (Code::Dooya, Command::Screen(status)) => {
+            Some(Message::Dooya(match status {
+                BlindStatus::DOWN => dooya::Status::DOWN,
+                BlindStatus::UP => dooya::Status::UP,
+            }))
+        }
+        // lights only speak dio, see Configuration
+        (_, Command::Light(_, _)) => None
+    }
+}
+
+/// which way blinds go from a position to another and how long until they are stopped,
+/// given how long they take all the way
+pub fn course(from: u8, to: u8, travel: Duration) -> (BlindStatus, Duration) {
+    let status = if to > from { BlindStatus::UP } else { BlindStatus::DOWN };
+    let distance = u32::from(from.max(to).min(100) - from.min(to).min(100));
+    (status, travel * distance / 100)
+}
+
+/// every command a device understands
+fn commands(device: &Device) -> Vec<Command> {
+    match (device.kind, device.room) {
+        (Kind::Light, Some(room)) => vec![Command::Light(room, LightStatus::ON), Command::Light(room, LightStatus::OFF)],
+        (Kind::Blinds, Some(room)) => vec![Command::Blinds(room, BlindStatus::UP), Command::Blinds(room, BlindStatus::DOWN)],
+        (Kind::Screen, _) => vec![Command::Screen(BlindStatus::UP), Command::Screen(BlindStatus::DOWN)],
+        (_, None) => vec![],
+    }
+}
+
+impl<R> MyHouse<R>
+    where R: Radio + Send {
+    pub fn new(radios: HashMap<String, R>, devices: Vec<Device>) -> Self {
+        MyHouse {
+            radios: radios.into_iter().map(|(name, radio)| (name, Mutex::new(radio))).collect(),
+            devices,
+            state: Mutex::new(State::default()),
+            sent: Mutex::new(vec![]),
+            protocols: vec![],
+            motors: Motors::new(&Limits::default()),
         }
     }
 
+    /// protocols defined in the configuration, sent when transmitting on their name
+    pub fn with_protocols(self, protocols: Vec<RadioProtocol<Vec<u8>>>) -> Self {
+        MyHouse { protocols, ..self }
+    }
+
+    /// how quickly motors may be commanded, whoever commands them
+    pub fn with_limits(self, limits: &Limits) -> Self {
+        MyHouse { motors: Motors::new(limits), ..self }
+    }
+
+    /// commands for different radios are sent in parallel, in order for a same radio,
+    /// the ones the motors refuse are dropped
+    fn execute(&self, commands: Vec<Command>) {
+        let now = clock::now();
+        let commands = commands.into_iter().filter(|&command| match self.motors.check(command, now) {
+            Ok(()) => true,
+            Err(Refusal::Duplicate) => false,
+            Err(refusal) => {
+                events::error(format!("{:?} refused, {}", command, refusal));
+                false
+            }
+        }).collect::<Vec<_>>();
+        let mut by_radio: HashMap<&str, Vec<(&Device, Command)>> = HashMap::new();
+        self.update(|state| commands.iter().for_each(|&c| state.apply(c)));
+        for command in commands {
+            for device in self.devices.iter().filter(|d| command.concerns(d)) {
+                by_radio.entry(&device.radio).or_default().push((device, command));
+            }
+        }
+        thread::scope(|s| {
+            for (radio, orders) in by_radio {
+                s.spawn(move || {
+                    for (device, command) in orders {
+                        self.transmit_command(radio, device, command);
+                    }
+                });
+            }
+        });
+    }
+
+    /// tells the subscribers when the state changes
+    fn update<F>(&self, f: F) where F: FnOnce(&mut State) {
+        let mut state = self.state.lock().expect("Can't get lock on state");
+        let before = state.clone();
+        f(&mut state);
+        if *state != before {
+            events::publish(HouseEvent::StateChanged { state: state.clone() });
+        }
+    }
+
+    fn transmit_command(&self, radio: &str, device: &Device, command: Command) {
+        if let Some(message) = encode(device, command) {
+            let frame = (message.protocol().to_string(), message.bytes(), Instant::now());
+            self.sent.lock().expect("Can't get lock on sent frames").push(frame);
+            events::publish(HouseEvent::CommandSent { device: device.to_string(), command });
+        }
+        match encode(device, command) {
+            Some(Message::Dio(m)) => self.send(radio, device, m, &DIO_PROTOCOL),
+            Some(Message::Celexon(m)) => self.send(radio, device, m, &celexon::CELEXON_PROTOCOL),
+            Some(Message::Dooya(m)) => self.send(radio, device, m, &DOOYA_PROTOCOL),
+            None => {}
+        }
+    }
+
+    fn send<M>(&self, radio: &str, device: &Device, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
+        let r = self.radios[radio].lock().expect("Can't get lock on radio");
+        unsafe {
+            r.send_to(&device.to_string(), message, protocol);
+        }
+    }
+
+    /// the commands a frame means for the configured devices
+    fn meaning(&self, protocol: &str, payload: &[u8]) -> Vec<Command> {
+        self.devices.iter()
+            .flat_map(|device| commands(device).into_iter().map(move |command| (device, command)))
+            .filter(|&(device, command)| encode(device, command).map_or(false, |m| m.protocol() == protocol && m.bytes() == payload))
+            .map(|(_, command)| command)
+            .collect()
+    }
+
+    fn echo(&self, protocol: &str, payload: &[u8]) -> bool {
+        let mut sent = self.sent.lock().expect("Can't get lock on sent frames");
+        sent.retain(|(_, _, at)| at.elapsed() < ECHO);
+        sent.iter().any(|(p, b, _)| p == protocol && b.as_slice() == payload)
+    }
+}
+
+impl<R> House for MyHouse<R>
+    where R: Radio + Send {
+    fn light(&self, room: Room, status: LightStatus) {
+        self.execute(vec![Command::Light(room, status)]);
+    }
+
+    fn blinds(&self, room: Room, status: BlindStatus) {
+        self.execute(vec![Command::Blinds(room, status)]);
+    }
+
     fn screen(&self, status: BlindStatus) {
-        let message = match status {
-            BlindStatus::DOWN => dooya::Status::DOWN,
-            BlindStatus::UP => dooya::Status::UP,
-        };
-        self.send(message, &DOOYA_PROTOCOL)
+        self.execute(vec![Command::Screen(status)]);
     }
 
     // modes should be configuration
     fn cinema(&self) {
-        self.blinds(Room::LivingRoom, BlindStatus::DOWN);
-        self.blinds(Room::Kitchen, BlindStatus::DOWN);
-        self.screen(BlindStatus::DOWN);
-        self.light(Room::LivingRoom, LightStatus::OFF);
-        self.light(Room::Kitchen, LightStatus::OFF);
+        self.execute(vec![
+            Command::Blinds(Room::LivingRoom, BlindStatus::DOWN),
+            Command::Blinds(Room::Kitchen, BlindStatus::DOWN),
+            Command::Screen(BlindStatus::DOWN),
+            Command::Light(Room::LivingRoom, LightStatus::OFF),
+            Command::Light(Room::Kitchen, LightStatus::OFF),
+        ]);
     }
 
     fn goodmorning(&self) {
-        self.blinds(Room::LivingRoom, BlindStatus::UP);
-        self.blinds(Room::Kitchen, BlindStatus::UP);
-        self.blinds(Room::BedRoom, BlindStatus::UP);
-        self.screen(BlindStatus::UP);
-        self.light(Room::LivingRoom, LightStatus::OFF);
-        self.light(Room::BedRoom, LightStatus::OFF);
-        self.light(Room::Kitchen, LightStatus::OFF);
+        self.execute(vec![
+            Command::Blinds(Room::LivingRoom, BlindStatus::UP),
+            Command::Blinds(Room::Kitchen, BlindStatus::UP),
+            Command::Blinds(Room::BedRoom, BlindStatus::UP),
+            Command::Screen(BlindStatus::UP),
+            Command::Light(Room::LivingRoom, LightStatus::OFF),
+            Command::Light(Room::BedRoom, LightStatus::OFF),
+            Command::Light(Room::Kitchen, LightStatus::OFF),
+        ]);
     }
 
     fn goodnight(&self) {
-        self.blinds(Room::LivingRoom, BlindStatus::DOWN);
-        self.blinds(Room::Kitchen, BlindStatus::DOWN);
-        self.blinds(Room::BedRoom, BlindStatus::DOWN);
-        self.screen(BlindStatus::UP);
-        self.light(Room::LivingRoom, LightStatus::OFF);
-        self.light(Room::BedRoom, LightStatus::OFF);
-        self.light(Room::Kitchen, LightStatus::OFF);
+        self.execute(vec![
+            Command::Blinds(Room::LivingRoom, BlindStatus::DOWN),
+            Command::Blinds(Room::Kitchen, BlindStatus::DOWN),
+            Command::Blinds(Room::BedRoom, BlindStatus::DOWN),
+            Command::Screen(BlindStatus::UP),
+            Command::Light(Room::LivingRoom, LightStatus::OFF),
+            Command::Light(Room::BedRoom, LightStatus::OFF),
+            Command::Light(Room::Kitchen, LightStatus::OFF),
+        ]);
+    }
+
+    fn dim(&self, room: Room, level: u8) {
+        let level = level.min(15);
+        self.update(|state| {
+            state.lights.insert(room, LightStatus::ON);
+            state.levels.insert(room, level);
+        });
+        let command = Command::Light(room, LightStatus::ON);
+        for device in self.devices.iter().filter(|d| d.dimmer && command.concerns(d)) {
+            if let Code::Dio { address } = device.code {
+                self.send(&device.radio, device, DioDim::new(address, level), &DIO_DIM_PROTOCOL);
+            }
+        }
+    }
+
+    fn stop(&self, room: Room, position: u8) {
+        self.update(|state| {
+            state.positions.insert(room, position.min(100));
+        });
+        let command = Command::Blinds(room, BlindStatus::UP);
+        for device in self.devices.iter().filter(|d| d.travel.is_some() && command.concerns(d)) {
+            self.send(&device.radio, device, dooya::Status::STOP, &DOOYA_PROTOCOL);
+        }
+    }
+
+    fn vacation(&self, enabled: bool) {
+        self.update(|state| state.vacation = enabled);
+    }
+
+    fn state(&self) -> State {
+        self.state.lock().expect("Can't get lock on state").clone()
+    }
+
+    fn received(&self, protocol: &str, payload: &[u8]) -> Option<Vec<Command>> {
+        if self.echo(protocol, payload) {
+            return None;
+        }
+        let heard = self.meaning(protocol, payload);
+        self.update(|state| heard.iter().for_each(|&c| state.apply(c)));
+        Some(heard)
+    }
+
+    fn check(&self, command: Command) -> Result<(), Refusal> {
+        self.motors.allowed(command, clock::now())
+    }
+
+    fn protocols(&self) -> Vec<RadioProtocol<Vec<u8>>> {
+        self.protocols.iter().chain(radio_protocol::BUNDLED.iter()).cloned().collect()
+    }
+
+    fn transmit(&self, radio: Option<&str>, protocol: &RadioProtocol<Vec<u8>>, payload: &[u8]) -> Result<(), &'static str> {
+        let radio = match radio {
+            Some(name) => self.radios.get(name).ok_or("Unknown radio")?,
+            None if self.radios.len() == 1 => self.radios.values().next().expect("There is a radio"),
+            None => return Err("Several radios could send it"),
+        };
+        let now = clock::now();
+        for command in self.meaning(protocol.name(), payload) {
+            match self.motors.check(command, now) {
+                Ok(()) => {}
+                Err(Refusal::Duplicate) => return Err("The same command was just sent"),
+                Err(Refusal::Reversal { .. }) => return Err("The motor can't go the other way yet"),
+            }
+        }
+        let frame = (protocol.name().to_string(), payload.to_vec(), Instant::now());
+        self.sent.lock().expect("Can't get lock on sent frames").push(frame);
+        let r = radio.lock().expect("Can't get lock on radio");
+        unsafe {
+            r.send(payload.to_vec(), protocol);
+        }
+        Ok(())
     }
 }
 
+enum AnyRadio<O> {
+    Gpio(O),
+    Serial(SerialGateway),
+    Record(Recorder),
+}
+
+impl<O> Radio for AnyRadio<O>
+    where O: DigitalOutput {
+    unsafe fn send<M>(&self, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
+        match self {
+            AnyRadio::Gpio(output) => output.send(message, protocol),
+            AnyRadio::Serial(gateway) => gateway.send(message, protocol),
+            AnyRadio::Record(recorder) => recorder.send(message, protocol),
+        }
+    }
+
+    unsafe fn send_to<M>(&self, device: &str, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
+        match self {
+            AnyRadio::Gpio(output) => output.send_to(device, message, protocol),
+            AnyRadio::Serial(gateway) => gateway.send_to(device, message, protocol),
+            AnyRadio::Record(recorder) => recorder.send_to(device, message, protocol),
+        }
+    }
+}
+
+fn radios<O, F>(configuration: &Configuration, gpio: F) -> HashMap<String, AnyRadio<O>>
+    where F: Fn(u64) -> O {
+    configuration.radios.iter().map(|r| {
+        let radio = match &r.kind {
+            RadioKind::Gpio { pin } => AnyRadio::Gpio(gpio(*pin)),
+            RadioKind::Serial { path, baud, protocols } =>
+                AnyRadio::Serial(SerialGateway::open(path, *baud, protocols.clone()).expect("Can't open serial gateway")),
+            RadioKind::Record { record, format } =>
+                AnyRadio::Record(Recorder::create(record, *format).expect("Can't open recording")),
+        };
+        (r.name.clone(), radio)
+    }).collect()
+}
+
 #[cfg(target_arch = "arm")]
-pub fn house() -> impl House {
+pub fn house(configuration: &Configuration) -> impl House {
     use sysfs_gpio::Pin;
     use sysfs_gpio::Direction;
     use std::thread::sleep;
 
-    let pin = Pin::new(23);
-    pin.export().unwrap();
-    if pin.set_direction(Direction::Low).is_err() {
-        sleep(Duration::from_millis(500));
-        pin.set_direction(Direction::Low).unwrap();
-    };
-    MyHouse::new(pin)
+    let radios = radios(configuration, |p| {
+        let pin = Pin::new(p);
+        pin.export().unwrap();
+        if pin.set_direction(Direction::Low).is_err() {
+            sleep(Duration::from_millis(500));
+            pin.set_direction(Direction::Low).unwrap();
+        };
+        pin
+    });
+    MyHouse::new(radios, configuration.devices.clone()).with_protocols(configuration.protocols.clone()).with_limits(&configuration.limits)
 }
 
 #[cfg(not(target_arch = "arm"))]
-pub fn house() -> impl House {
-    use crate::pin::DigitalOutput;
-
+pub fn house(configuration: &Configuration) -> impl House {
     #[derive(Debug, Clone)]
     pub struct FakeDigitalOutput {
-        pin: usize
+        pin: u64
     }
 
     impl FakeDigitalOutput {
-        fn new(pin: usize) -> Self {
+        fn new(pin: u64) -> Self {
             FakeDigitalOutput { pin }
         }
     }
 
     impl DigitalOutput for FakeDigitalOutput {
-        fn high_during(&self, duration: Duration) -> () {
+        fn high_during(&self, duration: Duration) {
             println!("high for {:?} on {}", duration, self.pin)
         }
 
-        fn low_during(&self, duration: Duration) -> () {
+        fn low_during(&self, duration: Duration) {
             println!("low for {:?} on {}", duration, self.pin)
         }
     }
 
-    let pin = FakeDigitalOutput::new(23);
-    MyHouse::new(pin)
+    MyHouse::new(radios(configuration, FakeDigitalOutput::new), configuration.devices.clone()).with_protocols(configuration.protocols.clone()).with_limits(&configuration.limits)
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec, clippy::bool_assert_comparison)]
 mod should {
     use super::*;
     use galvanic_assert::matchers::*;
     use crate::radio::mock::InMemoryRadio;
+    use crate::radio_protocol::{Footer, Header, One, Zero};
+
+    fn single_radio_house() -> MyHouse<InMemoryRadio> {
+        let radios = vec![("433".to_string(), InMemoryRadio::new())].into_iter().collect();
+        MyHouse::new(radios, Configuration::default().devices)
+    }
 
     #[test]
     fn lights() {
@@ -230,10 +615,9 @@ mod should {
             (Room::Kitchen, LightStatus::ON, DioMessage::new(0x0042, dio::Status::ON)),
             (Room::Kitchen, LightStatus::OFF, DioMessage::new(0x0042, dio::Status::OFF)),
         ] {
-            let radio = InMemoryRadio::new();
-            let house = MyHouse::new(radio);
+            let house = single_radio_house();
             house.light(room, status);
-            let received = house.radio.lock().unwrap().received(message, &DIO_PROTOCOL);
+            let received = house.radios["433"].lock().unwrap().received(message, &DIO_PROTOCOL);
             assert_that!(&received, eq(true));
         }
     }
@@ -246,20 +630,18 @@ mod should {
             (Room::Kitchen, BlindStatus::DOWN, DioMessage::new(0x2600, dio::Status::DOWN), &DIO_PROTOCOL),
             (Room::Kitchen, BlindStatus::UP, DioMessage::new(0x2600, dio::Status::UP), &DIO_PROTOCOL),
         ] {
-            let radio = InMemoryRadio::new();
-            let house = MyHouse::new(radio);
+            let house = single_radio_house();
             house.blinds(room, status);
-            let received = house.radio.lock().unwrap().received(message, protocol);
+            let received = house.radios["433"].lock().unwrap().received(message, protocol);
             assert_that!(&received, eq(true));
         }
         for (room, status, message, protocol) in vec![
             (Room::BedRoom, BlindStatus::DOWN, celexon::Status::DOWN, &celexon::CELEXON_PROTOCOL),
             (Room::BedRoom, BlindStatus::UP, celexon::Status::UP, &celexon::CELEXON_PROTOCOL),
         ] {
-            let radio = InMemoryRadio::new();
-            let house = MyHouse::new(radio);
+            let house = single_radio_house();
             house.blinds(room, status);
-            let received = house.radio.lock().unwrap().received(message, protocol);
+            let received = house.radios["433"].lock().unwrap().received(message, protocol);
             assert_that!(&received, eq(true));
         }
     }
@@ -270,14 +652,231 @@ mod should {
             (BlindStatus::DOWN, dooya::Status::DOWN),
             (BlindStatus::UP, dooya::Status::UP),
         ] {
-            let radio = InMemoryRadio::new();
-            let house = MyHouse::new(radio);
+            let house = single_radio_house();
             house.screen(status);
-            let received = house.radio.lock().unwrap().received(message, &DOOYA_PROTOCOL);
+            let received = house.radios["433"].lock().unwrap().received(message, &DOOYA_PROTOCOL);
             assert_that!(&received, eq(true));
         }
     }
 
+    #[test]
+    fn dim_lights_that_are_dimmers() {
+        let configuration: Configuration = r#"
+            [[radios]]
+            name = "433"
+            pin = 23
+
+            [[devices]]
+            kind = "light"
+            room = "Kitchen"
+            protocol = "dio"
+            address = 0x0042
+            radio = "433"
+            dimmer = true
+        "#.parse().unwrap();
+        let radios = vec![("433".to_string(), InMemoryRadio::new())].into_iter().collect();
+        let house = MyHouse::new(radios, configuration.devices);
+        house.dim(Room::Kitchen, 20);
+        let received = house.radios["433"].lock().unwrap().received(DioDim::new(0x0042, 15), &DIO_DIM_PROTOCOL);
+        assert_that!(&received, eq(true));
+        let state = house.state();
+        assert_that!(&state.lights.get(&Room::Kitchen), eq(Some(&LightStatus::ON)));
+        assert_that!(&state.levels.get(&Room::Kitchen), eq(Some(&15)));
+        house.light(Room::Kitchen, LightStatus::OFF);
+        assert_that!(&house.state().levels.contains_key(&Room::Kitchen), eq(false));
+    }
+
+    #[test]
+    fn not_dim_lights_that_only_switch() {
+        let house = single_radio_house();
+        house.dim(Room::Kitchen, 8);
+        let sent = house.radios["433"].lock().unwrap().signals.borrow().is_some();
+        assert_that!(&sent, eq(false));
+    }
+
+    #[test]
+    fn stop_blinds_on_their_way() {
+        let configuration: Configuration = r#"
+            [[radios]]
+            name = "433"
+            pin = 23
+
+            [[devices]]
+            kind = "blinds"
+            room = "BedRoom"
+            protocol = "dooya"
+            radio = "433"
+            travel = "30s"
+        "#.parse().unwrap();
+        let radios = vec![("433".to_string(), InMemoryRadio::new())].into_iter().collect();
+        let house = MyHouse::new(radios, configuration.devices);
+        house.blinds(Room::BedRoom, BlindStatus::DOWN);
+        assert_that!(&house.state().positions[&Room::BedRoom], eq(0));
+        house.stop(Room::BedRoom, 40);
+        let received = house.radios["433"].lock().unwrap().received(dooya::Status::STOP, &DOOYA_PROTOCOL);
+        assert_that!(&received, eq(true));
+        assert_that!(&house.state().positions[&Room::BedRoom], eq(40));
+    }
+
+    #[test]
+    fn go_the_way_of_the_position_for_part_of_the_travel() {
+        let travel = Duration::from_secs(30);
+        assert_that!(&course(0, 40, travel), eq((BlindStatus::UP, Duration::from_secs(12))));
+        assert_that!(&course(100, 40, travel), eq((BlindStatus::DOWN, Duration::from_secs(18))));
+        assert_that!(&course(100, 250, travel), eq((BlindStatus::UP, Duration::from_secs(0))));
+    }
+
+    #[test]
+    fn route_devices_to_their_radio() {
+        let configuration: Configuration = r#"
+            [[radios]]
+            name = "downstairs"
+            pin = 23
+
+            [[radios]]
+            name = "upstairs"
+            pin = 24
+
+            [[devices]]
+            kind = "light"
+            room = "Kitchen"
+            protocol = "dio"
+            address = 0x0042
+            radio = "downstairs"
+
+            [[devices]]
+            kind = "light"
+            room = "BedRoom"
+            protocol = "dio"
+            address = 0x985c
+            radio = "upstairs"
+        "#.parse().unwrap();
+        let radios = vec![
+            ("downstairs".to_string(), InMemoryRadio::new()),
+            ("upstairs".to_string(), InMemoryRadio::new()),
+        ].into_iter().collect();
+        let house = MyHouse::new(radios, configuration.devices);
+        house.light(Room::BedRoom, LightStatus::ON);
+        let upstairs = house.radios["upstairs"].lock().unwrap().received(DioMessage::new(0x985c, dio::Status::ON), &DIO_PROTOCOL);
+        let downstairs = house.radios["downstairs"].lock().unwrap().signals.borrow().is_some();
+        assert_that!(&upstairs, eq(true));
+        assert_that!(&downstairs, eq(false));
+    }
+
+    #[test]
+    fn send_scenes_on_every_radio() {
+        let configuration: Configuration = r#"
+            [[radios]]
+            name = "downstairs"
+            pin = 23
+
+            [[radios]]
+            name = "upstairs"
+            pin = 24
+
+            [[devices]]
+            kind = "blinds"
+            room = "Kitchen"
+            protocol = "dio"
+            address = 0x2600
+            radio = "downstairs"
+
+            [[devices]]
+            kind = "blinds"
+            room = "BedRoom"
+            protocol = "celexon"
+            radio = "upstairs"
+        "#.parse().unwrap();
+        let radios = vec![
+            ("downstairs".to_string(), InMemoryRadio::new()),
+            ("upstairs".to_string(), InMemoryRadio::new()),
+        ].into_iter().collect();
+        let house = MyHouse::new(radios, configuration.devices);
+        house.goodnight();
+        let downstairs = house.radios["downstairs"].lock().unwrap().received(DioMessage::new(0x2600, dio::Status::DOWN), &DIO_PROTOCOL);
+        let upstairs = house.radios["upstairs"].lock().unwrap().received(celexon::Status::DOWN, &celexon::CELEXON_PROTOCOL);
+        assert_that!(&downstairs, eq(true));
+        assert_that!(&upstairs, eq(true));
+    }
+
+    #[test]
+    fn follow_physical_remotes() {
+        let house = single_radio_house();
+        let heard = house.received("dio", &[0x27, 0x13, 0x37, 0x90]);
+        house.received("dio", &[0x27, 0x26, 0x00, 0x90]);
+        house.received("celexon", &[0x53, 0xe1, 0x3e, 0xd4, 0xfe, 0xff, 0xf4, 0xe9]);
+        house.received("dio", &[0x27, 0xca, 0xfe, 0x90]);
+        assert_that!(&heard, eq(Some(vec![Command::Light(Room::LivingRoom, LightStatus::ON)])));
+        let state = house.state();
+        assert_that!(&state.lights, eq(vec![(Room::LivingRoom, LightStatus::ON)].into_iter().collect::<HashMap<_, _>>()));
+        assert_that!(&state.blinds, eq(vec![(Room::Kitchen, BlindStatus::DOWN), (Room::BedRoom, BlindStatus::UP)].into_iter().collect::<HashMap<_, _>>()));
+        assert_that!(&state.screen, eq(None));
+    }
+
+    #[test]
+    fn publish_commands_and_state_changes() {
+        let house = single_radio_house();
+        let events = events::subscribe();
+        house.scene(Scene::Vacation);
+        house.screen(BlindStatus::DOWN);
+        let events = events.try_iter().collect::<Vec<_>>();
+        assert_that!(&events.contains(&HouseEvent::SceneStarted { scene: Scene::Vacation }), eq(true));
+        assert_that!(&events.contains(&HouseEvent::SceneFinished { scene: Scene::Vacation }), eq(true));
+        assert_that!(&events.contains(&HouseEvent::CommandSent { device: "screen".to_string(), command: Command::Screen(BlindStatus::DOWN) }), eq(true));
+        assert_that!(&events.iter().any(|e| match e {
+            HouseEvent::StateChanged { state } => state.vacation && state.screen == Some(BlindStatus::DOWN),
+            _ => false,
+        }), eq(true));
+    }
+
+    #[test]
+    fn ignore_own_frames() {
+        let house = single_radio_house();
+        house.light(Room::Kitchen, LightStatus::ON);
+        let heard = house.received("dio", &[0x27, 0x00, 0x42, 0x90]);
+        assert_that!(&heard, eq(None));
+        assert_that!(&house.received("dio", &[0x27, 0xca, 0xfe, 0x90]), eq(Some(vec![])));
+        assert_that!(&house.state().lights[&Room::Kitchen], eq(LightStatus::ON));
+    }
+
+    #[test]
+    fn transmit_frames_to_no_device_in_particular() {
+        let house = single_radio_house();
+        assert_that!(&house.transmit(None, &DOOYA_PROTOCOL.raw(), &[0x06, 0x41, 0xdf, 0xd1, 0x33]), eq(Ok(())));
+        let received = house.radios["433"].lock().unwrap().received(dooya::Status::DOWN, &DOOYA_PROTOCOL);
+        assert_that!(&received, eq(true));
+        assert_that!(&house.received("dooya", &[0x06, 0x41, 0xdf, 0xd1, 0x33]), eq(None));
+        assert_that!(&house.state(), eq(State::default()));
+    }
+
+    #[test]
+    fn protect_motors_whoever_commands_them() {
+        let house = single_radio_house();
+        house.blinds(Room::LivingRoom, BlindStatus::UP);
+        house.blinds(Room::LivingRoom, BlindStatus::DOWN);
+        let received = house.radios["433"].lock().unwrap().received(DioMessage::new(0x0932, dio::Status::UP), &DIO_PROTOCOL);
+        assert_that!(&received, eq(true));
+        assert_that!(&house.state().blinds[&Room::LivingRoom], eq(BlindStatus::UP));
+        assert_that!(&house.check(Command::Blinds(Room::LivingRoom, BlindStatus::UP)), eq(Err(Refusal::Duplicate)));
+        assert_that!(&house.transmit(None, &DIO_PROTOCOL.raw(), &[0x27, 0x09, 0x32, 0x90]), eq(Err("The motor can't go the other way yet")));
+        assert_that!(&house.transmit(None, &DIO_PROTOCOL.raw(), &[0x27, 0x09, 0x32, 0x80]), eq(Err("The same command was just sent")));
+    }
+
+    #[test]
+    fn refuse_to_transmit_through_unknown_radios() {
+        let house = single_radio_house();
+        assert_that!(&house.transmit(Some("868"), &DIO_PROTOCOL.raw(), &[0x42]), eq(Err("Unknown radio")));
+    }
+
+    #[test]
+    fn know_the_bundled_and_configured_protocols() {
+        let somfy = RadioProtocol::new("somfy", Header(vec![]), Footer(vec![]), Zero(vec![]), One(vec![]), 4);
+        let house = single_radio_house().with_protocols(vec![somfy.clone()]);
+        assert_that!(&house.protocol("celexon"), eq(Some(celexon::CELEXON_PROTOCOL.raw())));
+        assert_that!(&house.protocol("somfy"), eq(Some(somfy)));
+        assert_that!(&house.protocol("rts"), eq(None));
+    }
+
     #[test]
     fn parse_light_status() {
         assert_eq!("On".parse::<LightStatus>().unwrap(), LightStatus::ON);
@@ -313,4 +912,12 @@ mod should {
         }
         assert_eq!("plop".parse::<Room>().is_err(), true);
     }
+
+    #[test]
+    fn parse_scene() {
+        for (string, scene) in &[("cinema", Scene::Cinema), ("GoodMorning", Scene::GoodMorning), ("GOODNIGHT", Scene::GoodNight), ("vacation", Scene::Vacation)] {
+            assert_eq!(string.parse::<Scene>().unwrap(), *scene);
+        }
+        assert!("plop".parse::<Scene>().is_err());
+    }
 }
\ No newline at end of file
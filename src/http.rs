@@ -0,0 +1,244 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerName, StreamOwned};
+
+/// to connect, to send the request and for each read of the answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// what a server answered
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    /// like `HTTP/1.1 204 No Content`
+    pub status: String,
+    pub body: String,
+}
+
+impl Response {
+    pub fn code(&self) -> Option<u16> {
+        self.status.split_whitespace().nth(1)?.parse().ok()
+    }
+}
+
+lazy_static! {
+    /// the certificate authorities of the system, loaded once
+    static ref SYSTEM: Result<Arc<ClientConfig>, String> = system();
+}
+
+/// A server spoken to in http, or in https when its certificate is trusted, one request per connection.
+#[derive(Clone)]
+pub struct Client {
+    /// as written in the url, the port may be left out
+    host: String,
+    /// the paths of the requests are appended to this one
+    path: String,
+    tls: Option<Arc<ClientConfig>>,
+}
+
+impl Client {
+    /// `http://host[:port][/path]` or `https://…` with the certificate, in pem, the server is trusted with,
+    /// like the self-signed one janet writes, or with the certificate authorities of the system
+    pub fn new(url: &str, certificate: Option<&Path>) -> io::Result<Self> {
+        let (rest, tls) = match (url.strip_prefix("http://"), url.strip_prefix("https://"), certificate) {
+            (Some(rest), _, _) => (rest, None),
+            (_, Some(rest), Some(certificate)) => (rest, Some(Arc::new(trusting(certificate)?))),
+            (_, Some(rest), None) => (rest, Some(SYSTEM.clone().map_err(|e| invalid(format!("Give the certificate to trust {} with, {}", url, e)))?)),
+            _ => return Err(invalid(format!("Only http and https are supported, not {}", url))),
+        };
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        Ok(Client { host: host.to_string(), path: path.to_string(), tls })
+    }
+
+    pub fn secure(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    pub fn request(&self, method: &str, path: &str, headers: &[(&str, &str)], content_type: &str, body: &str) -> io::Result<Response> {
+        let path = match (self.path.as_str(), path) {
+            ("", "") => "/".to_string(),
+            (base, "") => base.to_string(),
+            (base, path) => format!("{}{}", base.trim_end_matches('/'), path),
+        };
+        let name = match self.host.rfind(':') {
+            Some(colon) if !self.host[colon..].contains(']') => &self.host[..colon],
+            _ => &self.host,
+        };
+        let address = match (name == self.host, self.secure()) {
+            (true, true) => format!("{}:443", self.host),
+            (true, false) => format!("{}:80", self.host),
+            (false, _) => self.host.clone(),
+        };
+        let address = address.to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Unknown host {}", self.host)))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let headers: String = headers.iter().map(|(name, value)| [name, ": ", value, "\r\n"].concat()).collect();
+        let request = format!("{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                              method, path, self.host, headers, content_type, body.len(), body);
+        match &self.tls {
+            Some(tls) => {
+                let name = ServerName::try_from(name.trim_start_matches('[').trim_end_matches(']')).map_err(|e| invalid(e.to_string()))?;
+                let connection = ClientConnection::new(tls.clone(), name).map_err(|e| invalid(e.to_string()))?;
+                exchange(StreamOwned::new(connection, stream), &request)
+            }
+            None => exchange(stream, &request),
+        }
+    }
+}
+
+/// the servers holding the certificate, and only them
+fn trusting(certificate: &Path) -> io::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    for der in rustls_pemfile::certs(&mut BufReader::new(File::open(certificate)?))? {
+        roots.add(&rustls::Certificate(der)).map_err(|e| invalid(e.to_string()))?;
+    }
+    if roots.is_empty() {
+        return Err(invalid(format!("No certificate in {}", certificate.display())));
+    }
+    Ok(ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth())
+}
+
+/// the servers with a certificate signed by an authority the system trusts
+fn system() -> Result<Arc<ClientConfig>, String> {
+    let mut roots = RootCertStore::empty();
+    let certificates = rustls_native_certs::load_native_certs().map_err(|e| e.to_string())?;
+    let (added, _) = roots.add_parsable_certificates(&certificates.into_iter().map(|c| c.0).collect::<Vec<_>>());
+    if added == 0 {
+        return Err("the system trusts no certificate authority".to_string());
+    }
+    Ok(Arc::new(ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth()))
+}
+
+/// the server closes the connection once it answered, some tls servers without notice
+fn exchange<S>(mut stream: S, request: &str) -> io::Result<Response> where S: Read + Write {
+    stream.write_all(request.as_bytes())?;
+    let mut response = vec![];
+    let mut chunk = [0; 4096];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(length) => response.extend_from_slice(&chunk[..length]),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !response.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+    }
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n").map_or(response.len(), |i| i + 4);
+    let head = String::from_utf8_lossy(&response[..end]);
+    let chunked = head.lines().skip(1)
+        .filter_map(|l| l.split_once(':'))
+        .any(|(name, value)| name.trim().eq_ignore_ascii_case("Transfer-Encoding") && value.to_lowercase().contains("chunked"));
+    let body = if chunked { dechunk(&response[end..])? } else { response[end..].to_vec() };
+    Ok(Response { status: head.lines().next().unwrap_or_default().trim().to_string(), body: String::from_utf8_lossy(&body).to_string() })
+}
+
+/// the body sent in chunks, each one preceded by its length in hexadecimal, until an empty one
+fn dechunk(mut chunks: &[u8]) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line = chunks.windows(2).position(|w| w == b"\r\n").ok_or_else(|| invalid("Truncated chunk".to_string()))?;
+        let size = String::from_utf8_lossy(&chunks[..line]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16)
+            .map_err(|_| invalid(format!("Invalid chunk size {}", size)))?;
+        if size == 0 {
+            return Ok(body);
+        }
+        let data = chunks.get(line + 2..line + 2 + size).ok_or_else(|| invalid("Truncated chunk".to_string()))?;
+        body.extend_from_slice(data);
+        chunks = chunks.get(line + 4 + size..).unwrap_or_default();
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::env;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+    use rustls::{PrivateKey, ServerConfig, ServerConnection};
+    use crate::tls;
+
+    /// the request, read until it ends like expected, once answered
+    fn serve<S>(mut stream: S, end: &[u8], answer: &[u8]) -> String where S: Read + Write {
+        let mut request = vec![];
+        let mut chunk = [0; 1024];
+        while !request.ends_with(end) {
+            let length = stream.read(&mut chunk).unwrap();
+            request.extend_from_slice(&chunk[..length]);
+        }
+        stream.write_all(answer).unwrap();
+        stream.flush().unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[test]
+    fn send_requests_below_the_path_of_the_url() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::new(&format!("http://{}/janet/", listener.local_addr().unwrap()), None).unwrap();
+        let server = thread::spawn(move || serve(listener.accept().unwrap().0, b"{}", b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n[]"));
+        let response = client.request("POST", "/api/timers", &[("Authorization", "Bearer secret")], "application/json", "{}").unwrap();
+        assert_that!(&response, eq(Response { status: "HTTP/1.1 200 OK".to_string(), body: "[]".to_string() }));
+        assert_that!(&response.code(), eq(Some(200)));
+        let request = server.join().unwrap();
+        assert_that!(&request.starts_with("POST /janet/api/timers HTTP/1.1\r\n"), eq(true));
+        assert_that!(&request.contains("\r\nAuthorization: Bearer secret\r\n"), eq(true));
+    }
+
+    #[test]
+    fn speak_https_to_servers_with_the_trusted_certificate() {
+        let (certificate, key) = tls::self_signed(&["localhost".to_string()]).unwrap();
+        let path = env::temp_dir().join(format!("janet-http-{}.crt", std::process::id()));
+        fs::write(&path, &certificate).unwrap();
+        let certificates = rustls_pemfile::certs(&mut certificate.as_bytes()).unwrap().into_iter().map(rustls::Certificate).collect();
+        let key = PrivateKey(rustls_pemfile::pkcs8_private_keys(&mut key.as_bytes()).unwrap().remove(0));
+        let config = Arc::new(ServerConfig::builder().with_safe_defaults().with_no_client_auth().with_single_cert(certificates, key).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let connection = ServerConnection::new(config).unwrap();
+            serve(StreamOwned::new(connection, listener.accept().unwrap().0), b"on", b"HTTP/1.1 204 No Content\r\n\r\n")
+        });
+        let client = Client::new(&format!("https://localhost:{}", port), Some(&path)).unwrap();
+        let response = client.request("POST", "/api/light", &[], "application/x-www-form-urlencoded", "status=on");
+        fs::remove_file(&path).unwrap();
+        assert_that!(&response.unwrap().code(), eq(Some(204)));
+        assert_that!(&server.join().unwrap().starts_with("POST /api/light HTTP/1.1\r\n"), eq(true));
+    }
+
+    #[test]
+    fn read_chunked_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::new(&format!("http://{}", listener.local_addr().unwrap()), None).unwrap();
+        thread::spawn(move || serve(listener.accept().unwrap().0, b"{}",
+                                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"ok\r\n5;last\r\n\": 1}\r\n0\r\n\r\n"));
+        let response = client.request("POST", "/hook", &[], "application/json", "{}").unwrap();
+        assert_that!(&response.body, eq("{\"ok\": 1}".to_string()));
+        assert_that!(&dechunk(b"5\r\nabc").is_err(), eq(true));
+        assert_that!(&dechunk(b"zz\r\n").is_err(), eq(true));
+    }
+
+    #[test]
+    fn only_speak_http_and_https() {
+        assert_that!(&Client::new("ftp://janet", None).is_err(), eq(true));
+        assert_that!(&Client::new("janet:8000", None).is_err(), eq(true));
+    }
+
+    #[test]
+    fn trust_the_system_authorities_without_a_certificate() {
+        let client = Client::new("https://example.com/hook", None);
+        assert_that!(&client.map(|c| c.secure()).map_err(|e| e.to_string()), eq(SYSTEM.clone().map(|_| true).map_err(|e| format!("Give the certificate to trust https://example.com/hook with, {}", e))));
+    }
+}
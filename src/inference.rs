@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::radio::Signal;
+use crate::radio_protocol::{self, *};
+
+/// durations within 20% of each other are considered the same
+const TOLERANCE: f64 = 0.2;
+const MAX_HEADER: usize = 6;
+const MAX_FOOTER: usize = 6;
+
+struct Encoding {
+    header: Vec<Pulse>,
+    zero: Vec<Pulse>,
+    one: Vec<Pulse>,
+    bits: Vec<bool>,
+    footer: Vec<Pulse>,
+}
+
+/// Guesses the protocol of a capture made of repeated frames, each frame being a header, bytes and a footer.
+///
+/// The polarity of bits can't be known from the signal: the symbol with the longest high is one.
+/// Neither can the order of the bits: both are read, and the one of a known protocol decoding the
+/// capture to the same bytes wins, most significant bit first otherwise.
+pub fn infer(name: &str, signals: &[Signal], known: &[RadioProtocol<Vec<u8>>]) -> Result<(RadioProtocol<Vec<u8>>, Vec<u8>), &'static str> {
+    let pulses = quantize(merge(signals));
+    let (frame, repetition) = most_common(split(&pulses)).ok_or("No frame found")?;
+    let encoding = encoding(&frame).ok_or("No bit encoding found")?;
+    let msb_first: Vec<u8> = encoding.bits.chunks(8)
+        .map(|byte| byte.iter().fold(0, |b, &bit| (b << 1) | bit as u8))
+        .collect();
+    let lsb_first: Vec<u8> = msb_first.iter().map(|b| b.reverse_bits()).collect();
+    let decoded = known.iter().flat_map(|p| p.decode(signals)).collect::<Vec<_>>();
+    let (bit_order, payload) = if !decoded.contains(&msb_first) && decoded.contains(&lsb_first) {
+        (BitOrder::LsbFirst, lsb_first)
+    } else {
+        (BitOrder::MsbFirst, msb_first)
+    };
+    let protocol = RadioProtocol::new(name,
+                                      Header(to_signals(&encoding.header)),
+                                      Footer(to_signals(&encoding.footer)),
+                                      Zero(to_signals(&encoding.zero)),
+                                      One(to_signals(&encoding.one)),
+                                      repetition.min(u8::MAX as usize) as u8)
+        .with(bit_order, radio_protocol::Encoding::Plain);
+    Ok((protocol, payload))
+}
+
+fn to_signals(pulses: &[Pulse]) -> Vec<Signal> {
+    pulses.iter().map(|&(high, micros)| {
+        let d = Duration::from_micros(micros);
+        if high { Signal::HIGH(d) } else { Signal::LOW(d) }
+    }).collect()
+}
+
+/// replaces every duration by the mean of the durations close to it at the same level
+fn quantize(pulses: Vec<Pulse>) -> Vec<Pulse> {
+    let mut means = HashMap::new();
+    for level in &[true, false] {
+        let mut durations = pulses.iter().filter(|p| p.0 == *level).map(|p| p.1).collect::<Vec<_>>();
+        durations.sort_unstable();
+        let mut cluster: Vec<u64> = vec![];
+        let mut sum = 0;
+        for d in durations.into_iter().chain(Some(u64::MAX)) {
+            let mean = sum as f64 / cluster.len().max(1) as f64;
+            if !cluster.is_empty() && d as f64 > mean * (1.0 + TOLERANCE) {
+                for c in cluster.drain(..) {
+                    means.insert((*level, c), mean.round() as u64);
+                }
+                sum = 0;
+            }
+            cluster.push(d);
+            sum += d as u128;
+        }
+    }
+    pulses.into_iter().map(|(level, d)| (level, means[&(level, d)])).collect()
+}
+
+/// frames are delimited by the longest pulse, ending them when low and starting them when high
+fn split(pulses: &[Pulse]) -> Vec<Vec<Pulse>> {
+    let extreme = match pulses.iter().max_by_key(|p| p.1) {
+        Some(&extreme) => extreme,
+        None => return vec![],
+    };
+    let mut frames = vec![vec![]];
+    for &pulse in pulses {
+        if pulse == extreme && extreme.0 {
+            frames.push(vec![]);
+        }
+        frames.last_mut().unwrap().push(pulse);
+        if pulse == extreme && !extreme.0 {
+            frames.push(vec![]);
+        }
+    }
+    frames
+}
+
+fn most_common(frames: Vec<Vec<Pulse>>) -> Option<(Vec<Pulse>, usize)> {
+    let mut counts: HashMap<Vec<Pulse>, usize> = HashMap::new();
+    for frame in frames.into_iter().filter(|f| !f.is_empty()) {
+        *counts.entry(frame).or_default() += 1;
+    }
+    counts.into_iter().max_by_key(|(frame, count)| (*count, frame.len()))
+}
+
+/// the smallest header and footer around bits made of two distinct high/low symbols
+fn encoding(frame: &[Pulse]) -> Option<Encoding> {
+    let mut candidates = vec![];
+    for h in 0..=MAX_HEADER {
+        for f in 0..=MAX_FOOTER {
+            if let Some(encoding) = frame.len().checked_sub(h + f).and_then(|_| candidate(frame, h, f)) {
+                candidates.push((h + f, encoding));
+            }
+        }
+    }
+    candidates.into_iter().min_by_key(|(size, _)| *size).map(|(_, encoding)| manchester(encoding))
+}
+
+fn candidate(frame: &[Pulse], h: usize, f: usize) -> Option<Encoding> {
+    let middle = &frame[h..frame.len() - f];
+    if middle.len() % 2 != 0 || middle.len() < 16 || middle.chunks(2).any(|c| !c[0].0) {
+        return None;
+    }
+    let mut symbols = middle.chunks(2).map(|c| c.to_vec()).collect::<Vec<_>>();
+    symbols.sort_by_key(|s| (s[0].1, s[1].1));
+    symbols.dedup();
+    if symbols.len() != 2 {
+        return None;
+    }
+    let (zero, one) = (symbols[0].clone(), symbols[1].clone());
+    let mut bits = middle.chunks(2).map(|c| c == one.as_slice()).collect::<Vec<_>>();
+    let mut footer = frame[frame.len() - f..].to_vec();
+    if bits.len() % 8 == 7 {
+        // the low of the last symbol got merged into a low starting the footer
+        match footer.as_slice() {
+            [(true, high), (false, low), ..] => {
+                let last = [&zero, &one].iter().position(|s| s[0].1 == *high && s[1].1 < *low)?;
+                let rest = low - [&zero, &one][last][1].1;
+                bits.push(last == 1);
+                footer.splice(0..2, Some((false, rest)));
+            }
+            _ => return None,
+        }
+    }
+    if bits.len() % 8 != 0 {
+        return None;
+    }
+    Some(Encoding { header: frame[..h].to_vec(), zero, one, bits, footer })
+}
+
+/// bits always coming as 01 or 10 pairs are one bit made of two symbols
+fn manchester(encoding: Encoding) -> Encoding {
+    let pairs = encoding.bits.chunks(2).collect::<Vec<_>>();
+    if encoding.bits.len() % 16 != 0 || pairs.iter().any(|p| p[0] == p[1]) {
+        return encoding;
+    }
+    Encoding {
+        zero: [encoding.zero.clone(), encoding.one.clone()].concat(),
+        one: [encoding.one, encoding.zero].concat(),
+        bits: pairs.iter().map(|p| p[0]).collect(),
+        ..encoding
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::dio::*;
+    use crate::dooya::*;
+    use crate::celexon::*;
+
+    fn on_air(signals: &[Signal]) -> Vec<Pulse> {
+        merge(signals)
+    }
+
+    #[test]
+    fn infer_dio() {
+        let signals = DIO_PROTOCOL.timings_for(DioMessage::new(0x1337, crate::dio::Status::ON));
+        let (protocol, payload) = infer("captured", &signals, &[]).unwrap();
+        assert_that!(&payload, eq(vec![0x27, 0x13, 0x37, 0x90]));
+        assert_that!(&protocol.timings_for(payload), eq(signals));
+    }
+
+    #[test]
+    fn infer_dooya_with_merged_footer() {
+        let signals = DOOYA_PROTOCOL.timings_for(crate::dooya::Status::DOWN);
+        let (protocol, payload) = infer("captured", &signals, &[]).unwrap();
+        assert_that!(&payload, eq(vec![0x06, 0x41, 0xdf, 0xd1, 0x33]));
+        assert_that!(&on_air(&protocol.timings_for(payload)), eq(on_air(&signals)));
+    }
+
+    #[test]
+    fn infer_celexon_with_inverted_bits() {
+        let signals = CELEXON_PROTOCOL.timings_for(crate::celexon::Status::UP);
+        let (protocol, payload) = infer("captured", &signals, &[]).unwrap();
+        assert_that!(&payload, eq(vec![!0x53, !0xe1, !0x3e, !0xd4, !0xfe, !0xff, !0xf4, !0xe9]));
+        assert_that!(&protocol.timings_for(payload), eq(signals));
+    }
+
+    #[test]
+    fn infer_despite_jitter_and_partial_frames() {
+        let signals = DIO_PROTOCOL.timings_for(DioMessage::new(0x0042, crate::dio::Status::OFF));
+        let noisy = signals[37..].iter().enumerate().map(|(i, s)| {
+            let jitter = |d: Duration| Duration::from_micros(d.as_micros() as u64 * (95 + (i as u64 * 7) % 11) / 100);
+            match *s {
+                Signal::HIGH(d) => Signal::HIGH(jitter(d)),
+                Signal::LOW(d) => Signal::LOW(jitter(d)),
+            }
+        }).collect::<Vec<_>>();
+        let (protocol, payload) = infer("captured", &noisy, &[]).unwrap();
+        assert_that!(&payload, eq(vec![0x27, 0x00, 0x42, 0x80]));
+        assert_that!(&protocol.timings_for(payload).len(), eq(signals.len() / 10 * 9));
+    }
+
+    #[test]
+    fn infer_flipper_captures() {
+        let signals = DIO_PROTOCOL.timings_for(DioMessage::new(0x985c, crate::dio::Status::ON));
+        let timings = signals.iter().map(|s| match s {
+            Signal::HIGH(d) => d.as_micros().to_string(),
+            Signal::LOW(d) => format!("-{}", d.as_micros()),
+        }).collect::<Vec<_>>();
+        let sub = format!("Filetype: Flipper SubGhz RAW File\nVersion: 1\nProtocol: RAW\nRAW_Data: {}\n", timings.join(" "));
+        let captured = crate::capture::flipper::read(sub.as_bytes()).unwrap();
+        let (_, payload) = infer("captured", &captured, &[]).unwrap();
+        assert_that!(&payload, eq(vec![0x27, 0x98, 0x5c, 0x90]));
+    }
+
+    #[test]
+    fn read_bits_in_the_order_of_known_protocols() {
+        let lsb_first = DIO_PROTOCOL.raw().named("lsb").with(BitOrder::LsbFirst, radio_protocol::Encoding::Plain);
+        let signals = lsb_first.timings_for_bytes(&[0x27, 0x13, 0x37, 0x90]);
+        let (protocol, payload) = infer("captured", &signals, &[lsb_first.clone()]).unwrap();
+        assert_that!(&payload, eq(vec![0x27, 0x13, 0x37, 0x90]));
+        assert_that!(&protocol.timings_for(payload), eq(signals.clone()));
+        let (_, payload) = infer("captured", &signals, &[DIO_PROTOCOL.raw()]).unwrap();
+        assert_that!(&payload, eq(vec![0xe4, 0xc8, 0xec, 0x09]));
+        let (_, payload) = infer("captured", &signals, &[]).unwrap();
+        assert_that!(&payload, eq(vec![0xe4, 0xc8, 0xec, 0x09]));
+    }
+
+    #[test]
+    fn reject_noise() {
+        let noise = (1..40).map(|i| if i % 2 == 0 { Signal::HIGH(Duration::from_micros(i * 97 % 1000)) } else { Signal::LOW(Duration::from_micros(i * 89 % 1000)) }).collect::<Vec<_>>();
+        assert_that!(&infer("noise", &noise, &[]).is_err(), eq(true));
+    }
+}
@@ -7,10 +7,37 @@ extern crate sysfs_gpio;
 #[macro_use]
 extern crate galvanic_assert;
 
+pub mod hex;
+pub mod clock;
 pub mod pin;
 pub mod radio_protocol;
 pub mod radio;
+pub mod serial;
+pub mod record;
+pub mod capture;
+pub mod inference;
 pub mod dio;
 pub mod dooya;
 pub mod celexon;
-pub mod house;
\ No newline at end of file
+pub mod ev1527;
+pub mod config;
+pub mod house;
+pub mod receiver;
+pub mod http;
+pub mod trigger;
+pub mod transmission;
+pub mod weather;
+pub mod sensor;
+pub mod sun;
+pub mod schedule;
+pub mod vacation;
+pub mod timer;
+pub mod rule;
+pub mod events;
+pub mod page;
+pub mod auth;
+pub mod tls;
+pub mod limit;
+pub mod mqtt;
+pub mod hermes;
+pub mod cli;
\ No newline at end of file
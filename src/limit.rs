@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::config::Limits;
+use crate::house::{BlindStatus, Command, Room};
+
+/// why a motor command isn't sent
+#[derive(Clone, Debug, PartialEq)]
+pub enum Refusal {
+    /// the motor went the other way too recently
+    Reversal { wait: Duration },
+    /// the same command was just sent
+    Duplicate,
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Refusal::Reversal { wait } => write!(f, "the motor can't go the other way for {}ms", wait.as_millis()),
+            Refusal::Duplicate => write!(f, "the same command was just sent"),
+        }
+    }
+}
+
+/// Keeps blinds and screen motors from flapping: a motor isn't reversed before some time,
+/// a command repeated within a short window is only sent once.
+pub struct Motors {
+    reversal: Duration,
+    coalesce: Duration,
+    /// last command sent to each motor and when
+    last: Mutex<HashMap<Option<Room>, (BlindStatus, u64)>>,
+}
+
+impl Motors {
+    pub fn new(limits: &Limits) -> Self {
+        Motors { reversal: limits.reversal.0, coalesce: limits.coalesce.0, last: Mutex::new(HashMap::new()) }
+    }
+
+    /// whether the command can be sent now, the ones which can are remembered
+    pub fn check(&self, command: Command, now: u64) -> Result<(), Refusal> {
+        self.decide(command, now, true)
+    }
+
+    /// whether the command can be sent now, without remembering it
+    pub fn allowed(&self, command: Command, now: u64) -> Result<(), Refusal> {
+        self.decide(command, now, false)
+    }
+
+    /// under a single lock, so concurrent commands can't both pass
+    fn decide(&self, command: Command, now: u64, remember: bool) -> Result<(), Refusal> {
+        let (motor, status) = match motor(command) {
+            Some(motor) => motor,
+            None => return Ok(()),
+        };
+        let mut last = self.last.lock().expect("Can't get lock on motors");
+        if let Some(&(previous, at)) = last.get(&motor) {
+            let elapsed = Duration::from_millis(now.saturating_sub(at));
+            if previous == status && elapsed < self.coalesce {
+                return Err(Refusal::Duplicate);
+            }
+            if previous != status && elapsed < self.reversal {
+                return Err(Refusal::Reversal { wait: self.reversal - elapsed });
+            }
+        }
+        if remember {
+            last.insert(motor, (status, now));
+        }
+        Ok(())
+    }
+}
+
+/// the motor a command moves, blinds of a room or the screen, and which way
+fn motor(command: Command) -> Option<(Option<Room>, BlindStatus)> {
+    match command {
+        Command::Blinds(room, status) => Some((Some(room), status)),
+        Command::Screen(status) => Some((None, status)),
+        Command::Light(_, _) => None,
+    }
+}
+
+/// A bucket of requests per client, refilled over a minute.
+pub struct RateLimit {
+    per_minute: u32,
+    proxy: Option<IpAddr>,
+    /// requests left and when they were counted
+    buckets: Mutex<HashMap<IpAddr, (f64, u64)>>,
+}
+
+impl RateLimit {
+    pub fn new(limits: &Limits) -> Self {
+        RateLimit { per_minute: limits.requests, proxy: limits.proxy, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// who sent a request, the connection unless it comes from the configured proxy which tells the client
+    pub fn client(&self, remote: Option<IpAddr>, real: Option<IpAddr>) -> Option<IpAddr> {
+        match (remote, real) {
+            (Some(remote), Some(real)) if Some(remote) == self.proxy => Some(real),
+            _ => remote,
+        }
+    }
+
+    /// takes a request from the bucket of the client when there is one left
+    pub fn allow(&self, client: IpAddr, now: u64) -> bool {
+        let capacity = self.per_minute as f64;
+        let refill = |(left, at): (f64, u64)| (capacity.min(left + now.saturating_sub(at) as f64 * capacity / 60_000.0), now);
+        let mut buckets = self.buckets.lock().expect("Can't get lock on rate limits");
+        buckets.retain(|_, bucket| refill(*bucket).0 < capacity);
+        let (left, _) = buckets.get(&client).map_or((capacity, now), |&bucket| refill(bucket));
+        if left < 1.0 {
+            return false;
+        }
+        buckets.insert(client, (left - 1.0, now));
+        true
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    fn limits() -> Limits {
+        Limits { reversal: "2s".parse().unwrap(), coalesce: "1s".parse().unwrap(), requests: 2, proxy: Some("10.0.0.1".parse().unwrap()) }
+    }
+
+    fn blinds(status: BlindStatus) -> Command {
+        Command::Blinds(Room::LivingRoom, status)
+    }
+
+    #[test]
+    fn wait_before_reversing_motors() {
+        let motors = Motors::new(&limits());
+        assert_that!(&motors.check(blinds(BlindStatus::UP), 0), eq(Ok(())));
+        assert_that!(&motors.check(blinds(BlindStatus::DOWN), 500), eq(Err(Refusal::Reversal { wait: Duration::from_millis(1500) })));
+        assert_that!(&motors.check(Command::Blinds(Room::BedRoom, BlindStatus::DOWN), 500), eq(Ok(())));
+        assert_that!(&motors.check(Command::Screen(BlindStatus::DOWN), 500), eq(Ok(())));
+        assert_that!(&motors.check(blinds(BlindStatus::DOWN), 2000), eq(Ok(())));
+    }
+
+    #[test]
+    fn send_repeated_commands_once() {
+        let motors = Motors::new(&limits());
+        assert_that!(&motors.check(blinds(BlindStatus::UP), 0), eq(Ok(())));
+        assert_that!(&motors.check(blinds(BlindStatus::UP), 999), eq(Err(Refusal::Duplicate)));
+        assert_that!(&motors.check(blinds(BlindStatus::UP), 1000), eq(Ok(())));
+        let light = Command::Light(Room::LivingRoom, crate::house::LightStatus::ON);
+        assert_that!(&motors.check(light, 1000), eq(Ok(())));
+        assert_that!(&motors.check(light, 1000), eq(Ok(())));
+    }
+
+    #[test]
+    fn tell_whether_commands_could_be_sent_without_sending_them() {
+        let motors = Motors::new(&limits());
+        assert_that!(&motors.allowed(blinds(BlindStatus::UP), 0), eq(Ok(())));
+        assert_that!(&motors.check(blinds(BlindStatus::DOWN), 0), eq(Ok(())));
+        assert_that!(&motors.allowed(blinds(BlindStatus::UP), 500), eq(Err(Refusal::Reversal { wait: Duration::from_millis(1500) })));
+    }
+
+    #[test]
+    fn let_only_one_of_concurrent_opposite_commands_through() {
+        let motors = std::sync::Arc::new(Motors::new(&limits()));
+        let threads = (0..8).map(|i| {
+            let motors = motors.clone();
+            let status = if i % 2 == 0 { BlindStatus::UP } else { BlindStatus::DOWN };
+            std::thread::spawn(move || motors.check(blinds(status), 0).is_ok())
+        }).collect::<Vec<_>>();
+        let passed = threads.into_iter().map(|t| t.join().unwrap()).filter(|&ok| ok).count();
+        assert_that!(&passed, eq(1));
+    }
+
+    #[test]
+    fn only_trust_the_proxy_to_tell_the_client() {
+        let limit = RateLimit::new(&limits());
+        let (proxy, phone, forged) = ("10.0.0.1".parse().unwrap(), "192.168.1.10".parse().unwrap(), "192.168.1.66".parse().unwrap());
+        assert_that!(&limit.client(Some(proxy), Some(phone)), eq(Some(phone)));
+        assert_that!(&limit.client(Some(phone), Some(forged)), eq(Some(phone)));
+        assert_that!(&limit.client(Some(phone), None), eq(Some(phone)));
+    }
+
+    #[test]
+    fn limit_requests_per_client() {
+        let limit = RateLimit::new(&limits());
+        let (phone, laptop) = ("192.168.1.10".parse().unwrap(), "192.168.1.11".parse().unwrap());
+        assert_that!(&limit.allow(phone, 0), eq(true));
+        assert_that!(&limit.allow(phone, 0), eq(true));
+        assert_that!(&limit.allow(phone, 0), eq(false));
+        assert_that!(&limit.allow(laptop, 0), eq(true));
+        assert_that!(&limit.allow(phone, 29_999), eq(false));
+        assert_that!(&limit.allow(phone, 30_000), eq(true));
+    }
+}
@@ -0,0 +1,530 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Mutex;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use crate::auth::Access;
+use crate::config::{Action, Broker, Device, Kind};
+use crate::events::{self, HouseEvent};
+use crate::house::{BlindStatus, House, LightStatus, Room, Scene, State};
+use crate::page::words;
+use crate::limit::Refusal;
+use crate::trigger;
+
+/// the broker drops janet when it hears nothing from it for this many seconds
+const KEEP_ALIVE: u16 = 60;
+/// before connecting again to the broker
+const RETRY: Duration = Duration::from_secs(10);
+
+/// The mqtt 3.1.1 packets janet uses, everything is sent at most once.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    /// the will is published, retained, when the connection is lost
+    Connect { client_id: String, keep_alive: u16, will: Option<(String, Vec<u8>)>, username: Option<String>, password: Option<String> },
+    ConnAck { code: u8 },
+    Publish { topic: String, payload: Vec<u8>, retain: bool },
+    Subscribe { id: u16, filters: Vec<String> },
+    SubAck { id: u16 },
+    PingReq,
+    PingResp,
+    Disconnect,
+    /// any other packet type, ignored
+    Other(u8),
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = vec![];
+        let header = match self {
+            Packet::Connect { client_id, keep_alive, will, username, password } => {
+                string(&mut body, b"MQTT");
+                body.push(4);
+                let mut flags = 0x02;
+                if will.is_some() {
+                    flags |= 0x24;
+                }
+                if username.is_some() {
+                    flags |= 0x80;
+                }
+                if password.is_some() {
+                    flags |= 0x40;
+                }
+                body.push(flags);
+                body.extend_from_slice(&keep_alive.to_be_bytes());
+                string(&mut body, client_id.as_bytes());
+                if let Some((topic, message)) = will {
+                    string(&mut body, topic.as_bytes());
+                    string(&mut body, message);
+                }
+                for field in username.iter().chain(password.iter()) {
+                    string(&mut body, field.as_bytes());
+                }
+                0x10
+            }
+            Packet::ConnAck { code } => {
+                body.extend_from_slice(&[0, *code]);
+                0x20
+            }
+            Packet::Publish { topic, payload, retain } => {
+                string(&mut body, topic.as_bytes());
+                body.extend_from_slice(payload);
+                if *retain { 0x31 } else { 0x30 }
+            }
+            Packet::Subscribe { id, filters } => {
+                body.extend_from_slice(&id.to_be_bytes());
+                for filter in filters {
+                    string(&mut body, filter.as_bytes());
+                    body.push(0);
+                }
+                0x82
+            }
+            Packet::SubAck { id } => {
+                body.extend_from_slice(&id.to_be_bytes());
+                body.push(0);
+                0x90
+            }
+            Packet::PingReq => 0xc0,
+            Packet::PingResp => 0xd0,
+            Packet::Disconnect => 0xe0,
+            Packet::Other(kind) => kind << 4,
+        };
+        let mut packet = vec![header];
+        let mut length = body.len();
+        loop {
+            let byte = (length % 128) as u8;
+            length /= 128;
+            packet.push(if length > 0 { byte | 0x80 } else { byte });
+            if length == 0 {
+                break;
+            }
+        }
+        packet.append(&mut body);
+        packet
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut byte = [0; 1];
+        reader.read_exact(&mut byte)?;
+        let header = byte[0];
+        let mut length = 0;
+        for shift in (0..28).step_by(7) {
+            reader.read_exact(&mut byte)?;
+            length |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body)?;
+                return parse(header, &body).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed mqtt packet"));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "Mqtt packet too long"))
+    }
+}
+
+fn string(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+fn parse(header: u8, body: &[u8]) -> Option<Packet> {
+    let id = || body.get(..2).map(|id| u16::from_be_bytes([id[0], id[1]]));
+    match header >> 4 {
+        0x2 => Some(Packet::ConnAck { code: *body.get(1)? }),
+        0x3 => {
+            let length = id()? as usize;
+            let topic = String::from_utf8(body.get(2..2 + length)?.to_vec()).ok()?;
+            // a packet id follows the topic when the quality of service isn't 0
+            let start = if header & 0x06 == 0 { 2 + length } else { 4 + length };
+            Some(Packet::Publish { topic, payload: body.get(start..)?.to_vec(), retain: header & 0x01 == 1 })
+        }
+        0x9 => Some(Packet::SubAck { id: id()? }),
+        0xc => Some(Packet::PingReq),
+        0xd => Some(Packet::PingResp),
+        0xe => Some(Packet::Disconnect),
+        kind => Some(Packet::Other(kind)),
+    }
+}
+
+/// A connection to a broker, packets are read by one thread and written by any.
+pub struct Client {
+    stream: TcpStream,
+    writer: Mutex<TcpStream>,
+}
+
+impl Client {
+    pub fn connect(broker: &Broker, will: Option<(String, Vec<u8>)>) -> io::Result<Self> {
+        let stream = TcpStream::connect((broker.host.as_str(), broker.port))?;
+        let client = Client { writer: Mutex::new(stream.try_clone()?), stream };
+        client.send(&Packet::Connect {
+            client_id: broker.client_id.clone(),
+            keep_alive: KEEP_ALIVE,
+            will,
+            username: broker.username.clone(),
+            password: broker.password.clone(),
+        })?;
+        match client.receive()? {
+            Packet::ConnAck { code: 0 } => Ok(client),
+            Packet::ConnAck { code } => Err(io::Error::new(io::ErrorKind::ConnectionRefused, format!("Broker refused the connection with code {}", code))),
+            packet => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected {:?}", packet))),
+        }
+    }
+
+    pub fn send(&self, packet: &Packet) -> io::Result<()> {
+        self.writer.lock().expect("Can't get lock on mqtt connection").write_all(&packet.encode())
+    }
+
+    pub fn publish(&self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        self.send(&Packet::Publish { topic: topic.to_string(), payload: payload.to_vec(), retain })
+    }
+
+    pub fn subscribe(&self, filters: Vec<String>) -> io::Result<()> {
+        self.send(&Packet::Subscribe { id: 1, filters })
+    }
+
+    pub fn receive(&self) -> io::Result<Packet> {
+        Packet::read(&mut &self.stream)
+    }
+
+    /// pings the broker while nothing else is sent, until the connection is closed
+    pub fn keep_alive(&self) {
+        while self.send(&Packet::PingReq).is_ok() {
+            thread::sleep(Duration::from_secs(KEEP_ALIVE as u64 / 2));
+        }
+    }
+
+    /// ends both reading and writing
+    pub fn close(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// What janet publishes and obeys: the state of each device under `<topic>/<kind>[/<room>]`,
+/// commands on the same topics followed by `/set`, and home assistant discovery messages.
+pub struct Bridge {
+    topic: String,
+    discovery: String,
+    devices: Vec<(Kind, Option<Room>)>,
+    access: Access,
+}
+
+impl Bridge {
+    pub fn new(broker: &Broker, devices: &[Device]) -> Self {
+        let mut unique = vec![];
+        for device in devices {
+            if !unique.contains(&(device.kind, device.room)) {
+                unique.push((device.kind, device.room));
+            }
+        }
+        let access = Access::new(&format!("mqtt {}", broker.host), broker.permissions.clone());
+        Bridge { topic: broker.topic.clone(), discovery: broker.discovery.clone(), devices: unique, access }
+    }
+
+    /// `online` while janet is connected, `offline` once it isn't
+    pub fn availability(&self) -> String {
+        format!("{}/status", self.topic)
+    }
+
+    pub fn subscriptions(&self) -> Vec<String> {
+        vec![format!("{}/+/set", self.topic), format!("{}/+/+/set", self.topic)]
+    }
+
+    fn device_topic(&self, kind: Kind, room: Option<Room>) -> String {
+        let kind = format!("{:?}", kind).to_lowercase();
+        match room {
+            Some(room) => format!("{}/{}/{:?}", self.topic, kind, room),
+            None => format!("{}/{}", self.topic, kind),
+        }
+    }
+
+    fn scene_topic(&self, scene: Scene) -> String {
+        format!("{}/scene/{}", self.topic, format!("{:?}", scene).to_lowercase())
+    }
+
+    /// retained configuration messages making home assistant show lights, covers and scenes
+    pub fn discovery(&self) -> Vec<(String, String)> {
+        let device = json!({ "identifiers": [self.topic], "name": "Janet" });
+        let mut messages = vec![];
+        for &(kind, room) in &self.devices {
+            let topic = self.device_topic(kind, room);
+            let id = topic.replace('/', "_").to_lowercase();
+            let name = room.map_or(words(kind), |room| format!("{} {}", words(room), format!("{:?}", kind).to_lowercase()));
+            let mut config = json!({
+                "name": name,
+                "unique_id": id,
+                "state_topic": topic,
+                "command_topic": format!("{}/set", topic),
+                "availability_topic": self.availability(),
+                "device": device,
+            });
+            let component = match kind {
+                Kind::Light => {
+                    config["payload_on"] = json!("ON");
+                    config["payload_off"] = json!("OFF");
+                    "light"
+                }
+                Kind::Blinds | Kind::Screen => {
+                    config["device_class"] = json!(if kind == Kind::Blinds { "blind" } else { "shade" });
+                    config["payload_open"] = json!("OPEN");
+                    config["payload_close"] = json!("CLOSE");
+                    config["payload_stop"] = json!(null);
+                    config["state_open"] = json!("open");
+                    config["state_closed"] = json!("closed");
+                    "cover"
+                }
+            };
+            messages.push((format!("{}/{}/{}/config", self.discovery, component, id), config.to_string()));
+        }
+        for &scene in Scene::ALL.iter() {
+            let topic = self.scene_topic(scene);
+            let id = topic.replace('/', "_").to_lowercase();
+            let config = json!({
+                "name": words(scene),
+                "unique_id": id,
+                "command_topic": format!("{}/set", topic),
+                "payload_on": "ON",
+                "availability_topic": self.availability(),
+                "device": device,
+            });
+            messages.push((format!("{}/scene/{}/config", self.discovery, id), config.to_string()));
+        }
+        messages
+    }
+
+    /// the retained state messages of the known devices
+    pub fn states(&self, state: &State) -> Vec<(String, String)> {
+        self.devices.iter().filter_map(|&(kind, room)| {
+            let payload = match (kind, room) {
+                (Kind::Light, Some(room)) => state.lights.get(&room).map(|s| if *s == LightStatus::ON { "ON" } else { "OFF" }),
+                (Kind::Blinds, Some(room)) => state.blinds.get(&room).map(cover),
+                (Kind::Screen, _) => state.screen.as_ref().map(cover),
+                _ => None,
+            };
+            payload.map(|payload| (self.device_topic(kind, room), payload.to_string()))
+        }).collect()
+    }
+
+    /// the action asked by a message on a command topic
+    pub fn command(&self, topic: &str, payload: &[u8]) -> Option<Action> {
+        let topic = topic.strip_prefix(&self.topic)?.strip_prefix('/')?.strip_suffix("/set")?;
+        let payload = std::str::from_utf8(payload).ok()?;
+        let (kind, room) = match topic.split_once('/') {
+            Some(("scene", scene)) => return if payload == "ON" { scene.parse().ok().map(|scene| Action::Scene { scene }) } else { None },
+            Some((kind, room)) => (kind, Some(room.parse().ok()?)),
+            None => (topic, None),
+        };
+        let device = self.devices.iter().find(|(k, r)| format!("{:?}", k).to_lowercase() == kind && *r == room)?;
+        let direction = || match payload {
+            "OPEN" => Some(BlindStatus::UP),
+            "CLOSE" => Some(BlindStatus::DOWN),
+            _ => None,
+        };
+        match *device {
+            (Kind::Light, Some(light)) => payload.parse().ok().map(|status| Action::Light { light, status }),
+            (Kind::Blinds, Some(blinds)) => direction().map(|status| Action::Blinds { blinds, status }),
+            (Kind::Screen, _) => direction().map(|screen| Action::Screen { screen }),
+            _ => None,
+        }
+    }
+
+    /// whether the clients of the broker may run the action
+    pub fn permits(&self, action: &Action) -> bool {
+        self.access.action(action)
+    }
+}
+
+fn cover(status: &BlindStatus) -> &'static str {
+    match status {
+        BlindStatus::UP => "open",
+        BlindStatus::DOWN => "closed",
+    }
+}
+
+/// keeps janet connected to the broker, publishing the state of the house and obeying commands
+pub fn run<H>(house: &H, bridge: &Bridge, broker: &Broker) where H: House + Sync + ?Sized {
+    loop {
+        if let Err(e) = connect(house, bridge, broker) {
+            events::error(format!("Lost connection to the mqtt broker {}: {}", broker.host, e));
+        }
+        thread::sleep(RETRY);
+    }
+}
+
+/// one connection to the broker, until it fails
+pub fn connect<H>(house: &H, bridge: &Bridge, broker: &Broker) -> io::Result<()> where H: House + Sync + ?Sized {
+    let events = events::subscribe();
+    let client = Client::connect(broker, Some((bridge.availability(), b"offline".to_vec())))?;
+    client.subscribe(bridge.subscriptions())?;
+    for (topic, payload) in bridge.discovery() {
+        client.publish(&topic, payload.as_bytes(), true)?;
+    }
+    client.publish(&bridge.availability(), b"online", true)?;
+    let mut published = bridge.states(&house.state());
+    for (topic, payload) in &published {
+        client.publish(topic, payload.as_bytes(), true)?;
+    }
+    thread::scope(|scope| {
+        let commands = scope.spawn(|| {
+            let result = obey(house, bridge, &client);
+            client.close();
+            result
+        });
+        // the broker is pinged when nothing was sent for a while, however many other events there are
+        let ping = Duration::from_secs(KEEP_ALIVE as u64 / 2);
+        let mut sent = Instant::now();
+        let result = loop {
+            match events.recv_timeout(ping.saturating_sub(sent.elapsed())) {
+                Ok(HouseEvent::StateChanged { state }) => {
+                    let states = bridge.states(&state);
+                    let changed = states.iter().filter(|s| !published.contains(s)).collect::<Vec<_>>();
+                    if let Err(e) = changed.iter().try_for_each(|(topic, payload)| client.publish(topic, payload.as_bytes(), true)) {
+                        break Err(e);
+                    }
+                    if !changed.is_empty() {
+                        sent = Instant::now();
+                    }
+                    published = states;
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break Ok(()),
+            }
+            if sent.elapsed() >= ping {
+                if let Err(e) = client.send(&Packet::PingReq) {
+                    break Err(e);
+                }
+                sent = Instant::now();
+            }
+        };
+        client.close();
+        let commands = commands.join().expect("Mqtt commands thread panicked");
+        result.and(commands)
+    })
+}
+
+/// runs the permitted commands the house accepts, the state is published again
+/// when a motor refuses so that home assistant doesn't show a position it never reached
+fn obey<H>(house: &H, bridge: &Bridge, client: &Client) -> io::Result<()> where H: House + ?Sized {
+    loop {
+        if let Packet::Publish { topic, payload, .. } = client.receive()? {
+            match bridge.command(&topic, &payload) {
+                Some(action) if !bridge.permits(&action) => events::error(format!("{:?} isn't allowed through mqtt", action)),
+                Some(action) => match action.command().map_or(Ok(()), |command| house.check(command)) {
+                    Ok(()) => {
+                        if let Err(e) = trigger::run(house, &action, &json!({ "topic": topic })) {
+                            events::error(format!("Can't run {:?}: {}", action, e));
+                        }
+                    }
+                    Err(Refusal::Duplicate) => {}
+                    Err(refusal) => {
+                        events::error(format!("{:?} refused, {}", action, refusal));
+                        for (topic, payload) in bridge.states(&house.state()) {
+                            client.publish(&topic, payload.as_bytes(), true)?;
+                        }
+                    }
+                },
+                None => events::error(format!("Unknown mqtt command {} {}", topic, String::from_utf8_lossy(&payload))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Code;
+
+    fn bridge() -> Bridge {
+        let device = |kind, room, code| Device { kind, room, code, radio: "upstairs".to_string(), dimmer: false, travel: None };
+        let devices = vec![
+            device(Kind::Light, Some(Room::LivingRoom), Code::Dio { address: 1 }),
+            device(Kind::Light, Some(Room::LivingRoom), Code::Dio { address: 2 }),
+            device(Kind::Blinds, Some(Room::BedRoom), Code::Dooya),
+            device(Kind::Screen, None, Code::Celexon),
+        ];
+        let broker: Broker = toml::from_str(r#"host = "localhost""#).unwrap();
+        Bridge::new(&broker, &devices)
+    }
+
+    #[test]
+    fn encode_packets() {
+        let publish = Packet::Publish { topic: "a/b".to_string(), payload: b"ON".to_vec(), retain: true };
+        assert_that!(&publish.encode(), eq(vec![0x31, 7, 0, 3, b'a', b'/', b'b', b'O', b'N']));
+        let subscribe = Packet::Subscribe { id: 1, filters: vec!["a/#".to_string()] };
+        assert_that!(&subscribe.encode(), eq(vec![0x82, 8, 0, 1, 0, 3, b'a', b'/', b'#', 0]));
+        let connect = Packet::Connect { client_id: "j".to_string(), keep_alive: 60, will: None, username: None, password: None };
+        assert_that!(&connect.encode(), eq(vec![0x10, 13, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 60, 0, 1, b'j']));
+        assert_that!(&Packet::PingReq.encode(), eq(vec![0xc0, 0]));
+    }
+
+    #[test]
+    fn read_packets_back() {
+        let payload = vec![b'x'; 200];
+        let publish = Packet::Publish { topic: "janet/light/LivingRoom/set".to_string(), payload, retain: false };
+        let bytes = publish.encode();
+        assert_that!(&bytes[1..3].to_vec(), eq(vec![0xe4, 0x01]));
+        assert_that!(&Packet::read(&mut &bytes[..]).unwrap(), eq(publish));
+        assert_that!(&Packet::read(&mut &[0x20, 2, 0, 5][..]).unwrap(), eq(Packet::ConnAck { code: 5 }));
+        let with_id = [0x32, 8, 0, 3, b'a', b'/', b'b', 0, 7, b'1'];
+        assert_that!(&Packet::read(&mut &with_id[..]).unwrap(), eq(Packet::Publish { topic: "a/b".to_string(), payload: b"1".to_vec(), retain: false }));
+        assert_that!(&Packet::read(&mut &[0x30, 5, 0, 9][..]).is_err(), eq(true));
+    }
+
+    #[test]
+    fn obey_commands_of_known_devices() {
+        let bridge = bridge();
+        assert_that!(&bridge.command("janet/light/LivingRoom/set", b"ON"), eq(Some(Action::Light { light: Room::LivingRoom, status: LightStatus::ON })));
+        assert_that!(&bridge.command("janet/blinds/BedRoom/set", b"CLOSE"), eq(Some(Action::Blinds { blinds: Room::BedRoom, status: BlindStatus::DOWN })));
+        assert_that!(&bridge.command("janet/screen/set", b"OPEN"), eq(Some(Action::Screen { screen: BlindStatus::UP })));
+        assert_that!(&bridge.command("janet/scene/cinema/set", b"ON"), eq(Some(Action::Scene { scene: Scene::Cinema })));
+        assert_that!(&bridge.command("janet/light/Kitchen/set", b"ON"), eq(None));
+        assert_that!(&bridge.command("janet/blinds/BedRoom/set", b"STOP"), eq(None));
+        assert_that!(&bridge.command("janet/light/LivingRoom", b"ON"), eq(None));
+    }
+
+    #[test]
+    fn obey_only_permitted_commands() {
+        let broker: Broker = toml::from_str(r#"
+            host = "localhost"
+            permissions = ["light LivingRoom", "screen"]
+        "#).unwrap();
+        let bridge = Bridge::new(&broker, &[]);
+        assert_that!(&bridge.permits(&Action::Light { light: Room::LivingRoom, status: LightStatus::ON }), eq(true));
+        assert_that!(&bridge.permits(&Action::Screen { screen: BlindStatus::UP }), eq(true));
+        assert_that!(&bridge.permits(&Action::Blinds { blinds: Room::BedRoom, status: BlindStatus::DOWN }), eq(false));
+        assert_that!(&bridge.permits(&Action::Scene { scene: Scene::Cinema }), eq(false));
+        assert_that!(&self::bridge().permits(&Action::Scene { scene: Scene::Cinema }), eq(true));
+    }
+
+    #[test]
+    fn publish_known_states() {
+        let bridge = bridge();
+        let mut state = State::default();
+        state.lights.insert(Room::LivingRoom, LightStatus::OFF);
+        state.screen = Some(BlindStatus::DOWN);
+        assert_that!(&bridge.states(&state), eq(vec![
+            ("janet/light/LivingRoom".to_string(), "OFF".to_string()),
+            ("janet/screen".to_string(), "closed".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn describe_devices_to_home_assistant() {
+        let discovery = bridge().discovery();
+        let topics: Vec<&str> = discovery.iter().map(|(topic, _)| topic.as_str()).collect();
+        assert_that!(&topics, eq(vec![
+            "homeassistant/light/janet_light_livingroom/config",
+            "homeassistant/cover/janet_blinds_bedroom/config",
+            "homeassistant/cover/janet_screen/config",
+            "homeassistant/scene/janet_scene_goodmorning/config",
+            "homeassistant/scene/janet_scene_goodnight/config",
+            "homeassistant/scene/janet_scene_cinema/config",
+            "homeassistant/scene/janet_scene_vacation/config",
+        ]));
+        let blinds: serde_json::Value = serde_json::from_str(&discovery[1].1).unwrap();
+        assert_that!(&blinds["name"], eq(json!("Bed room blinds")));
+        assert_that!(&blinds["command_topic"], eq(json!("janet/blinds/BedRoom/set")));
+        assert_that!(&blinds["device_class"], eq(json!("blind")));
+        assert_that!(&blinds["availability_topic"], eq(json!("janet/status")));
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::config::{Device, Kind};
+use crate::house::{BlindStatus, LightStatus, Room, Scene, State};
+
+/// what the web page shows, every configured device grouped by room with its known state
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Page {
+    pub scenes: Vec<SceneView>,
+    pub rooms: Vec<RoomView>,
+    pub vacation: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SceneView {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RoomView {
+    pub name: String,
+    pub devices: Vec<DeviceView>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DeviceView {
+    pub name: String,
+    /// where the form posts
+    pub endpoint: String,
+    pub room: Option<String>,
+    pub choices: Vec<String>,
+    /// the current choice, none when unknown
+    pub state: Option<String>,
+    /// a slider for dimmers and blinds stopping on the way
+    pub range: Option<Range>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Range {
+    /// where the slider posts
+    pub endpoint: String,
+    /// the field it posts
+    pub name: String,
+    pub max: u8,
+    /// none when unknown
+    pub value: Option<u8>,
+}
+
+impl Page {
+    pub fn new(devices: &[Device], state: &State) -> Self {
+        let mut rooms: Vec<(Option<Room>, RoomView)> = vec![];
+        for device in devices {
+            let view = device_view(device, state);
+            match rooms.iter_mut().find(|(room, _)| *room == device.room) {
+                Some((_, room)) => {
+                    if !room.devices.contains(&view) {
+                        room.devices.push(view);
+                    }
+                }
+                None => rooms.push((device.room, RoomView { name: device.room.map_or("House".to_string(), words), devices: vec![view] })),
+            }
+        }
+        Page {
+            scenes: Scene::ALL.iter().map(|&scene| SceneView { name: words(scene), value: format!("{:?}", scene).to_lowercase() }).collect(),
+            rooms: rooms.into_iter().map(|(_, room)| room).collect(),
+            vacation: state.vacation,
+        }
+    }
+}
+
+fn device_view(device: &Device, state: &State) -> DeviceView {
+    let up_down = || vec!["Up".to_string(), "Down".to_string()];
+    let (name, endpoint, choices, current) = match device.kind {
+        Kind::Light => ("Lights", "/api/light", vec!["On".to_string(), "Off".to_string()],
+                        device.room.and_then(|r| state.lights.get(&r)).map(|s| match s {
+                            LightStatus::ON => "On",
+                            LightStatus::OFF => "Off",
+                        })),
+        Kind::Blinds => ("Blinds", "/api/blinds", up_down(), device.room.and_then(|r| state.blinds.get(&r)).map(position)),
+        Kind::Screen => ("Screen", "/api/screen", up_down(), state.screen.as_ref().map(position)),
+    };
+    let range = |endpoint: &str, name: &str, max, values: &HashMap<Room, u8>| Some(Range {
+        endpoint: endpoint.to_string(),
+        name: name.to_string(),
+        max,
+        value: device.room.and_then(|r| values.get(&r)).cloned(),
+    });
+    let range = match device.kind {
+        Kind::Light if device.dimmer => range("/api/light/level", "level", 15, &state.levels),
+        Kind::Blinds if device.travel.is_some() => range("/api/blinds/position", "position", 100, &state.positions),
+        _ => None,
+    };
+    DeviceView {
+        name: name.to_string(),
+        endpoint: endpoint.to_string(),
+        room: device.room.map(|r| format!("{:?}", r)),
+        choices,
+        state: current.map(str::to_string),
+        range,
+    }
+}
+
+fn position(status: &BlindStatus) -> &'static str {
+    match status {
+        BlindStatus::UP => "Up",
+        BlindStatus::DOWN => "Down",
+    }
+}
+
+/// `LivingRoom` as `Living room`
+pub fn words<T: std::fmt::Debug>(value: T) -> String {
+    let mut words = String::new();
+    for (i, c) in format!("{:?}", value).chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            words.push(' ');
+            words.extend(c.to_lowercase());
+        } else {
+            words.push(c);
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Configuration;
+
+    #[test]
+    fn list_every_room_and_device() {
+        let page = Page::new(&Configuration::default().devices, &State::default());
+        let rooms = page.rooms.iter().map(|r| (r.name.as_str(), r.devices.iter().map(|d| d.name.as_str()).collect::<Vec<_>>())).collect::<Vec<_>>();
+        assert_that!(&rooms, eq(vec![
+            ("Living room", vec!["Lights", "Blinds"]),
+            ("Kitchen", vec!["Lights", "Blinds"]),
+            ("Bed room", vec!["Lights", "Blinds"]),
+            ("House", vec!["Screen"]),
+        ]));
+        assert_that!(&page.scenes[0], eq(SceneView { name: "Good morning".to_string(), value: "goodmorning".to_string() }));
+    }
+
+    #[test]
+    fn show_the_state_of_devices() {
+        let mut state = State::default();
+        state.apply(crate::house::Command::Light(Room::Kitchen, LightStatus::ON));
+        state.apply(crate::house::Command::Screen(BlindStatus::DOWN));
+        let page = Page::new(&Configuration::default().devices, &state);
+        let kitchen = page.rooms.iter().find(|r| r.name == "Kitchen").unwrap();
+        assert_that!(&kitchen.devices[0].state, eq(Some("On".to_string())));
+        assert_that!(&kitchen.devices[1].state, eq(None));
+        assert_that!(&page.rooms[3].devices[0].state, eq(Some("Down".to_string())));
+    }
+
+    #[test]
+    fn slide_dimmers_and_blinds_stopping_on_the_way() {
+        let configuration: Configuration = r#"
+            [[radios]]
+            name = "433"
+            pin = 23
+
+            [[devices]]
+            kind = "light"
+            room = "Kitchen"
+            protocol = "dio"
+            address = 0x0042
+            radio = "433"
+            dimmer = true
+
+            [[devices]]
+            kind = "blinds"
+            room = "Kitchen"
+            protocol = "dooya"
+            radio = "433"
+            travel = "30s"
+
+            [[devices]]
+            kind = "light"
+            room = "BedRoom"
+            protocol = "dio"
+            address = 0x985c
+            radio = "433"
+        "#.parse().unwrap();
+        let mut state = State::default();
+        state.levels.insert(Room::Kitchen, 8);
+        let page = Page::new(&configuration.devices, &state);
+        let level = Range { endpoint: "/api/light/level".to_string(), name: "level".to_string(), max: 15, value: Some(8) };
+        let position = Range { endpoint: "/api/blinds/position".to_string(), name: "position".to_string(), max: 100, value: None };
+        assert_that!(&page.rooms[0].devices[0].range, eq(Some(level)));
+        assert_that!(&page.rooms[0].devices[1].range, eq(Some(position)));
+        assert_that!(&page.rooms[1].devices[0].range, eq(None));
+    }
+}
@@ -3,20 +3,20 @@ use std::time::Duration;
 use std::thread::sleep;
 
 pub trait DigitalOutput {
-    fn high_during(&self, duration: Duration) -> ();
-    fn low_during(&self, duration: Duration) -> ();
+    fn high_during(&self, duration: Duration);
+    fn low_during(&self, duration: Duration);
 }
 
 const LOW: u8 = 0;
 const HIGH: u8 = 1;
 
 impl DigitalOutput for Pin {
-    fn high_during(&self, duration: Duration) -> () {
+    fn high_during(&self, duration: Duration) {
         self.set_value(HIGH).unwrap();
         sleep(duration);
     }
 
-    fn low_during(&self, duration: Duration) -> () {
+    fn low_during(&self, duration: Duration) {
         self.set_value(LOW).unwrap();
         sleep(duration);
     }
@@ -33,6 +33,7 @@ pub mod mock {
         LOW,
     }
 
+    #[derive(Default)]
     pub struct InMemoryPin {
         pub states: RefCell<Vec<(PinState, Duration)>>
     }
@@ -46,11 +47,11 @@ pub mod mock {
     }
 
     impl DigitalOutput for InMemoryPin {
-        fn high_during(&self, duration: Duration) -> () {
+        fn high_during(&self, duration: Duration) {
             self.states.borrow_mut().push((PinState::HIGH, duration));
         }
 
-        fn low_during(&self, duration: Duration) -> () {
+        fn low_during(&self, duration: Duration) {
             self.states.borrow_mut().push((PinState::LOW, duration));
         }
     }
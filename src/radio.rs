@@ -1,6 +1,10 @@
 use crate::pin::DigitalOutput;
 use crate::radio_protocol::RadioProtocol;
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Signal {
@@ -8,20 +12,68 @@ pub enum Signal {
     LOW(Duration),
 }
 
+/// `+` for high and `-` for low followed by the duration in microseconds
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signal::HIGH(d) => write!(f, "+{}", d.as_micros()),
+            Signal::LOW(d) => write!(f, "-{}", d.as_micros()),
+        }
+    }
+}
+
+impl FromStr for Signal {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let duration = s.get(1..).and_then(|d| d.parse().ok()).map(Duration::from_micros).ok_or("Invalid duration")?;
+        match s.chars().next() {
+            Some('+') => Ok(Signal::HIGH(duration)),
+            Some('-') => Ok(Signal::LOW(duration)),
+            _ => Err("Unknown signal"),
+        }
+    }
+}
+
+impl Serialize for Signal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
 pub trait Radio {
     /// no thread safe garantees
+    ///
+    /// # Safety
+    /// callers must not send on the same radio from several threads at once
     unsafe fn send<M>(&self, message: M, protocol: &RadioProtocol<M>)
         where M: IntoIterator<Item=u8>;
+
+    /// same as send, for radios that care about the device the message is for
+    ///
+    /// # Safety
+    /// see send
+    unsafe fn send_to<M>(&self, _device: &str, message: M, protocol: &RadioProtocol<M>)
+        where M: IntoIterator<Item=u8> {
+        self.send(message, protocol)
+    }
 }
 
-const DELAY_CORRECTION: Duration = Duration::from_micros(100);
+/// how late the pin switches, signals are shortened by as much and can't be shorter
+pub const DELAY_CORRECTION: Duration = Duration::from_micros(100);
 
 impl<T: DigitalOutput> Radio for T {
     unsafe fn send<M>(&self, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
         let timings = protocol.timings_for(message);
         let corrected_timings: Vec<Signal> = timings.iter().map(|s| match *s {
-            Signal::LOW(d) => Signal::LOW(d - DELAY_CORRECTION),
-            Signal::HIGH(d) => Signal::HIGH(d - DELAY_CORRECTION),
+            Signal::LOW(d) => Signal::LOW(d.saturating_sub(DELAY_CORRECTION)),
+            Signal::HIGH(d) => Signal::HIGH(d.saturating_sub(DELAY_CORRECTION)),
         }).collect();
         for s in corrected_timings {
             match s {
@@ -33,8 +85,10 @@ impl<T: DigitalOutput> Radio for T {
 }
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 pub mod should {
     use super::*;
+    use galvanic_assert::matchers::*;
     use galvanic_assert::matchers::collection::*;
     use crate::pin::mock::InMemoryPin;
     use crate::pin::mock::PinState;
@@ -44,6 +98,7 @@ pub mod should {
     fn replay_timings() {
         let message = vec![5];
         let protocol = RadioProtocol::new(
+            "test",
             Header(vec![Signal::HIGH(Duration::from_micros(13 + 100))]),
             Footer(vec![Signal::LOW(Duration::from_micros(37 + 100))]),
             Zero(vec![Signal::LOW(Duration::from_micros(0 + 100))]),
@@ -68,6 +123,16 @@ pub mod should {
         (PinState::LOW, Duration::from_micros(37)),
         ]));
     }
+
+    #[test]
+    fn format_signals() {
+        assert_that!(&Signal::HIGH(Duration::from_micros(283)).to_string(), eq("+283".to_string()));
+        assert_that!(&Signal::LOW(Duration::from_micros(2793)).to_string(), eq("-2793".to_string()));
+        assert_that!(&"+283".parse::<Signal>(), eq(Ok(Signal::HIGH(Duration::from_micros(283)))));
+        assert_that!(&"-2793".parse::<Signal>(), eq(Ok(Signal::LOW(Duration::from_micros(2793)))));
+        assert_that!(&"283".parse::<Signal>().is_err(), eq(true));
+        assert_that!(&"+".parse::<Signal>().is_err(), eq(true));
+    }
 }
 
 #[cfg(test)]
@@ -75,6 +140,7 @@ pub mod mock {
     use super::*;
     use std::cell::RefCell;
 
+    #[derive(Default)]
     pub struct InMemoryRadio {
         pub signals: RefCell<Option<Vec<Signal>>>,
     }
@@ -1,8 +1,20 @@
 use std::marker::PhantomData;
-use crate::radio::Signal;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::radio::{Signal, DELAY_CORRECTION};
 
 type Timings = Vec<Signal>;
 
+/// a level, true for high, and how long it lasted in microseconds
+pub(crate) type Pulse = (bool, u64);
+
+/// durations within 25%, or 100µs for short ones, of each other are considered the same
+const TOLERANCE: f64 = 0.25;
+const SLACK: u64 = 100;
+const MAX_BYTES: usize = 32;
+/// no protocol keeps the radio high or low longer, the radio is busy while sending
+const LONGEST: Duration = Duration::from_millis(100);
+
 pub struct Header(pub Timings);
 
 pub struct Footer(pub Timings);
@@ -11,45 +23,147 @@ pub struct Zero(pub Timings);
 
 pub struct One(pub Timings);
 
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitOrder {
+    #[default]
+    MsbFirst,
+    LsbFirst,
+}
+
+/// how bits are put on air with the zero and one symbols
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// a zero is the zero symbol, a one the one symbol
+    #[default]
+    Plain,
+    /// a zero is the zero symbol followed by the one symbol, a one is the other way round
+    Manchester,
+}
+
+/// timings are written like signals, `["+283", "-2793"]`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct RadioProtocol<T> {
+    #[serde(default = "unnamed")]
+    name: String,
     header: Timings,
     footer: Timings,
     zero: Timings,
     one: Timings,
     repetition: u8,
+    #[serde(default)]
+    bit_order: BitOrder,
+    #[serde(default)]
+    encoding: Encoding,
+    #[serde(skip)]
     message: PhantomData<T>,
 }
 
+/// the protocols janet knows, `[[protocols]]` like in protocols.toml
+#[derive(Deserialize, Serialize)]
+#[serde(bound = "")]
+struct Protocols<T> {
+    protocols: Vec<RadioProtocol<T>>,
+}
+
+/// protocol definitions, in toml
+pub fn parse<T>(definitions: &str) -> Result<Vec<RadioProtocol<T>>, String> {
+    toml::from_str::<Protocols<T>>(definitions).map(|p| p.protocols).map_err(|e| e.to_string())
+}
+
+/// protocol definitions, in toml as they are parsed
+pub fn definitions<T>(protocols: Vec<RadioProtocol<T>>) -> String {
+    toml::to_string(&Protocols { protocols }).expect("Protocols are toml")
+}
+
+lazy_static! {
+    /// the protocols shipped with janet
+    pub static ref BUNDLED: Vec<RadioProtocol<Vec<u8>>> = parse(include_str!("../protocols.toml")).expect("Invalid bundled protocols");
+}
+
+pub fn bundled<T>(name: &str) -> RadioProtocol<T> {
+    BUNDLED.iter().find(|p| p.name == name).map(RadioProtocol::cast).unwrap_or_else(|| panic!("No bundled protocol {}", name))
+}
+
+/// the name of protocols defined on the spot, gateways send them as raw pulses
+pub const RAW: &str = "raw";
+
+fn unnamed() -> String {
+    RAW.to_string()
+}
+
 impl<T> RadioProtocol<T> {
-    pub fn new(header: Header, footer: Footer, zero: Zero, one: One, repetition: u8) -> Self {
+    pub fn new(name: &str, header: Header, footer: Footer, zero: Zero, one: One, repetition: u8) -> Self {
         RadioProtocol {
+            name: name.to_string(),
             header: header.0,
             footer: footer.0,
             zero: zero.0,
             one: one.0,
             repetition,
+            bit_order: BitOrder::MsbFirst,
+            encoding: Encoding::Plain,
             message: PhantomData,
         }
     }
-}
 
-struct Byte(u8);
+    pub fn with(self, bit_order: BitOrder, encoding: Encoding) -> Self {
+        RadioProtocol { bit_order, encoding, ..self }
+    }
 
-impl Byte {
-    fn at(&self, index: u8) -> u8 {
-        self.0 & (1 << index)
+    pub fn named(self, name: &str) -> Self {
+        RadioProtocol { name: name.to_string(), ..self }
     }
-    fn iter(&self) -> impl Iterator<Item=u8> {
-        let l: Vec<u8> = (0..8).rev().map(|i| self.at(i)).collect();
-        l.into_iter()
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
-}
 
+    /// whether frames of the protocol can be sent and told apart
+    pub fn check(&self) -> Result<(), String> {
+        if self.repetition == 0 {
+            return Err(format!("The {} protocol should be sent at least once", self.name));
+        }
+        if self.zero.is_empty() || self.one.is_empty() {
+            return Err(format!("The {} protocol needs the timings of zero and one", self.name));
+        }
+        if self.zero == self.one {
+            return Err(format!("The {} protocol can't tell zero from one", self.name));
+        }
+        let mut durations = self.header.iter().chain(&self.footer).chain(&self.zero).chain(&self.one).map(|s| match *s {
+            Signal::HIGH(d) | Signal::LOW(d) => d,
+        });
+        if durations.clone().any(|d| d < DELAY_CORRECTION) {
+            return Err(format!("The {} protocol has signals shorter than {}µs", self.name, DELAY_CORRECTION.as_micros()));
+        }
+        if durations.any(|d| d > LONGEST) {
+            return Err(format!("The {} protocol has signals longer than {}ms", self.name, LONGEST.as_millis()));
+        }
+        Ok(())
+    }
 
-impl<T: IntoIterator<Item=u8>> RadioProtocol<T> {
-    pub fn timings_for(&self, message: T) -> Timings {
+    /// the same protocol for frames given as bytes
+    pub fn raw(&self) -> RadioProtocol<Vec<u8>> {
+        self.cast()
+    }
+
+    fn cast<U>(&self) -> RadioProtocol<U> {
+        RadioProtocol {
+            name: self.name.clone(),
+            header: self.header.clone(),
+            footer: self.footer.clone(),
+            zero: self.zero.clone(),
+            one: self.one.clone(),
+            repetition: self.repetition,
+            bit_order: self.bit_order,
+            encoding: self.encoding,
+            message: PhantomData,
+        }
+    }
+
+    pub fn timings_for_bytes(&self, bytes: &[u8]) -> Timings {
         let mut r = vec![];
-        let bytes = message.into_iter().collect::<Vec<_>>();
         for _ in 0..self.repetition {
             r.push(self.header.clone());
             r.append(&mut bytes.iter()
@@ -61,25 +175,174 @@ impl<T: IntoIterator<Item=u8>> RadioProtocol<T> {
     }
 
     fn timings_for_byte(&self, byte: Byte) -> Timings {
-        byte.iter().map(|b|
-            if b == 0 {
-                return self.zero.clone();
-            } else {
-                return self.one.clone();
+        byte.iter(self.bit_order).map(|b| self.bit(b != 0)).collect::<Vec<Timings>>().concat()
+    }
+
+    /// timings of a bit once encoded
+    fn bit(&self, one: bool) -> Timings {
+        match (self.encoding, one) {
+            (Encoding::Plain, false) => self.zero.clone(),
+            (Encoding::Plain, true) => self.one.clone(),
+            (Encoding::Manchester, false) => [self.zero.clone(), self.one.clone()].concat(),
+            (Encoding::Manchester, true) => [self.one.clone(), self.zero.clone()].concat(),
+        }
+    }
+
+    fn byte(&self, bits: &[bool]) -> u8 {
+        let fold = |b: u8, &bit: &bool| (b << 1) | bit as u8;
+        match self.bit_order {
+            BitOrder::MsbFirst => bits.iter().fold(0, fold),
+            BitOrder::LsbFirst => bits.iter().rev().fold(0, fold),
+        }
+    }
+}
+
+impl<T> RadioProtocol<T> {
+    /// payloads of the frames found in the signals, one per repetition received
+    pub fn decode(&self, signals: &[Signal]) -> Vec<Vec<u8>> {
+        let pulses = merge(signals);
+        let bits = (self.bit(false), self.bit(true));
+        let mut payloads = vec![];
+        let mut start = 0;
+        while start < pulses.len() {
+            match self.frame(Cursor { pulses: &pulses, index: start, consumed: 0 }, &bits) {
+                Some((payload, end)) => {
+                    payloads.push(payload);
+                    start = end.index;
+                }
+                None => start += 1,
             }
-        ).collect::<Vec<Timings>>().concat()
+        }
+        payloads
+    }
+
+    /// a frame ends with a footer followed by nothing or by the header of the next repetition
+    fn frame<'a>(&self, start: Cursor<'a>, (zero, one): &(Timings, Timings)) -> Option<(Vec<u8>, Cursor<'a>)> {
+        let mut cursor = start.take_all(&self.header, false)?;
+        let mut bits = vec![];
+        while bits.len() <= MAX_BYTES * 8 {
+            if !bits.is_empty() && bits.len() % 8 == 0 {
+                if let Some(end) = cursor.take_all(&self.footer, true) {
+                    if end.at_end() || end.take_all(&self.header, false).is_some() {
+                        let payload = bits.chunks(8).map(|byte| self.byte(byte)).collect();
+                        return Some((payload, end));
+                    }
+                }
+            }
+            let (bit, next) = match (cursor.take_all(zero, false), cursor.take_all(one, false)) {
+                (Some(zero), Some(one)) if zero.consumed > 0 && one.consumed == 0 => (true, one),
+                (Some(zero), _) => (false, zero),
+                (None, Some(one)) => (true, one),
+                (None, None) => return None,
+            };
+            bits.push(bit);
+            cursor = next;
+        }
+        None
+    }
+}
+
+/// consecutive signals at the same level can't be told apart once on air
+pub(crate) fn merge(signals: &[Signal]) -> Vec<Pulse> {
+    let mut pulses: Vec<Pulse> = vec![];
+    for signal in signals {
+        let (high, d) = match *signal {
+            Signal::HIGH(d) => (true, d.as_micros() as u64),
+            Signal::LOW(d) => (false, d.as_micros() as u64),
+        };
+        match pulses.last_mut() {
+            _ if d == 0 => {}
+            Some((level, micros)) if *level == high => *micros += d,
+            _ => pulses.push((high, d)),
+        }
+    }
+    pulses
+}
+
+/// a position in received pulses, a pulse can be partly consumed when it merged several signals
+#[derive(Copy, Clone)]
+struct Cursor<'a> {
+    pulses: &'a [Pulse],
+    index: usize,
+    consumed: u64,
+}
+
+impl<'a> Cursor<'a> {
+    /// a partly consumed low at the end is the silence after the last frame
+    fn at_end(&self) -> bool {
+        self.index >= self.pulses.len() || (self.index == self.pulses.len() - 1 && self.consumed > 0 && !self.pulses[self.index].0)
+    }
+
+    /// the last low of an open ended sequence may last longer, it is followed by silence
+    fn take_all(self, timings: &[Signal], open_ended: bool) -> Option<Self> {
+        timings.iter().enumerate().try_fold(self, |cursor, (i, signal)| {
+            cursor.take(signal, open_ended && i == timings.len() - 1)
+        })
+    }
+
+    fn take(self, signal: &Signal, open_ended: bool) -> Option<Self> {
+        let (high, expected) = match *signal {
+            Signal::HIGH(d) => (true, d.as_micros() as u64),
+            Signal::LOW(d) => (false, d.as_micros() as u64),
+        };
+        if expected == 0 {
+            return Some(self);
+        }
+        let &(level, duration) = self.pulses.get(self.index)?;
+        let remaining = duration - self.consumed;
+        if level != high {
+            None
+        } else if same(remaining, expected) || (open_ended && !high && remaining > expected) {
+            Some(Cursor { index: self.index + 1, consumed: 0, ..self })
+        } else if remaining > expected {
+            Some(Cursor { consumed: self.consumed + expected, ..self })
+        } else {
+            None
+        }
+    }
+}
+
+fn same(actual: u64, expected: u64) -> bool {
+    let margin = ((expected as f64 * TOLERANCE) as u64).max(SLACK);
+    actual + margin >= expected && actual <= expected + margin
+}
+
+struct Byte(u8);
+
+impl Byte {
+    fn at(&self, index: u8) -> u8 {
+        self.0 & (1 << index)
+    }
+    fn iter(&self, order: BitOrder) -> impl Iterator<Item=u8> {
+        let l: Vec<u8> = match order {
+            BitOrder::MsbFirst => (0..8).rev().map(|i| self.at(i)).collect(),
+            BitOrder::LsbFirst => (0..8).map(|i| self.at(i)).collect(),
+        };
+        l.into_iter()
+    }
+}
+
+
+impl<T: IntoIterator<Item=u8>> RadioProtocol<T> {
+    pub fn timings_for(&self, message: T) -> Timings {
+        self.timings_for_bytes(&message.into_iter().collect::<Vec<_>>())
     }
 }
 
 #[cfg(test)]
 mod should {
     use super::*;
+    use galvanic_assert::matchers::*;
     use galvanic_assert::matchers::collection::*;
     use std::time::Duration;
+    use crate::dio::*;
+    use crate::dooya::DOOYA_PROTOCOL;
+    use crate::celexon::CELEXON_PROTOCOL;
 
     #[test]
     fn send_byte() {
-        let protocol = RadioProtocol::new(Header(vec![Signal::HIGH(Duration::from_micros(13))]),
+        let protocol = RadioProtocol::new("test",
+                                          Header(vec![Signal::HIGH(Duration::from_micros(13))]),
                                           Footer(vec![Signal::LOW(Duration::from_micros(37))]),
                                           Zero(vec![Signal::HIGH(Duration::from_micros(0))]),
                                           One(vec![Signal::LOW(Duration::from_micros(1))]),
@@ -102,7 +365,8 @@ mod should {
     fn send_bytes() {
         let z = 10;
         let o = 11;
-        let protocol = RadioProtocol::new(Header(vec![Signal::HIGH(Duration::from_micros(4)), Signal::LOW(Duration::from_micros(2))]),
+        let protocol = RadioProtocol::new("test",
+                                          Header(vec![Signal::HIGH(Duration::from_micros(4)), Signal::LOW(Duration::from_micros(2))]),
                                           Footer(vec![Signal::HIGH(Duration::from_micros(13)), Signal::LOW(Duration::from_micros(37))]),
                                           Zero(vec![Signal::HIGH(Duration::from_micros(z))]),
                                           One(vec![Signal::LOW(Duration::from_micros(o))]),
@@ -151,4 +415,103 @@ mod should {
         Signal::LOW(Duration::from_micros(37))
         ]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn decode_every_repetition() {
+        let signals = DIO_PROTOCOL.timings_for(DioMessage::new(0x1337, Status::ON));
+        assert_that!(&DIO_PROTOCOL.decode(&signals), eq(vec![vec![0x27, 0x13, 0x37, 0x90]; 10]));
+    }
+
+    #[test]
+    fn decode_footers_merged_into_the_last_bit() {
+        let signals = DOOYA_PROTOCOL.timings_for(crate::dooya::Status::DOWN);
+        assert_that!(&DOOYA_PROTOCOL.decode(&signals), eq(vec![vec![0x06, 0x41, 0xdf, 0xd1, 0x33]; 10]));
+    }
+
+    #[test]
+    fn decode_footers_looking_like_bits() {
+        let signals = CELEXON_PROTOCOL.timings_for(crate::celexon::Status::UP);
+        assert_that!(&CELEXON_PROTOCOL.decode(&signals), eq(vec![vec![0x53, 0xe1, 0x3e, 0xd4, 0xfe, 0xff, 0xf4, 0xe9]; 10]));
+    }
+
+    #[test]
+    fn decode_despite_jitter_silence_and_partial_frames() {
+        let signals = DIO_PROTOCOL.timings_for(DioMessage::new(0x0042, Status::OFF));
+        let mut noisy = signals[37..].iter().enumerate().map(|(i, s)| {
+            let jitter = |d: Duration| Duration::from_micros(d.as_micros() as u64 * (90 + (i as u64 * 7) % 21) / 100);
+            match *s {
+                Signal::HIGH(d) => Signal::HIGH(jitter(d)),
+                Signal::LOW(d) => Signal::LOW(jitter(d)),
+            }
+        }).collect::<Vec<_>>();
+        noisy.push(Signal::LOW(Duration::from_millis(50)));
+        assert_that!(&DIO_PROTOCOL.decode(&noisy), eq(vec![vec![0x27, 0x00, 0x42, 0x80]; 9]));
+    }
+
+    #[test]
+    fn decode_nothing_from_other_protocols() {
+        let signals = DOOYA_PROTOCOL.timings_for(crate::dooya::Status::UP);
+        assert_that!(&DIO_PROTOCOL.decode(&signals).is_empty(), eq(true));
+        assert_that!(&CELEXON_PROTOCOL.decode(&signals).is_empty(), eq(true));
+    }
+
+    #[test]
+    fn read_definitions() {
+        let protocol: RadioProtocol<Vec<u8>> = serde_json::from_value(serde_json::json!({
+            "header": ["+4800", "-1500"],
+            "footer": ["-8600"],
+            "zero": ["+350", "-750"],
+            "one": ["+700", "-400"],
+            "repetition": 10,
+        })).unwrap();
+        assert_that!(&protocol.name(), eq("raw"));
+        assert_that!(&protocol.timings_for_bytes(&[0x06, 0x41]), eq(DOOYA_PROTOCOL.timings_for_bytes(&[0x06, 0x41])));
+    }
+
+    #[test]
+    fn send_manchester_bits_as_pairs_of_symbols() {
+        let signals = |timings: &[i64]| timings.iter().map(|&t| if t > 0 { Signal::HIGH(Duration::from_micros(t as u64)) } else { Signal::LOW(Duration::from_micros(-t as u64)) }).collect::<Vec<_>>();
+        let timings = DIO_PROTOCOL.timings_for(DioMessage::new(0x1337, Status::ON));
+        assert_that!(&timings[2..14].to_vec(), eq(signals(&[283, -283, 283, -1355, 283, -283, 283, -1355, 283, -1355, 283, -283])));
+    }
+
+    #[test]
+    fn send_and_decode_least_significant_bits_first() {
+        let protocol = RadioProtocol::<Vec<u8>>::new("test",
+                                                    Header(vec![Signal::HIGH(Duration::from_micros(4000)), Signal::LOW(Duration::from_micros(2000))]),
+                                                    Footer(vec![Signal::LOW(Duration::from_micros(8000))]),
+                                                    Zero(vec![Signal::HIGH(Duration::from_micros(300)), Signal::LOW(Duration::from_micros(900))]),
+                                                    One(vec![Signal::HIGH(Duration::from_micros(900)), Signal::LOW(Duration::from_micros(300))]),
+                                                    1).with(BitOrder::LsbFirst, Encoding::Plain);
+        let timings = protocol.timings_for(vec![0x01, 0x80]);
+        assert_that!(&timings[2..4].to_vec(), eq(vec![Signal::HIGH(Duration::from_micros(900)), Signal::LOW(Duration::from_micros(300))]));
+        assert_that!(&protocol.decode(&timings), eq(vec![vec![0x01, 0x80]]));
+    }
+
+    #[test]
+    fn bundle_the_protocols_janet_speaks() {
+        let names = BUNDLED.iter().map(|p| p.name()).collect::<Vec<_>>();
+        assert_that!(&names, eq(vec!["dio", "dooya", "celexon", "ev1527"]));
+        assert_that!(&parse::<Vec<u8>>("[[protocols]]\nname = \"incomplete\"").is_err(), eq(true));
+        assert_that!(&BUNDLED.iter().all(|p| p.check().is_ok()), eq(true));
+    }
+
+    #[test]
+    fn write_definitions_as_they_are_parsed() {
+        let definitions = definitions(BUNDLED.clone());
+        assert_that!(&definitions.starts_with("[[protocols]]\nname = \"dio\"\n"), eq(true));
+        assert_that!(&parse::<Vec<u8>>(&definitions), eq(Ok(BUNDLED.clone())));
+    }
+
+    #[test]
+    fn reject_protocols_which_can_not_be_sent_or_told_apart() {
+        let signal = |micros| Signal::HIGH(Duration::from_micros(micros));
+        let protocol = |zero, one, repetition| RadioProtocol::<Vec<u8>>::new("test", Header(vec![]), Footer(vec![signal(500)]), Zero(zero), One(one), repetition);
+        assert_that!(&protocol(vec![signal(300)], vec![signal(900)], 1).check(), eq(Ok(())));
+        assert_that!(&protocol(vec![signal(300)], vec![signal(900)], 0).check(), eq(Err("The test protocol should be sent at least once".to_string())));
+        assert_that!(&protocol(vec![], vec![signal(900)], 1).check(), eq(Err("The test protocol needs the timings of zero and one".to_string())));
+        assert_that!(&protocol(vec![signal(300)], vec![signal(300)], 1).check(), eq(Err("The test protocol can't tell zero from one".to_string())));
+        assert_that!(&protocol(vec![signal(300)], vec![signal(5_000_000)], 1).check(), eq(Err("The test protocol has signals longer than 100ms".to_string())));
+        assert_that!(&protocol(vec![signal(99)], vec![signal(900)], 1).check(), eq(Err("The test protocol has signals shorter than 100µs".to_string())));
+    }
+}
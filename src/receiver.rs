@@ -0,0 +1,191 @@
+use std::io;
+use std::time::{Duration, Instant};
+use sysfs_gpio::{Direction, Edge, Pin, PinPoller};
+use crate::config::{Action, ReceiverConfiguration};
+use crate::sensor::{Sensor, Sensors};
+use crate::clock;
+use crate::house::House;
+use crate::trigger::{self, Triggers};
+use crate::weather::{self, Weather};
+use crate::rule::{Event, Rules};
+use crate::events::{self, HouseEvent};
+use crate::hex;
+use crate::radio::Signal;
+use crate::serial::{Frame, SerialGateway};
+use crate::radio_protocol::RadioProtocol;
+
+/// a burst of pulses ends after this much silence
+const SILENCE: Duration = Duration::from_millis(50);
+/// receivers output noise when nothing is sent, bursts are cut at this length
+const MAX_PULSES: usize = 4096;
+
+pub type Frames = Box<dyn Iterator<Item=io::Result<Frame>> + Send>;
+
+/// A 433MHz receiver module wired on a gpio, every burst of pulses it hears is a raw frame.
+pub struct GpioReceiver {
+    pin: Pin,
+    poller: PinPoller,
+}
+
+impl GpioReceiver {
+    pub fn open(pin: u64) -> io::Result<Self> {
+        let pin = Pin::new(pin);
+        pin.export().map_err(to_io)?;
+        pin.set_direction(Direction::In).map_err(to_io)?;
+        pin.set_edge(Edge::BothEdges).map_err(to_io)?;
+        let poller = pin.get_poller().map_err(to_io)?;
+        Ok(GpioReceiver { pin, poller })
+    }
+}
+
+impl Iterator for GpioReceiver {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut signals = vec![];
+        let mut high = match self.pin.get_value() {
+            Ok(value) => value == 1,
+            Err(e) => return Some(Err(to_io(e))),
+        };
+        let mut last = Instant::now();
+        loop {
+            match self.poller.poll(SILENCE.as_millis() as isize) {
+                Ok(Some(value)) => {
+                    let now = Instant::now();
+                    if (value == 1) != high {
+                        signals.push(if high { Signal::HIGH(now - last) } else { Signal::LOW(now - last) });
+                        high = value == 1;
+                        last = now;
+                    }
+                    if signals.len() >= MAX_PULSES {
+                        return Some(Ok(Frame::Raw(signals)));
+                    }
+                }
+                Ok(None) if signals.is_empty() => last = Instant::now(),
+                Ok(None) => {
+                    signals.push(if high { Signal::HIGH(last.elapsed()) } else { Signal::LOW(last.elapsed()) });
+                    return Some(Ok(Frame::Raw(signals)));
+                }
+                Err(e) => return Some(Err(to_io(e))),
+            }
+        }
+    }
+}
+
+fn to_io(error: sysfs_gpio::Error) -> io::Error {
+    match error {
+        sysfs_gpio::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+pub fn open(configuration: &ReceiverConfiguration) -> io::Result<Frames> {
+    match configuration {
+        ReceiverConfiguration::Gpio { pin } => Ok(Box::new(GpioReceiver::open(*pin)?)),
+        ReceiverConfiguration::Serial { path, baud } => Ok(Box::new(SerialGateway::open(path, *baud, vec![])?.frames()?)),
+    }
+}
+
+/// the payloads of a frame for each protocol given, repetitions are only kept once
+pub fn decode(frame: Frame, protocols: &[RadioProtocol<Vec<u8>>]) -> Vec<(String, Vec<u8>)> {
+    match frame {
+        Frame::Decoded { protocol, payload } => vec![(protocol, payload)],
+        Frame::Raw(signals) => {
+            let mut payloads = vec![];
+            for protocol in protocols {
+                for payload in protocol.decode(&signals) {
+                    let frame = (protocol.name().to_string(), payload);
+                    if !payloads.contains(&frame) {
+                        payloads.push(frame);
+                    }
+                }
+            }
+            payloads
+        }
+    }
+}
+
+/// tells the house, the sensors and the rules about every frame heard, runs the triggers they press
+/// and records weather readings, until the receiver fails
+pub fn listen<H, S>(house: &H, sensors: &Sensors<S>, triggers: &Triggers, weather: &Weather, rules: &Rules, frames: Frames) -> io::Result<()>
+    where H: House + ?Sized, S: Sensor {
+    let protocols = house.protocols();
+    for frame in frames {
+        let frame = frame?;
+        let at = Instant::now();
+        let timestamp = clock::now();
+        if let Frame::Raw(signals) = &frame {
+            for reading in weather::decode(signals) {
+                let room = weather.room(&reading);
+                let previous = room.and_then(|r| weather.temperature(r));
+                let event = serde_json::to_value(&reading).expect("Readings are json");
+                for action in weather.record(&reading, timestamp) {
+                    run(house, &action, &event);
+                }
+                if let (Some(room), Some(temperature)) = (room, reading.temperature) {
+                    rules.fire(house, &Event::Temperature { room, temperature, previous }, timestamp);
+                }
+            }
+        }
+        for (protocol, payload) in decode(frame, &protocols) {
+            // janet's own frames were already acted upon when they were sent
+            if house.received(&protocol, &payload).is_none() {
+                continue;
+            }
+            events::publish(HouseEvent::Frame { protocol: protocol.clone(), payload: hex::encode(&payload) });
+            let event = serde_json::json!({ "protocol": protocol, "payload": hex::encode(&payload) });
+            for action in triggers.pressed(&protocol, &payload, at) {
+                run(house, &action, &event);
+            }
+            for change in sensors.received(&protocol, &payload, timestamp) {
+                for action in sensors.automations(&change) {
+                    run(house, &action, &event);
+                }
+                rules.fire(house, &Event::Sensor { sensor: change.sensor, active: change.active }, timestamp);
+            }
+            rules.fire(house, &Event::Frame { protocol, payload: hex::encode(&payload) }, timestamp);
+        }
+    }
+    Ok(())
+}
+
+fn run<H>(house: &H, action: &Action, event: &serde_json::Value) where H: House + ?Sized {
+    if let Err(e) = trigger::run(house, action, event) {
+        events::error(format!("Can't run {:?}: {}", action, e));
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::dio::{DioMessage, Status, DIO_PROTOCOL};
+    use crate::celexon::CELEXON_PROTOCOL;
+    use crate::radio_protocol::{self, BitOrder, Encoding, Footer, Header, One, Zero};
+
+    #[test]
+    fn decode_raw_frames_once_per_burst() {
+        let mut signals = DIO_PROTOCOL.timings_for(DioMessage::new(0x1337, Status::ON));
+        signals.append(&mut CELEXON_PROTOCOL.timings_for(crate::celexon::Status::DOWN));
+        assert_that!(&decode(Frame::Raw(signals), &radio_protocol::BUNDLED), eq(vec![
+            ("dio".to_string(), vec![0x27, 0x13, 0x37, 0x90]),
+            ("celexon".to_string(), vec![0x53, 0xe1, 0x3e, 0xd4, 0xfe, 0xff, 0xbc, 0xb1]),
+        ]));
+    }
+
+    #[test]
+    fn decode_configured_protocols() {
+        let microseconds = |high, low| vec![Signal::HIGH(Duration::from_micros(high)), Signal::LOW(Duration::from_micros(low))];
+        let somfy = RadioProtocol::new("somfy", Header(microseconds(2400, 2400)), Footer(microseconds(600, 30000)),
+                                       Zero(microseconds(600, 1200)), One(microseconds(1200, 600)), 2)
+            .with(BitOrder::LsbFirst, Encoding::Plain);
+        let signals = somfy.timings_for_bytes(&[0xa7, 0x42]);
+        assert_that!(&decode(Frame::Raw(signals), &[somfy]), eq(vec![("somfy".to_string(), vec![0xa7, 0x42])]));
+    }
+
+    #[test]
+    fn pass_decoded_frames_through() {
+        let frame = Frame::Decoded { protocol: "dio".to_string(), payload: vec![0x27, 0x13, 0x37, 0x80] };
+        assert_that!(&decode(frame, &[]), eq(vec![("dio".to_string(), vec![0x27, 0x13, 0x37, 0x80])]));
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs::{File, OpenOptions};
+use std::convert::TryFrom;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error;
+use crate::events;
+use crate::hex;
+use crate::radio::{Radio, Signal};
+use crate::radio_protocol::RadioProtocol;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// one json object per line, easy to diff
+    #[default]
+    Jsonl,
+    /// little endian records: timestamp as u64, device and protocol as u8 length then utf8,
+    /// payload as u16 length then bytes, signals as u32 count then u32 each
+    /// with the highest bit set for high and the duration in microseconds below
+    Binary,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Transmission {
+    /// milliseconds since epoch
+    pub timestamp: u64,
+    pub device: String,
+    pub protocol: String,
+    #[serde(serialize_with = "serialize_payload", deserialize_with = "deserialize_payload")]
+    pub payload: Vec<u8>,
+    pub signals: Vec<Signal>,
+}
+
+/// A radio writing every transmission to a file instead of the air
+pub struct Recorder {
+    file: File,
+    format: Format,
+}
+
+const HIGH_BIT: u32 = 1 << 31;
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P, format: Format) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder { file, format })
+    }
+
+    fn record(&self, transmission: &Transmission) -> io::Result<()> {
+        let bytes = match self.format {
+            Format::Jsonl => {
+                let mut line = serde_json::to_vec(transmission)?;
+                line.push(b'\n');
+                line
+            }
+            Format::Binary => to_binary(transmission)?,
+        };
+        (&self.file).write_all(&bytes)
+    }
+}
+
+impl Radio for Recorder {
+    unsafe fn send<M>(&self, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
+        self.send_to("", message, protocol)
+    }
+
+    unsafe fn send_to<M>(&self, device: &str, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
+        let payload = message.into_iter().collect::<Vec<u8>>();
+        let transmission = Transmission {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+            device: device.to_string(),
+            protocol: protocol.name().to_string(),
+            signals: protocol.timings_for_bytes(&payload),
+            payload,
+        };
+        if let Err(e) = self.record(&transmission) {
+            events::error(format!("Can't record transmission: {}", e));
+        }
+    }
+}
+
+pub fn read<R: Read>(reader: R, format: Format) -> io::Result<Vec<Transmission>> {
+    match format {
+        Format::Jsonl => BufReader::new(reader).lines()
+            .filter(|l| l.as_ref().map(|l| !l.trim().is_empty()).unwrap_or(true))
+            .map(|l| Ok(serde_json::from_str(&l?)?))
+            .collect(),
+        Format::Binary => {
+            let mut reader = BufReader::new(reader);
+            let mut transmissions = vec![];
+            while !reader.fill_buf()?.is_empty() {
+                transmissions.push(from_binary(&mut reader)?);
+            }
+            Ok(transmissions)
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn to_binary(transmission: &Transmission) -> io::Result<Vec<u8>> {
+    let mut bytes = transmission.timestamp.to_le_bytes().to_vec();
+    for text in &[&transmission.device, &transmission.protocol] {
+        if text.len() > u8::MAX as usize {
+            return Err(invalid("Name too long"));
+        }
+        bytes.push(text.len() as u8);
+        bytes.extend_from_slice(text.as_bytes());
+    }
+    if transmission.payload.len() > u16::MAX as usize {
+        return Err(invalid("Payload too long"));
+    }
+    bytes.extend_from_slice(&(transmission.payload.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&transmission.payload);
+    let count = u32::try_from(transmission.signals.len()).map_err(|_| invalid("Too many signals"))?;
+    bytes.extend_from_slice(&count.to_le_bytes());
+    for signal in &transmission.signals {
+        let (high, duration) = match signal {
+            Signal::HIGH(d) => (HIGH_BIT, d),
+            Signal::LOW(d) => (0, d),
+        };
+        let micros = u32::try_from(duration.as_micros()).ok().filter(|&m| m & HIGH_BIT == 0).ok_or_else(|| invalid("Signal too long"))?;
+        bytes.extend_from_slice(&(high | micros).to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+fn from_binary<R: Read>(reader: &mut R) -> io::Result<Transmission> {
+    fn take<R: Read>(reader: &mut R, length: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0; length];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn text<R: Read>(reader: &mut R) -> io::Result<String> {
+        let length = take(reader, 1)?[0] as usize;
+        String::from_utf8(take(reader, length)?).map_err(|_| invalid("Invalid name"))
+    }
+    let mut timestamp = [0; 8];
+    reader.read_exact(&mut timestamp)?;
+    let device = text(reader)?;
+    let protocol = text(reader)?;
+    let mut length = [0; 2];
+    reader.read_exact(&mut length)?;
+    let payload = take(reader, u16::from_le_bytes(length) as usize)?;
+    let mut count = [0; 4];
+    reader.read_exact(&mut count)?;
+    let signals = (0..u32::from_le_bytes(count)).map(|_| {
+        let mut pulse = [0; 4];
+        reader.read_exact(&mut pulse)?;
+        let pulse = u32::from_le_bytes(pulse);
+        let duration = Duration::from_micros((pulse & !HIGH_BIT) as u64);
+        Ok(if pulse & HIGH_BIT != 0 { Signal::HIGH(duration) } else { Signal::LOW(duration) })
+    }).collect::<io::Result<Vec<_>>>()?;
+    Ok(Transmission { timestamp: u64::from_le_bytes(timestamp), device, protocol, payload, signals })
+}
+
+fn serialize_payload<S>(payload: &[u8], serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+    serializer.serialize_str(&hex::encode(payload))
+}
+
+fn deserialize_payload<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error> where D: Deserializer<'de> {
+    hex::decode(&String::deserialize(deserializer)?).ok_or_else(|| D::Error::custom("Invalid payload"))
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::env;
+    use crate::radio_protocol::*;
+
+    fn protocol() -> RadioProtocol<Vec<u8>> {
+        RadioProtocol::new("test",
+                           Header(vec![Signal::HIGH(Duration::from_micros(13))]),
+                           Footer(vec![Signal::LOW(Duration::from_micros(37))]),
+                           Zero(vec![Signal::LOW(Duration::from_micros(2))]),
+                           One(vec![Signal::HIGH(Duration::from_micros(1))]),
+                           1)
+    }
+
+    fn transmission() -> Transmission {
+        Transmission {
+            timestamp: 1_550_000_000_000,
+            device: "light Kitchen".to_string(),
+            protocol: "dio".to_string(),
+            payload: vec![0x27, 0x00, 0x42, 0x90],
+            signals: vec![Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(2793))],
+        }
+    }
+
+    #[test]
+    fn write_json_lines() {
+        let line = serde_json::to_string(&transmission()).unwrap();
+        assert_that!(&line, eq(r#"{"timestamp":1550000000000,"device":"light Kitchen","protocol":"dio","payload":"27004290","signals":["+283","-2793"]}"#.to_string()));
+    }
+
+    #[test]
+    fn read_back_json_lines() {
+        let line = serde_json::to_string(&transmission()).unwrap() + "\n";
+        let transmissions = read(line.as_bytes(), Format::Jsonl).unwrap();
+        assert_that!(&transmissions, eq(vec![transmission()]));
+    }
+
+    #[test]
+    fn read_back_binary() {
+        let mut bytes = to_binary(&transmission()).unwrap();
+        bytes.append(&mut to_binary(&transmission()).unwrap());
+        let transmissions = read(bytes.as_slice(), Format::Binary).unwrap();
+        assert_that!(&transmissions, eq(vec![transmission(), transmission()]));
+    }
+
+    #[test]
+    fn reject_truncated_binary() {
+        let bytes = to_binary(&transmission()).unwrap();
+        assert_that!(&read(&bytes[..bytes.len() - 1], Format::Binary).is_err(), eq(true));
+    }
+
+    #[test]
+    fn refuse_signals_too_long_for_binary() {
+        let mut transmission = transmission();
+        transmission.signals.push(Signal::LOW(Duration::from_micros(1 << 31)));
+        assert_that!(&to_binary(&transmission).unwrap_err().to_string(), eq("Signal too long".to_string()));
+        transmission.signals.pop();
+        transmission.signals.push(Signal::HIGH(Duration::from_micros((1 << 31) - 1)));
+        let bytes = to_binary(&transmission).unwrap();
+        assert_that!(&read(bytes.as_slice(), Format::Binary).unwrap(), eq(vec![transmission]));
+    }
+
+    #[test]
+    fn record_transmissions() {
+        for format in [Format::Jsonl, Format::Binary] {
+            let path = env::temp_dir().join(format!("janet_record_{:?}_{}", format, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let recorder = Recorder::create(&path, format).unwrap();
+            unsafe {
+                recorder.send_to("screen", vec![0x81], &protocol());
+                recorder.send(vec![0x01], &protocol());
+            }
+            let transmissions = read(File::open(&path).unwrap(), format).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_that!(&transmissions.len(), eq(2));
+            assert_that!(&transmissions[0].device, eq("screen".to_string()));
+            assert_that!(&transmissions[0].protocol, eq("test".to_string()));
+            assert_that!(&transmissions[0].payload, eq(vec![0x81]));
+            assert_that!(&transmissions[0].signals, eq(protocol().timings_for(vec![0x81])));
+            assert_that!(&transmissions[1].device, eq("".to_string()));
+        }
+    }
+}
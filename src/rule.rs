@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+use crate::config::{Action, Check, Condition, Configuration, Pattern, Rule, Step};
+use crate::clock::{self, Minute, TimeOfDay};
+use crate::house::{House, Room, State};
+use crate::timer::Timers;
+use crate::trigger;
+use crate::events::{self, HouseEvent};
+
+/// how often the configuration file is checked for changes
+const POLL: Duration = Duration::from_secs(2);
+
+/// what happened, for rules to react to
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    /// payload in hexadecimal
+    Frame { protocol: String, payload: String },
+    Api { name: String },
+    Schedule { name: String },
+    Sensor { sensor: String, active: bool },
+    Temperature { room: Room, temperature: f32, previous: Option<f32> },
+}
+
+impl Pattern {
+    fn matches(&self, event: &Event, time: TimeOfDay) -> bool {
+        match (self, event) {
+            (Pattern::Frame { protocol, payload }, Event::Frame { protocol: p, payload: b }) =>
+                protocol == p && payload.as_ref().map_or(true, |payload| payload.eq_ignore_ascii_case(b)),
+            (Pattern::Api { api }, Event::Api { name }) => api == name,
+            (Pattern::Schedule { schedule }, Event::Schedule { name }) => schedule == name,
+            (Pattern::Condition(condition), event) => condition.becomes_true(event, time),
+            _ => false,
+        }
+    }
+}
+
+impl Condition {
+    /// whether the event makes the condition true when it wasn't, at that time of the day
+    pub fn becomes_true(&self, event: &Event, time: TimeOfDay) -> bool {
+        match (self, event) {
+            (Condition::Temperature { room, above, below }, Event::Temperature { room: r, temperature, previous }) => {
+                let holds = |t: f32| above.map_or(true, |a| t > a) && below.map_or(true, |b| t < b);
+                room == r && holds(*temperature) && !previous.map_or(false, holds)
+            }
+            (Condition::Sensor { sensor, active, after, before }, Event::Sensor { sensor: s, active: a }) =>
+                sensor == s && active == a && time.between(*after, *before),
+            _ => false,
+        }
+    }
+}
+
+impl Check {
+    fn holds(&self, state: &State, now: Minute) -> bool {
+        match self {
+            Check::Light { light, is } => state.lights.get(light) == Some(is),
+            Check::Blinds { blinds, is } => state.blinds.get(blinds) == Some(is),
+            Check::Screen { screen } => state.screen == Some(*screen),
+            Check::Vacation { vacation } => state.vacation == *vacation,
+            Check::Days { days } => days.contains(now.weekday()),
+            Check::Time { after, before } => now.time().between(*after, *before),
+        }
+    }
+}
+
+/// The rules of the configuration, reloaded when it changes. Steps following a delay become timers.
+pub struct Rules {
+    rules: Mutex<Vec<Rule>>,
+    timers: Arc<Timers>,
+}
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>, timers: Arc<Timers>) -> Self {
+        Rules { rules: Mutex::new(rules), timers }
+    }
+
+    pub fn reload(&self, rules: Vec<Rule>) {
+        *self.rules.lock().expect("Can't get lock on rules") = rules;
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.rules.lock().expect("Can't get lock on rules").iter().map(|r| r.name.clone()).collect()
+    }
+
+    /// the actions of the rules reacting to the event, with how long to wait before running them
+    pub fn evaluate<H>(&self, house: &H, event: &Event, now: Minute) -> Vec<(Duration, Action)> where H: House + ?Sized {
+        let state = house.state();
+        let mut actions = vec![];
+        for rule in self.rules.lock().expect("Can't get lock on rules").iter()
+            .filter(|r| r.on.matches(event, now.time()) && r.conditions.iter().all(|c| c.holds(&state, now))) {
+            let mut delay = Duration::from_secs(0);
+            for step in &rule.then {
+                match step {
+                    Step::Delay { delay: d } => delay += d.0,
+                    Step::Action(action) => actions.push((delay, action.clone())),
+                }
+            }
+        }
+        actions
+    }
+
+    /// runs what the rules do on the event, delayed actions are left to the timers
+    pub fn fire<H>(&self, house: &H, event: &Event, timestamp: u64) where H: House + ?Sized {
+        let json = serde_json::to_value(event).expect("Events are json");
+        for (delay, action) in self.evaluate(house, event, clock::local(timestamp)) {
+            if delay == Duration::from_secs(0) {
+                if let Err(e) = trigger::run(house, &action, &json) {
+                    events::error(format!("Can't run {:?}: {}", action, e));
+                }
+            } else if let Err(e) = self.timers.add(timestamp + delay.as_millis() as u64, action) {
+                events::error(format!("Can't save timers: {}", e));
+            }
+        }
+    }
+}
+
+/// reloads the rules whenever the configuration file changes, for ever
+pub fn watch<P: AsRef<Path>>(path: P, rules: &Rules) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last: Option<SystemTime> = modified(path.as_ref());
+    loop {
+        sleep(POLL);
+        let current = modified(path.as_ref());
+        if current == last {
+            continue;
+        }
+        last = current;
+        match Configuration::from_file(path.as_ref()) {
+            Ok(configuration) => {
+                rules.reload(configuration.rules);
+                events::publish(HouseEvent::RulesReloaded);
+            }
+            Err(e) => events::error(format!("Rules not reloaded: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::clock::TimeOfDay;
+    use crate::house::{LightStatus, MyHouse};
+    use crate::radio::mock::InMemoryRadio;
+
+    const LIGHT_ON: Action = Action::Light { light: Room::LivingRoom, status: LightStatus::ON };
+    const LIGHT_OFF: Action = Action::Light { light: Room::LivingRoom, status: LightStatus::OFF };
+
+    fn house() -> MyHouse<InMemoryRadio> {
+        let radios = vec![("433".to_string(), InMemoryRadio::new())].into_iter().collect();
+        MyHouse::new(radios, Configuration::default().devices)
+    }
+
+    fn rules(timers: Arc<Timers>) -> Rules {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[thermometers]]
+            room = "LivingRoom"
+            protocol = "oregon"
+
+            [[rules]]
+            name = "doorbell"
+            on = { api = "doorbell" }
+            if = [{ light = "LivingRoom", is = "off" }, { after = "19:00", before = "07:00" }, { days = "mon-fri" }]
+            then = [{ light = "LivingRoom", status = "on" }, { delay = "5m" }, { light = "LivingRoom", status = "off" }]
+
+            [[rules]]
+            name = "cold"
+            on = { room = "LivingRoom", below = 18.0 }
+            then = [{ scene = "goodnight" }]
+
+            [[rules]]
+            name = "remote"
+            on = { protocol = "dio", payload = "2713379a" }
+            then = [{ scene = "cinema" }]
+        "#.parse().unwrap();
+        Rules::new(configuration.rules, timers)
+    }
+
+    fn friday(time: &str) -> Minute {
+        Minute::new(2019, 2, 15, time.parse::<TimeOfDay>().unwrap())
+    }
+
+    #[test]
+    fn react_to_events_when_conditions_hold() {
+        let house = house();
+        let rules = rules(Arc::new(Timers::new()));
+        let doorbell = Event::Api { name: "doorbell".to_string() };
+        assert_that!(&rules.evaluate(&house, &doorbell, friday("20:00")), eq(vec![]));
+        house.light(Room::LivingRoom, LightStatus::OFF);
+        assert_that!(&rules.evaluate(&house, &doorbell, friday("20:00")), eq(vec![
+            (Duration::from_secs(0), LIGHT_ON),
+            (Duration::from_secs(300), LIGHT_OFF),
+        ]));
+        assert_that!(&rules.evaluate(&house, &doorbell, friday("12:00")), eq(vec![]));
+        assert_that!(&rules.evaluate(&house, &doorbell, Minute::new(2019, 2, 16, TimeOfDay { hour: 20, minute: 0 })), eq(vec![]));
+        assert_that!(&rules.evaluate(&house, &Event::Api { name: "alarm".to_string() }, friday("20:00")), eq(vec![]));
+    }
+
+    #[test]
+    fn run_actions_and_leave_delayed_ones_to_timers() {
+        let house = house();
+        let timers = Arc::new(Timers::new());
+        let rules = rules(timers.clone());
+        house.light(Room::LivingRoom, LightStatus::OFF);
+        let timestamp = clock::timestamp(friday("20:00"));
+        rules.fire(&house, &Event::Api { name: "doorbell".to_string() }, timestamp);
+        assert_that!(&house.state().lights[&Room::LivingRoom], eq(LightStatus::ON));
+        assert_that!(&timers.due(timestamp + 300_000).unwrap(), eq(vec![LIGHT_OFF]));
+    }
+
+    #[test]
+    fn react_to_frames_and_temperatures() {
+        let house = house();
+        let rules = rules(Arc::new(Timers::new()));
+        let frame = Event::Frame { protocol: "dio".to_string(), payload: "2713379A".to_string() };
+        assert_that!(&rules.evaluate(&house, &frame, friday("12:00")).len(), eq(1));
+        let cold = |previous| Event::Temperature { room: Room::LivingRoom, temperature: 17.5, previous };
+        assert_that!(&rules.evaluate(&house, &cold(Some(18.5)), friday("12:00")).len(), eq(1));
+        assert_that!(&rules.evaluate(&house, &cold(Some(17.8)), friday("12:00")).len(), eq(0));
+    }
+
+    #[test]
+    fn reload_rules() {
+        let house = house();
+        let rules = rules(Arc::new(Timers::new()));
+        rules.reload(vec![]);
+        assert_that!(&rules.names(), eq(Vec::<String>::new()));
+        let frame = Event::Frame { protocol: "dio".to_string(), payload: "2713379A".to_string() };
+        assert_that!(&rules.evaluate(&house, &frame, friday("12:00")), eq(vec![]));
+    }
+}
@@ -0,0 +1,449 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::Duration;
+use serde::Serialize;
+use crate::config::{Action, Location, Schedule, When};
+use crate::clock::{self, Minute, TimeOfDay};
+use crate::sun;
+use crate::house::House;
+use crate::trigger;
+use crate::events;
+use crate::rule::{Event, Rules};
+
+/// minutes skipped by the wall clock, like when moving to summer time, are caught up to this limit
+const CATCH_UP: i64 = 60;
+/// the wall clock going back further than this is an adjustment, not a change to winter time
+const ADJUSTMENT: i64 = 2 * 60;
+const TICK: Duration = Duration::from_secs(15);
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// minute, hour, day of month, month and day of week, the day matches either of its fields when both are restricted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn matches(&self, minute: Minute) -> bool {
+        let (_, month, _) = minute.date();
+        let time = minute.time();
+        self.minutes & 1 << time.minute != 0 && self.hours & 1 << time.hour != 0 && self.months & 1 << month != 0 && self.matches_day(minute)
+    }
+
+    fn matches_day(&self, minute: Minute) -> bool {
+        let (_, _, day) = minute.date();
+        let by_day = self.days & 1 << day != 0;
+        let by_weekday = self.weekdays & 1 << minute.weekday() != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => by_day,
+            (true, false) => by_weekday,
+            (false, false) => by_day || by_weekday,
+        }
+    }
+
+    /// the first minute after the given one matching the expression, within a few years
+    pub fn next(&self, after: Minute) -> Option<Minute> {
+        let mut minute = Minute(after.0 + 1);
+        let end = after.0 + 5 * 366 * 1440;
+        while minute.0 < end {
+            let (_, month, _) = minute.date();
+            if self.months & 1 << month == 0 || !self.matches_day(minute) {
+                minute = Minute(minute.midnight().0 + 1440);
+            } else if self.matches(minute) {
+                return Some(minute);
+            } else {
+                minute = Minute(minute.0 + 1);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl FromStr for Cron {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err("A cron expression has 5 fields");
+        }
+        let weekdays = field(fields[4], 0, 7, &WEEKDAYS, 0)?;
+        Ok(Cron {
+            expression: fields.join(" "),
+            minutes: field(fields[0], 0, 59, &[], 0)?,
+            hours: field(fields[1], 0, 23, &[], 0)?,
+            days: field(fields[2], 1, 31, &[], 0)?,
+            months: field(fields[3], 1, 12, &MONTHS, 1)?,
+            // sunday is both 0 and 7
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+}
+
+/// days of the week, like the last field of a cron expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weekdays {
+    expression: String,
+    days: u64,
+}
+
+impl Weekdays {
+    /// 0 for sunday
+    pub fn contains(&self, weekday: u8) -> bool {
+        self.days & 1 << weekday != 0
+    }
+}
+
+impl FromStr for Weekdays {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let days = field(s.trim(), 0, 7, &WEEKDAYS, 0)?;
+        Ok(Weekdays { expression: s.trim().to_string(), days: (days | days >> 7) & 0x7f })
+    }
+}
+
+impl When {
+    pub fn matches(&self, minute: Minute, location: Option<Location>) -> bool {
+        match self {
+            When::Cron { cron } => cron.matches(minute),
+            // an offset may move the event to the day before or after
+            When::Sun { .. } => (-1..=1).any(|d| self.on(Minute(minute.0 + d * 1440).date(), location) == Some(minute)),
+        }
+    }
+
+    pub fn next(&self, after: Minute, location: Option<Location>) -> Option<Minute> {
+        match self {
+            When::Cron { cron } => cron.next(after),
+            When::Sun { .. } => (-1..=366)
+                .filter_map(|d| self.on(Minute(after.0 + d * 1440).date(), location))
+                .find(|&minute| minute > after),
+        }
+    }
+
+    /// when a sun schedule is due on a day
+    fn on(&self, date: (i64, u8, u8), location: Option<Location>) -> Option<Minute> {
+        match (self, location) {
+            (When::Sun { sun, offset, not_before, not_after, days }, Some(location)) => {
+                let (year, month, day) = date;
+                let at = |time: TimeOfDay| Minute::new(year, month, day, time);
+                if days.as_ref().map_or(false, |days| !days.contains(at(TimeOfDay { hour: 0, minute: 0 }).weekday())) {
+                    return None;
+                }
+                let event = clock::local(sun::time(*sun, date, location.latitude, location.longitude)?);
+                let mut minute = Minute(event.0 + offset);
+                if let Some(not_before) = not_before {
+                    minute = minute.max(at(*not_before));
+                }
+                if let Some(not_after) = not_after {
+                    minute = minute.min(at(*not_after));
+                }
+                Some(minute)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            When::Cron { cron } => write!(f, "{}", cron),
+            When::Sun { sun, offset, not_before, not_after, days } => {
+                write!(f, "{}", sun)?;
+                if *offset != 0 {
+                    write!(f, " {:+}min", offset)?;
+                }
+                if let Some(not_before) = not_before {
+                    write!(f, ", not before {}", not_before)?;
+                }
+                if let Some(not_after) = not_after {
+                    write!(f, ", not after {}", not_after)?;
+                }
+                if let Some(days) = days {
+                    write!(f, ", on {}", days.expression)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// comma separated values, ranges and steps as bits
+fn field(field: &str, min: u8, max: u8, names: &[&str], first: u8) -> Result<u64, &'static str> {
+    let value = |v: &str| -> Result<u8, &'static str> {
+        let v = names.iter().position(|n| n.eq_ignore_ascii_case(v)).map(|i| i as u8 + first)
+            .or_else(|| v.parse().ok())
+            .ok_or("Invalid cron value")?;
+        if v < min || v > max { Err("Cron value out of range") } else { Ok(v) }
+    };
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u8>().ok().filter(|&s| s > 0).ok_or("Invalid cron step")?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err("Invalid cron range");
+        }
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Planned {
+    pub name: String,
+    pub when: String,
+    pub enabled: bool,
+    /// milliseconds since epoch
+    pub next: Option<u64>,
+}
+
+pub struct Scheduler {
+    schedules: Vec<Schedule>,
+    location: Option<Location>,
+    enabled: Mutex<Vec<bool>>,
+    last: Mutex<Option<Minute>>,
+}
+
+impl Scheduler {
+    pub fn new(schedules: Vec<Schedule>, location: Option<Location>) -> Self {
+        let enabled = schedules.iter().map(|s| s.enabled).collect();
+        Scheduler { schedules, location, enabled: Mutex::new(enabled), last: Mutex::new(None) }
+    }
+
+    /// the schedules due since the previous tick, each wall clock minute is only run once
+    pub fn tick(&self, now: u64) -> Vec<(String, Action)> {
+        let current = clock::local(now);
+        let mut last = self.last.lock().expect("Can't get lock on last tick");
+        let minutes = match *last {
+            Some(l) if l == current => vec![],
+            Some(l) if l < current && current.0 - l.0 <= CATCH_UP => (l.0 + 1..=current.0).map(Minute).collect(),
+            Some(l) if l > current && l.0 - current.0 <= ADJUSTMENT => return vec![],
+            _ => vec![current],
+        };
+        *last = Some(current);
+        let enabled = self.enabled.lock().expect("Can't get lock on schedules");
+        minutes.into_iter()
+            .flat_map(|minute| self.schedules.iter().zip(enabled.iter())
+                .filter(move |(s, &enabled)| enabled && s.when.matches(minute, self.location)))
+            .map(|(s, _)| (s.name.clone(), s.action.clone()))
+            .collect()
+    }
+
+    pub fn planned(&self, now: u64) -> Vec<Planned> {
+        let current = clock::local(now);
+        let enabled = self.enabled.lock().expect("Can't get lock on schedules");
+        self.schedules.iter().zip(enabled.iter()).map(|(s, &enabled)| Planned {
+            name: s.name.clone(),
+            when: s.when.to_string(),
+            enabled,
+            next: if enabled { s.when.next(current, self.location).map(clock::timestamp) } else { None },
+        }).collect()
+    }
+
+    /// false when there is no such schedule
+    pub fn enable(&self, name: &str, enabled: bool) -> bool {
+        match self.schedules.iter().position(|s| s.name == name) {
+            Some(index) => {
+                self.enabled.lock().expect("Can't get lock on schedules")[index] = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// runs the schedules when they are due and the rules following them, for ever
+pub fn run<H>(house: &H, scheduler: &Scheduler, rules: &Rules) where H: House + ?Sized {
+    loop {
+        let now = clock::now();
+        for (name, action) in scheduler.tick(now) {
+            if let Err(e) = trigger::run(house, &action, &serde_json::json!({ "schedule": name })) {
+                events::error(format!("Can't run {} schedule: {}", name, e));
+            }
+            rules.fire(house, &Event::Schedule { name }, now);
+        }
+        let until_next_minute = Duration::from_millis(60_000 - clock::now() % 60_000);
+        sleep(until_next_minute.min(TICK));
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Configuration;
+    use crate::house::Scene;
+    use crate::sun::SunEvent;
+
+    fn minute(year: i64, month: u8, day: u8, time: &str) -> Minute {
+        Minute::new(year, month, day, time.parse::<TimeOfDay>().unwrap())
+    }
+
+    fn scheduler() -> Scheduler {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [location]
+            latitude = 48.8566
+            longitude = 2.3522
+
+            [[schedules]]
+            name = "wake up"
+            cron = "30 7 * * mon-fri"
+            scene = "goodmorning"
+
+            [[schedules]]
+            name = "night"
+            cron = "0 23 * * *"
+            scene = "goodnight"
+            enabled = false
+
+            [[schedules]]
+            name = "blinds up"
+            sun = "sunrise"
+            not_before = "07:00"
+            days = "mon-fri"
+            blinds = "LivingRoom"
+            status = "up"
+
+            [[schedules]]
+            name = "lights on"
+            sun = "dusk"
+            offset = -15
+            light = "LivingRoom"
+            status = "on"
+        "#.parse().unwrap();
+        Scheduler::new(configuration.schedules, configuration.location)
+    }
+
+    #[test]
+    fn parse_cron_expressions() {
+        let cron = "*/15 7-9,18 1 jan-mar 7".parse::<Cron>().unwrap();
+        assert_that!(&cron.minutes, eq(1 | 1 << 15 | 1 << 30 | 1 << 45));
+        assert_that!(&cron.hours, eq(1 << 7 | 1 << 8 | 1 << 9 | 1 << 18));
+        assert_that!(&cron.months, eq(1 << 1 | 1 << 2 | 1 << 3));
+        assert_that!(&cron.weekdays, eq(1));
+        assert_that!(&cron.to_string(), eq("*/15 7-9,18 1 jan-mar 7".to_string()));
+        for invalid in &["* * * *", "60 * * * *", "* * * * funday", "5-1 * * * *", "*/0 * * * *"] {
+            assert_that!(&invalid.parse::<Cron>().is_err(), eq(true));
+        }
+    }
+
+    #[test]
+    fn match_either_day_field_when_both_are_restricted() {
+        let cron = "0 12 13 * fri".parse::<Cron>().unwrap();
+        assert_that!(&cron.matches(minute(2019, 9, 13, "12:00")), eq(true));
+        assert_that!(&cron.matches(minute(2019, 9, 20, "12:00")), eq(true));
+        assert_that!(&cron.matches(minute(2019, 10, 13, "12:00")), eq(true));
+        assert_that!(&cron.matches(minute(2019, 10, 14, "12:00")), eq(false));
+    }
+
+    #[test]
+    fn find_next_runs() {
+        let cron = "30 7 * * mon-fri".parse::<Cron>().unwrap();
+        assert_that!(&cron.next(minute(2019, 2, 15, "07:30")), eq(Some(minute(2019, 2, 18, "07:30"))));
+        assert_that!(&"0 0 29 2 *".parse::<Cron>().unwrap().next(minute(2019, 1, 1, "00:00")), eq(Some(minute(2020, 2, 29, "00:00"))));
+    }
+
+    #[test]
+    fn run_enabled_schedules_once_per_minute() {
+        let scheduler = scheduler();
+        let at = |minute: Minute| clock::timestamp(minute) + 5_000;
+        scheduler.tick(at(minute(2019, 2, 15, "07:29")));
+        let due = scheduler.tick(at(minute(2019, 2, 15, "07:30")));
+        assert_that!(&due, eq(vec![("wake up".to_string(), Action::Scene { scene: Scene::GoodMorning })]));
+        assert_that!(&scheduler.tick(at(minute(2019, 2, 15, "07:30")) + 30_000), eq(vec![]));
+        scheduler.tick(at(minute(2019, 2, 15, "22:59")));
+        assert_that!(&scheduler.tick(at(minute(2019, 2, 15, "23:00"))), eq(vec![]));
+        assert_that!(&scheduler.enable("night", true), eq(true));
+        assert_that!(&scheduler.enable("nap", true), eq(false));
+        scheduler.tick(at(minute(2019, 2, 16, "22:59")));
+        assert_that!(&scheduler.tick(at(minute(2019, 2, 16, "23:00"))).len(), eq(1));
+    }
+
+    #[test]
+    fn catch_up_minutes_skipped_by_the_wall_clock() {
+        let scheduler = scheduler();
+        *scheduler.last.lock().unwrap() = Some(minute(2019, 2, 15, "07:00"));
+        assert_that!(&scheduler.tick(clock::timestamp(minute(2019, 2, 15, "07:45"))).len(), eq(1));
+    }
+
+    #[test]
+    fn not_run_again_when_the_wall_clock_goes_back() {
+        let scheduler = scheduler();
+        *scheduler.last.lock().unwrap() = Some(minute(2019, 2, 15, "08:29"));
+        assert_that!(&scheduler.tick(clock::timestamp(minute(2019, 2, 15, "07:30"))), eq(vec![]));
+        assert_that!(&scheduler.tick(clock::timestamp(minute(2019, 2, 15, "08:30"))), eq(vec![]));
+    }
+
+    #[test]
+    fn plan_next_runs() {
+        let scheduler = scheduler();
+        let planned = scheduler.planned(clock::timestamp(minute(2019, 2, 15, "08:00")));
+        assert_that!(&planned[..2].to_vec(), eq(vec![
+            Planned { name: "wake up".to_string(), when: "30 7 * * mon-fri".to_string(), enabled: true, next: Some(clock::timestamp(minute(2019, 2, 18, "07:30"))) },
+            Planned { name: "night".to_string(), when: "0 23 * * *".to_string(), enabled: false, next: None },
+        ]));
+    }
+
+    #[test]
+    fn follow_the_sun() {
+        let scheduler = scheduler();
+        let paris = scheduler.location;
+        let dusk = clock::local(sun::time(SunEvent::Dusk, (2019, 6, 21), 48.8566, 2.3522).unwrap());
+        let lights_on = &scheduler.schedules[3].when;
+        assert_that!(&lights_on.matches(Minute(dusk.0 - 15), paris), eq(true));
+        assert_that!(&lights_on.matches(dusk, paris), eq(false));
+        assert_that!(&lights_on.to_string(), eq("dusk -15min".to_string()));
+    }
+
+    #[test]
+    fn move_sun_schedules_inside_their_window_and_days() {
+        let scheduler = scheduler();
+        let blinds_up = &scheduler.schedules[2].when;
+        assert_that!(&blinds_up.next(minute(2019, 6, 21, "00:00"), scheduler.location), eq(Some(minute(2019, 6, 21, "07:00"))));
+        assert_that!(&blinds_up.next(minute(2019, 6, 21, "07:00"), scheduler.location), eq(Some(minute(2019, 6, 24, "07:00"))));
+        assert_that!(&blinds_up.to_string(), eq("sunrise, not before 07:00, on mon-fri".to_string()));
+    }
+
+    #[test]
+    fn parse_weekdays() {
+        let weekend = "sat,7".parse::<Weekdays>().unwrap();
+        assert_that!(&(0..7).filter(|&d| weekend.contains(d)).collect::<Vec<_>>(), eq(vec![0, 6]));
+        assert_that!(&"someday".parse::<Weekdays>().is_err(), eq(true));
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::config::{Action, Automation, BinarySensor, SensorCode};
+use crate::ev1527::{Ev1527Message, EV1527_PROTOCOL};
+use crate::clock;
+use crate::rule;
+
+/// sensors that don't tell when they stop, like motion sensors, stay active this long
+const ACTIVE: u64 = 60 * 1000;
+const EVENTS: usize = 100;
+const UNKNOWN: usize = 20;
+
+/// something telling janet what happens in the house, where a House only acts on it
+pub trait Sensor {
+    fn name(&self) -> &str;
+    /// whether the frame tells the sensor became active, None when it isn't from this sensor
+    fn decode(&self, protocol: &str, payload: &[u8]) -> Option<bool>;
+    /// how long the sensor stays active when it doesn't tell when it stops, in milliseconds
+    fn timeout(&self) -> Option<u64>;
+}
+
+impl Sensor for BinarySensor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn decode(&self, protocol: &str, payload: &[u8]) -> Option<bool> {
+        match self.code {
+            SensorCode::Ev1527 { address } if protocol == EV1527_PROTOCOL.name() => {
+                let message = Ev1527Message::parse(payload).filter(|m| m.address() == address)?;
+                if message.data() == self.on {
+                    Some(true)
+                } else if Some(message.data()) == self.off {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn timeout(&self) -> Option<u64> {
+        match self.off {
+            Some(_) => None,
+            None => Some(ACTIVE),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SensorState {
+    pub name: String,
+    pub active: bool,
+    /// milliseconds since epoch
+    pub last_seen: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Event {
+    /// milliseconds since epoch
+    pub timestamp: u64,
+    pub sensor: String,
+    pub active: bool,
+}
+
+/// a code heard from no configured sensor, to learn new ones
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Unknown {
+    pub protocol: String,
+    pub address: u32,
+    pub data: u8,
+    pub last_seen: u64,
+}
+
+pub struct Sensors<S>
+    where S: Sensor {
+    sensors: Vec<S>,
+    automations: Vec<Automation>,
+    heard: Mutex<HashMap<String, (bool, u64)>>,
+    events: Mutex<VecDeque<Event>>,
+    unknown: Mutex<VecDeque<Unknown>>,
+}
+
+impl<S> Sensors<S>
+    where S: Sensor {
+    pub fn new(sensors: Vec<S>, automations: Vec<Automation>) -> Self {
+        Sensors {
+            sensors,
+            automations,
+            heard: Mutex::new(HashMap::new()),
+            events: Mutex::new(VecDeque::new()),
+            unknown: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// updates the sensors the frame is from, returns their changes of state
+    pub fn received(&self, protocol: &str, payload: &[u8], timestamp: u64) -> Vec<Event> {
+        let mut changes = vec![];
+        let mut known = false;
+        for sensor in &self.sensors {
+            if let Some(active) = sensor.decode(protocol, payload) {
+                known = true;
+                let was = self.active(sensor, timestamp);
+                self.heard.lock().expect("Can't get lock on sensors").insert(sensor.name().to_string(), (active, timestamp));
+                if was != active {
+                    let event = Event { timestamp, sensor: sensor.name().to_string(), active };
+                    let mut events = self.events.lock().expect("Can't get lock on events");
+                    events.push_back(event.clone());
+                    if events.len() > EVENTS {
+                        events.pop_front();
+                    }
+                    changes.push(event);
+                }
+            }
+        }
+        if !known && protocol == EV1527_PROTOCOL.name() {
+            if let Some(message) = Ev1527Message::parse(payload) {
+                let mut unknown = self.unknown.lock().expect("Can't get lock on unknown codes");
+                unknown.retain(|u| (u.address, u.data) != (message.address(), message.data()));
+                unknown.push_back(Unknown { protocol: protocol.to_string(), address: message.address(), data: message.data(), last_seen: timestamp });
+                if unknown.len() > UNKNOWN {
+                    unknown.pop_front();
+                }
+            }
+        }
+        changes
+    }
+
+    fn active(&self, sensor: &S, now: u64) -> bool {
+        match self.heard.lock().expect("Can't get lock on sensors").get(sensor.name()) {
+            Some(&(active, at)) => active && sensor.timeout().map_or(true, |timeout| now < at + timeout),
+            None => false,
+        }
+    }
+
+    /// the actions of the automations triggered by a change of state
+    pub fn automations(&self, event: &Event) -> Vec<Action> {
+        let time = clock::local_time(event.timestamp);
+        let event = rule::Event::Sensor { sensor: event.sensor.clone(), active: event.active };
+        self.automations.iter().filter(|a| a.condition.becomes_true(&event, time)).map(|a| a.action.clone()).collect()
+    }
+
+    pub fn states(&self, now: u64) -> Vec<SensorState> {
+        self.sensors.iter().map(|sensor| SensorState {
+            name: sensor.name().to_string(),
+            active: self.active(sensor, now),
+            last_seen: self.heard.lock().expect("Can't get lock on sensors").get(sensor.name()).map(|&(_, at)| at),
+        }).collect()
+    }
+
+    /// latest changes of state, oldest first
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().expect("Can't get lock on events").iter().cloned().collect()
+    }
+
+    pub fn unknown(&self) -> Vec<Unknown> {
+        self.unknown.lock().expect("Can't get lock on unknown codes").iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Configuration;
+    use crate::house::{Room, LightStatus};
+
+    fn sensors(after: &str, before: &str) -> Sensors<BinarySensor> {
+        let configuration: Configuration = format!(r#"
+            radios = []
+            devices = []
+
+            [[sensors]]
+            name = "front door"
+            kind = "door"
+            protocol = "ev1527"
+            address = 0x12345
+            on = 0xa
+            off = 0xe
+
+            [[sensors]]
+            name = "kitchen motion"
+            kind = "motion"
+            room = "Kitchen"
+            protocol = "ev1527"
+            address = 0x5a3c1
+            on = 0x8
+
+            [[automations]]
+            sensor = "kitchen motion"
+            {}
+            {}
+            light = "Kitchen"
+            status = "on"
+        "#, after, before).parse().unwrap();
+        Sensors::new(configuration.sensors, configuration.automations)
+    }
+
+    fn actions(sensors: &Sensors<BinarySensor>, payload: &[u8], timestamp: u64) -> Vec<Action> {
+        sensors.received("ev1527", payload, timestamp).iter().flat_map(|e| sensors.automations(e)).collect()
+    }
+
+    #[test]
+    fn follow_contacts() {
+        let sensors = sensors("", "");
+        sensors.received("ev1527", &[0x12, 0x34, 0x5a], 1000);
+        assert_that!(&sensors.states(2000)[0], eq(SensorState { name: "front door".to_string(), active: true, last_seen: Some(1000) }));
+        sensors.received("ev1527", &[0x12, 0x34, 0x5e], 3000);
+        assert_that!(&sensors.states(ACTIVE * 2)[0].active, eq(false));
+        assert_that!(&sensors.events(), eq(vec![
+            Event { timestamp: 1000, sensor: "front door".to_string(), active: true },
+            Event { timestamp: 3000, sensor: "front door".to_string(), active: false },
+        ]));
+    }
+
+    #[test]
+    fn forget_motion_after_a_while() {
+        let sensors = sensors("", "");
+        let first = actions(&sensors, &[0x5a, 0x3c, 0x18], 0);
+        assert_that!(&first, eq(vec![Action::Light { light: Room::Kitchen, status: LightStatus::ON }]));
+        assert_that!(&actions(&sensors, &[0x5a, 0x3c, 0x18], 1000), eq(vec![]));
+        assert_that!(&sensors.states(ACTIVE)[1].active, eq(true));
+        assert_that!(&sensors.states(ACTIVE + 1000)[1].active, eq(false));
+        assert_that!(&actions(&sensors, &[0x5a, 0x3c, 0x18], ACTIVE + 1000).len(), eq(1));
+    }
+
+    #[test]
+    fn run_automations_during_their_time_of_day() {
+        let now = 1_550_000_000_000;
+        let time = clock::local_time(now);
+        let outside = sensors("", &format!("before = \"{}\"", time));
+        assert_that!(&actions(&outside, &[0x5a, 0x3c, 0x18], now), eq(vec![]));
+        let inside = sensors(&format!("after = \"{}\"", time), "");
+        assert_that!(&actions(&inside, &[0x5a, 0x3c, 0x18], now).len(), eq(1));
+    }
+
+    #[test]
+    fn list_unknown_codes_to_learn_them() {
+        let sensors = sensors("", "");
+        sensors.received("ev1527", &[0xca, 0xfe, 0x12], 1000);
+        sensors.received("ev1527", &[0xca, 0xfe, 0x12], 2000);
+        sensors.received("dio", &[0x27, 0x13, 0x37, 0x90], 2000);
+        assert_that!(&sensors.unknown(), eq(vec![Unknown { protocol: "ev1527".to_string(), address: 0xcafe1, data: 0x2, last_seen: 2000 }]));
+    }
+}
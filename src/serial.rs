@@ -0,0 +1,189 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use crate::radio::{Radio, Signal};
+use crate::radio_protocol::RadioProtocol;
+use crate::events;
+use crate::hex;
+
+/// A USB RF gateway (RFLink like) speaking a line protocol over a serial port.
+///
+/// Commands are `10;<protocol>;<payload>;` for the protocols the gateway knows
+/// and `10;RAW;<pulses>;` otherwise, where pulses are `+` for high and `-` for low
+/// durations in microseconds. Received frames come back the same way prefixed by `20;<counter>;`.
+pub struct SerialGateway {
+    port: File,
+    protocols: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Frame {
+    Decoded { protocol: String, payload: Vec<u8> },
+    Raw(Vec<Signal>),
+}
+
+pub struct Frames {
+    reader: BufReader<File>,
+}
+
+impl SerialGateway {
+    pub fn open<P: AsRef<Path>>(path: P, baud: u32, protocols: Vec<String>) -> io::Result<Self> {
+        let port = OpenOptions::new().read(true).write(true).open(path)?;
+        configure(&port, baud)?;
+        Ok(SerialGateway { port, protocols })
+    }
+
+    /// received frames, lines the gateway sends that aren't frames are skipped
+    pub fn frames(&self) -> io::Result<Frames> {
+        Ok(Frames { reader: BufReader::new(self.port.try_clone()?) })
+    }
+
+    fn command(&self, protocol: &str, bytes: &[u8], timings: Vec<Signal>) -> String {
+        if self.protocols.iter().any(|p| p == protocol) {
+            format!("10;{};{};\r\n", protocol, hex::encode(bytes))
+        } else {
+            format!("10;RAW;{};\r\n", timings.iter().map(Signal::to_string).collect::<Vec<_>>().join(","))
+        }
+    }
+}
+
+impl Radio for SerialGateway {
+    unsafe fn send<M>(&self, message: M, protocol: &RadioProtocol<M>) where M: IntoIterator<Item=u8> {
+        let bytes = message.into_iter().collect::<Vec<u8>>();
+        let command = self.command(protocol.name(), &bytes, protocol.timings_for_bytes(&bytes));
+        if let Err(e) = (&self.port).write_all(command.as_bytes()) {
+            events::error(format!("Can't write to gateway: {}", e));
+        }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => if let Some(frame) = parse_frame(line.trim()) {
+                    return Some(Ok(frame));
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn configure(port: &File, baud: u32) -> io::Result<()> {
+    let speed = match baud {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115_200 => libc::B115200,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported baud rate")),
+    };
+    let fd = port.as_raw_fd();
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        libc::cfsetspeed(&mut termios, speed);
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn parse_frame(line: &str) -> Option<Frame> {
+    let fields = line.trim_end_matches(';').split(';').collect::<Vec<_>>();
+    match fields.as_slice() {
+        ["20", _, "RAW", pulses] => pulses.split(',').map(|p| p.parse().ok()).collect::<Option<_>>().map(Frame::Raw),
+        ["20", _, protocol, payload] => hex::decode(payload).map(|payload| Frame::Decoded { protocol: protocol.to_string(), payload }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::ffi::CStr;
+    use std::time::Duration;
+    use crate::radio_protocol::*;
+
+    fn pty() -> (File, String) {
+        let mut master = 0;
+        let mut slave = 0;
+        let mut name = [0 as libc::c_char; 64];
+        unsafe {
+            assert_eq!(libc::openpty(&mut master, &mut slave, name.as_mut_ptr(), std::ptr::null(), std::ptr::null()), 0);
+            libc::close(slave);
+            (File::from_raw_fd(master), CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    fn protocol() -> RadioProtocol<Vec<u8>> {
+        RadioProtocol::new("test",
+                           Header(vec![Signal::HIGH(Duration::from_micros(13))]),
+                           Footer(vec![Signal::LOW(Duration::from_micros(37))]),
+                           Zero(vec![Signal::LOW(Duration::from_micros(2))]),
+                           One(vec![Signal::HIGH(Duration::from_micros(1))]),
+                           1)
+    }
+
+    fn read_line(device: &mut File) -> String {
+        let mut line = vec![];
+        let mut byte = [0];
+        while line.last() != Some(&b'\n') {
+            device.read_exact(&mut byte).unwrap();
+            line.push(byte[0]);
+        }
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn send_raw_pulses_for_unknown_protocols() {
+        let (mut device, path) = pty();
+        let gateway = SerialGateway::open(path, 57600, vec![]).unwrap();
+        unsafe {
+            gateway.send(vec![0x81], &protocol());
+        }
+        assert_that!(&read_line(&mut device), eq("10;RAW;+13,+1,-2,-2,-2,-2,-2,-2,+1,-37;\r\n".to_string()));
+    }
+
+    #[test]
+    fn send_protocol_commands_for_known_protocols() {
+        let (mut device, path) = pty();
+        let gateway = SerialGateway::open(path, 57600, vec!["test".to_string()]).unwrap();
+        unsafe {
+            gateway.send(vec![0x27, 0x13, 0x37, 0x90], &protocol());
+        }
+        assert_that!(&read_line(&mut device), eq("10;test;27133790;\r\n".to_string()));
+    }
+
+    #[test]
+    fn read_received_frames() {
+        let (mut device, path) = pty();
+        let gateway = SerialGateway::open(path, 57600, vec![]).unwrap();
+        device.write_all(b"20;00;Nodo RadioFrameLink - RFLink Gateway V1.1 - R46;\r\n20;01;dio;27133790;\r\n20;02;RAW;+283,-2793;\r\n").unwrap();
+        let frames = gateway.frames().unwrap().take(2).map(|f| f.unwrap()).collect::<Vec<_>>();
+        assert_that!(&frames, eq(vec![
+            Frame::Decoded { protocol: "dio".to_string(), payload: vec![0x27, 0x13, 0x37, 0x90] },
+            Frame::Raw(vec![Signal::HIGH(Duration::from_micros(283)), Signal::LOW(Duration::from_micros(2793))]),
+        ]));
+    }
+
+    #[test]
+    fn reject_unsupported_baud_rates() {
+        let (_device, path) = pty();
+        assert_that!(&SerialGateway::open(path, 42, vec![]).is_err(), eq(true));
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use serde::Deserialize;
+use crate::clock::{Minute, TimeOfDay};
+
+/// julian day of 1970-01-01 00:00 UTC
+const UNIX_EPOCH: f64 = 2_440_587.5;
+const J2000: f64 = 2_451_545.0;
+const OBLIQUITY: f64 = 23.4397;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SunEvent {
+    /// civil dawn, the sun is 6° below the horizon
+    Dawn,
+    Sunrise,
+    Sunset,
+    /// civil dusk, the sun is 6° below the horizon
+    Dusk,
+}
+
+impl SunEvent {
+    /// elevation of the center of the sun, refraction included
+    fn elevation(self) -> f64 {
+        match self {
+            SunEvent::Dawn | SunEvent::Dusk => -6.0,
+            SunEvent::Sunrise | SunEvent::Sunset => -0.833,
+        }
+    }
+
+    fn rising(self) -> bool {
+        matches!(self, SunEvent::Dawn | SunEvent::Sunrise)
+    }
+}
+
+impl fmt::Display for SunEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// timestamp in milliseconds of an event on a day, none when the sun doesn't get there that day,
+/// within a minute or so, see https://en.wikipedia.org/wiki/Sunrise_equation
+pub fn time(event: SunEvent, date: (i64, u8, u8), latitude: f64, longitude: f64) -> Option<u64> {
+    let (year, month, day) = date;
+    let days = Minute::new(year, month, day, TimeOfDay { hour: 12, minute: 0 }).0 as f64 / 1440.0;
+    let noon = (days + UNIX_EPOCH - J2000).round() - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * noon).rem_euclid(360.0).to_radians();
+    let center = 1.9148 * anomaly.sin() + 0.0200 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+    let transit = J2000 + noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+    let declination = (ecliptic.sin() * OBLIQUITY.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let hour_angle = (event.elevation().to_radians().sin() - latitude.sin() * declination.sin()) / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&hour_angle) {
+        return None;
+    }
+    let half_day = hour_angle.acos().to_degrees() / 360.0;
+    let julian = if event.rising() { transit - half_day } else { transit + half_day };
+    Some(((julian - UNIX_EPOCH) * 86_400_000.0).round() as u64)
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+
+    const PARIS: (f64, f64) = (48.8566, 2.3522);
+
+    /// minutes since epoch in UTC
+    fn utc(year: i64, month: u8, day: u8, time: &str) -> i64 {
+        Minute::new(year, month, day, time.parse().unwrap()).0
+    }
+
+    fn minutes(timestamp: Option<u64>) -> i64 {
+        (timestamp.unwrap() / 60_000) as i64
+    }
+
+    #[test]
+    fn compute_sunrise_and_sunset() {
+        let date = (2019, 6, 21);
+        let sunrise = minutes(time(SunEvent::Sunrise, date, PARIS.0, PARIS.1));
+        let sunset = minutes(time(SunEvent::Sunset, date, PARIS.0, PARIS.1));
+        assert_that!(&(sunrise - utc(2019, 6, 21, "03:47")).abs(), lt(2));
+        assert_that!(&(sunset - utc(2019, 6, 21, "19:58")).abs(), lt(2));
+    }
+
+    #[test]
+    fn compute_civil_twilight() {
+        let date = (2019, 12, 21);
+        let dawn = minutes(time(SunEvent::Dawn, date, PARIS.0, PARIS.1));
+        let dusk = minutes(time(SunEvent::Dusk, date, PARIS.0, PARIS.1));
+        assert_that!(&(dawn - utc(2019, 12, 21, "07:03")).abs(), lt(2));
+        assert_that!(&(dusk - utc(2019, 12, 21, "16:34")).abs(), lt(2));
+    }
+
+    #[test]
+    fn find_no_sunset_during_polar_days() {
+        assert_that!(&time(SunEvent::Sunset, (2019, 6, 21), 78.2232, 15.6267), eq(None));
+    }
+}
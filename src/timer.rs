@@ -0,0 +1,265 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::config::Action;
+use crate::clock;
+use crate::house::{BlindStatus, House, LightStatus};
+use crate::trigger;
+use crate::events;
+
+const TICK: Duration = Duration::from_secs(1);
+
+/// an action to run later
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Timer {
+    pub id: u64,
+    /// milliseconds since epoch
+    pub at: u64,
+    pub action: Action,
+}
+
+/// Pending timers, saved to a file on every change so they survive a restart.
+pub struct Timers {
+    path: Option<PathBuf>,
+    timers: Mutex<Vec<Timer>>,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers { path: None, timers: Mutex::new(vec![]) }
+    }
+
+    /// timers saved in the file, none when it doesn't exist yet,
+    /// an unreadable file is moved aside to `.corrupt` rather than overwritten
+    pub fn load<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let timers = match fs::read(&path).map(|json| serde_json::from_slice(&json)) {
+            Ok(Ok(timers)) => timers,
+            Ok(Err(e)) => {
+                let aside = path.with_extension("corrupt");
+                fs::rename(&path, &aside)?;
+                events::error(format!("Can't read timers, {} was moved to {}: {}", path.display(), aside.display(), e));
+                vec![]
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(Timers { path: Some(path), timers: Mutex::new(timers) })
+    }
+
+    pub fn list(&self) -> Vec<Timer> {
+        self.timers.lock().expect("Can't get lock on timers").clone()
+    }
+
+    pub fn add(&self, at: u64, action: Action) -> io::Result<Timer> {
+        let mut timers = self.timers.lock().expect("Can't get lock on timers");
+        let id = timers.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+        let timer = Timer { id, at, action };
+        timers.push(timer.clone());
+        timers.sort_by_key(|t| t.at);
+        self.save(&timers)?;
+        Ok(timer)
+    }
+
+    /// false when there is no such timer
+    pub fn cancel(&self, id: u64) -> io::Result<bool> {
+        let mut timers = self.timers.lock().expect("Can't get lock on timers");
+        let count = timers.len();
+        timers.retain(|t| t.id != id);
+        if timers.len() == count {
+            return Ok(false);
+        }
+        self.save(&timers)?;
+        Ok(true)
+    }
+
+    /// for an order now, during and after some time: the action to run at once, if any,
+    /// the others become timers
+    pub fn order(&self, now: u64, action: Action, after: Option<Duration>, during: Option<Duration>) -> io::Result<Option<Action>> {
+        let millis = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+        let start = now.saturating_add(after.map_or(0, millis));
+        if let (Some(during), Some(undo)) = (during, undo(&action)) {
+            self.add(start.saturating_add(millis(during)), undo)?;
+        }
+        match after {
+            Some(_) => self.add(start, action).map(|_| None),
+            None => Ok(Some(action)),
+        }
+    }
+
+    /// removes and returns the actions of the timers that are due
+    pub fn due(&self, now: u64) -> io::Result<Vec<Action>> {
+        let mut timers = self.timers.lock().expect("Can't get lock on timers");
+        if !timers.iter().any(|t| t.at <= now) {
+            return Ok(vec![]);
+        }
+        let (due, pending) = timers.drain(..).partition::<Vec<_>, _>(|t| t.at <= now);
+        *timers = pending;
+        self.save(&timers)?;
+        Ok(due.into_iter().map(|t| t.action).collect())
+    }
+
+    /// writes a temporary file first, a crash while saving can't lose every timer
+    fn save(&self, timers: &[Timer]) -> io::Result<()> {
+        match &self.path {
+            Some(path) => {
+                let temporary = path.with_extension("tmp");
+                fs::write(&temporary, serde_json::to_vec(timers)?)?;
+                fs::rename(temporary, path)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Timers::new()
+    }
+}
+
+/// the action putting things back as they were, scenes and webhooks can't be undone
+fn undo(action: &Action) -> Option<Action> {
+    match *action {
+        Action::Light { light, status: LightStatus::ON } => Some(Action::Light { light, status: LightStatus::OFF }),
+        Action::Light { light, status: LightStatus::OFF } => Some(Action::Light { light, status: LightStatus::ON }),
+        Action::Blinds { blinds, status: BlindStatus::UP } => Some(Action::Blinds { blinds, status: BlindStatus::DOWN }),
+        Action::Blinds { blinds, status: BlindStatus::DOWN } => Some(Action::Blinds { blinds, status: BlindStatus::UP }),
+        Action::Screen { screen: BlindStatus::UP } => Some(Action::Screen { screen: BlindStatus::DOWN }),
+        Action::Screen { screen: BlindStatus::DOWN } => Some(Action::Screen { screen: BlindStatus::UP }),
+        Action::Scene { .. } | Action::Webhook { .. } => None,
+    }
+}
+
+/// a duration written like `parse_duration` reads it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Delay(pub Duration);
+
+impl FromStr for Delay {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_duration(s).map(Delay)
+    }
+}
+
+/// `90s`, `20m`, `2h`, `1h30m` or a number of minutes
+pub fn parse_duration(s: &str) -> Result<Duration, &'static str> {
+    let s = s.trim();
+    if let Ok(minutes) = s.parse::<u64>() {
+        return minutes.checked_mul(60).map(Duration::from_secs).ok_or("Invalid duration");
+    }
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            's' | 'm' | 'h' if !number.is_empty() => {
+                let unit = match c { 's' => 1, 'm' => 60, _ => 3600 };
+                seconds = number.parse::<u64>().ok()
+                    .and_then(|n| n.checked_mul(unit))
+                    .and_then(|n| n.checked_add(seconds))
+                    .ok_or("Invalid duration")?;
+                number.clear();
+            }
+            _ => return Err("Invalid duration"),
+        }
+    }
+    if s.is_empty() || !number.is_empty() { Err("Invalid duration") } else { Ok(Duration::from_secs(seconds)) }
+}
+
+/// runs the timers when they are due, for ever
+pub fn run<H>(house: &H, timers: &Timers) where H: House + ?Sized {
+    loop {
+        match timers.due(clock::now()) {
+            Ok(actions) => for action in actions {
+                if let Err(e) = trigger::run(house, &action, &serde_json::json!({ "timer": true })) {
+                    events::error(format!("Can't run {:?}: {}", action, e));
+                }
+            },
+            Err(e) => events::error(format!("Can't save timers: {}", e)),
+        }
+        sleep(TICK);
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::env;
+    use crate::house::Room;
+
+    const KITCHEN_ON: Action = Action::Light { light: Room::Kitchen, status: LightStatus::ON };
+    const KITCHEN_OFF: Action = Action::Light { light: Room::Kitchen, status: LightStatus::OFF };
+
+    #[test]
+    fn parse_durations() {
+        assert_that!(&parse_duration("20"), eq(Ok(Duration::from_secs(20 * 60))));
+        assert_that!(&parse_duration("90s"), eq(Ok(Duration::from_secs(90))));
+        assert_that!(&parse_duration("1h30m"), eq(Ok(Duration::from_secs(5400))));
+        for invalid in &["", "m", "2d", "1h30", "307445734561825861", "5124095576030432h", "18446744073709551615s1s"] {
+            assert_that!(&parse_duration(invalid).is_err(), eq(true));
+        }
+    }
+
+    #[test]
+    fn switch_off_after_a_while() {
+        let timers = Timers::new();
+        let now = 1_000_000;
+        let now_action = timers.order(now, KITCHEN_ON, None, Some(Duration::from_secs(20 * 60))).unwrap();
+        assert_that!(&now_action, eq(Some(KITCHEN_ON)));
+        assert_that!(&timers.due(now + 20 * 60_000 - 1).unwrap(), eq(vec![]));
+        assert_that!(&timers.due(now + 20 * 60_000).unwrap(), eq(vec![KITCHEN_OFF]));
+        assert_that!(&timers.list(), eq(vec![]));
+    }
+
+    #[test]
+    fn delay_orders() {
+        let timers = Timers::new();
+        let screen = Action::Screen { screen: BlindStatus::UP };
+        assert_that!(&timers.order(0, screen.clone(), Some(Duration::from_secs(7200)), None).unwrap(), eq(None));
+        assert_that!(&timers.list(), eq(vec![Timer { id: 1, at: 7_200_000, action: screen }]));
+    }
+
+    #[test]
+    fn cancel_timers() {
+        let timers = Timers::new();
+        let timer = timers.add(10, KITCHEN_OFF).unwrap();
+        assert_that!(&timers.cancel(timer.id + 1).unwrap(), eq(false));
+        assert_that!(&timers.cancel(timer.id).unwrap(), eq(true));
+        assert_that!(&timers.due(10).unwrap(), eq(vec![]));
+    }
+
+    #[test]
+    fn survive_restarts() {
+        let path = env::temp_dir().join(format!("janet_timers_{}.json", std::process::id()));
+        let timers = Timers::load(&path).unwrap();
+        timers.add(10, KITCHEN_OFF).unwrap();
+        timers.add(20, Action::Blinds { blinds: Room::BedRoom, status: BlindStatus::DOWN }).unwrap();
+        let restarted = Timers::load(&path).unwrap();
+        assert_that!(&restarted.list(), eq(timers.list()));
+        restarted.due(10).unwrap();
+        assert_that!(&Timers::load(&path).unwrap().list().len(), eq(1));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keep_saving_when_the_file_is_unreadable() {
+        let path = env::temp_dir().join(format!("janet_corrupt_timers_{}.json", std::process::id()));
+        fs::write(&path, "[{").unwrap();
+        let timers = Timers::load(&path).unwrap();
+        assert_that!(&timers.list(), eq(vec![]));
+        assert_that!(&fs::read_to_string(path.with_extension("corrupt")).unwrap(), eq("[{".to_string()));
+        timers.add(10, KITCHEN_OFF).unwrap();
+        assert_that!(&Timers::load(&path).unwrap().list(), eq(timers.list()));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("corrupt")).unwrap();
+    }
+}
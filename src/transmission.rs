@@ -0,0 +1,142 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::config::{Button, Code};
+use crate::hex;
+use crate::house::House;
+use crate::radio::Signal;
+use crate::radio_protocol::{self, RadioProtocol};
+use crate::trigger;
+
+/// longest a transmission may keep the radio busy, remotes send for less than a second
+const AIRTIME: Duration = Duration::from_secs(2);
+
+/// a protocol janet knows by its name, or defined on the spot
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Protocol {
+    Named(String),
+    Defined(RadioProtocol<Vec<u8>>),
+}
+
+/// bytes sent as they are, the body of the transmit api
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Transmission {
+    pub protocol: Protocol,
+    /// in hexadecimal
+    pub payload: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radio: Option<String>,
+}
+
+/// how a payload went on air
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Transmitted {
+    pub protocol: String,
+    pub signals: Vec<Signal>,
+}
+
+impl Transmission {
+    /// what a remote with this code sends when the button is pressed
+    pub fn frame(code: Code, button: Button, radio: Option<String>) -> Self {
+        let (protocol, payload) = trigger::frame(code, button);
+        Transmission { protocol: Protocol::Named(protocol.to_string()), payload: hex::encode(&payload), radio }
+    }
+
+    /// protocols defined on the spot are raw, gateways would otherwise send them with their own timings
+    pub fn transmit<H>(&self, house: &H) -> Result<Transmitted, String> where H: House + ?Sized {
+        let (protocol, payload) = self.on_air(|name| house.protocol(name))?;
+        house.transmit(self.radio.as_deref(), &protocol, &payload)?;
+        Ok(Transmitted { protocol: protocol.name().to_string(), signals: protocol.timings_for_bytes(&payload) })
+    }
+
+    /// the protocol and the payload, once checked they can go on air, named protocols are found with `protocol`
+    pub fn on_air<F>(&self, protocol: F) -> Result<(RadioProtocol<Vec<u8>>, Vec<u8>), String>
+        where F: Fn(&str) -> Option<RadioProtocol<Vec<u8>>> {
+        let protocol = match &self.protocol {
+            Protocol::Named(name) => protocol(name).ok_or_else(|| format!("Unknown protocol {}", name))?,
+            Protocol::Defined(protocol) => protocol.clone().named(radio_protocol::RAW),
+        };
+        protocol.check()?;
+        let payload = hex::decode(&self.payload).ok_or("The payload should be hexadecimal bytes")?;
+        if protocol.timings_for_bytes(&payload).iter().map(|&s| match s { Signal::HIGH(d) | Signal::LOW(d) => d }).sum::<Duration>() > AIRTIME {
+            return Err(format!("The transmission would take longer than {}s", AIRTIME.as_secs()));
+        }
+        Ok((protocol, payload))
+    }
+}
+
+/// `dio` needs the address, in hexadecimal when it starts with `0x`
+pub fn code(protocol: &str, address: Option<&str>) -> Result<Code, &'static str> {
+    match (protocol.to_lowercase().as_str(), address) {
+        ("dio", Some(address)) => {
+            let address = match address.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => address.parse(),
+            };
+            Ok(Code::Dio { address: address.map_err(|_| "Invalid address")? })
+        }
+        ("dio", None) => Err("Dio needs an address"),
+        ("dooya", _) => Ok(Code::Dooya),
+        ("celexon", _) => Ok(Code::Celexon),
+        _ => Err("Unknown protocol"),
+    }
+}
+
+pub fn button(status: &str) -> Result<Button, &'static str> {
+    match status.to_lowercase().as_str() {
+        "on" => Ok(Button::On),
+        "off" => Ok(Button::Off),
+        "up" => Ok(Button::Up),
+        "down" => Ok(Button::Down),
+        _ => Err("Unknown status"),
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::dio::DIO_PROTOCOL;
+    use crate::house::MyHouse;
+    use crate::radio::mock::InMemoryRadio;
+    use crate::radio_protocol::{Footer, Header, One, Zero};
+
+    fn house() -> MyHouse<InMemoryRadio> {
+        MyHouse::new(vec![("433".to_string(), InMemoryRadio::new())].into_iter().collect(), vec![])
+    }
+
+    fn defined(repetition: u8) -> Transmission {
+        with_header(4800, repetition)
+    }
+
+    fn with_header(high: u64, repetition: u8) -> Transmission {
+        let microseconds = |high, low| vec![Signal::HIGH(Duration::from_micros(high)), Signal::LOW(Duration::from_micros(low))];
+        let protocol = RadioProtocol::new("dio", Header(microseconds(high, 1500)), Footer(microseconds(350, 8600)),
+                                          Zero(microseconds(350, 750)), One(microseconds(700, 400)), repetition);
+        Transmission { protocol: Protocol::Defined(protocol), payload: "0641DFD133".to_string(), radio: None }
+    }
+
+    #[test]
+    fn transmit_any_payload_and_tell_how() {
+        let house = house();
+        let transmission = |protocol: &str, payload: &str| Transmission { protocol: Protocol::Named(protocol.to_string()), payload: payload.to_string(), radio: None };
+        let transmitted = transmission("dio", "27133790").transmit(&house).unwrap();
+        assert_that!(&transmitted.protocol, eq("dio".to_string()));
+        assert_that!(&transmitted.signals, eq(DIO_PROTOCOL.timings_for_bytes(&[0x27, 0x13, 0x37, 0x90])));
+        assert_that!(&transmission("dio", "27133").transmit(&house), eq(Err("The payload should be hexadecimal bytes".to_string())));
+        assert_that!(&transmission("somfy", "42").transmit(&house), eq(Err("Unknown protocol somfy".to_string())));
+    }
+
+    #[test]
+    fn transmit_protocols_defined_on_the_spot_as_raw() {
+        let transmitted = defined(10).transmit(&house()).unwrap();
+        assert_that!(&transmitted.protocol, eq(radio_protocol::RAW.to_string()));
+    }
+
+    #[test]
+    fn refuse_transmissions_which_could_not_be_received() {
+        assert_that!(&defined(0).transmit(&house()), eq(Err("The raw protocol should be sent at least once".to_string())));
+        assert_that!(&defined(255).transmit(&house()), eq(Err("The transmission would take longer than 2s".to_string())));
+        assert_that!(&with_header(50, 10).transmit(&house()), eq(Err("The raw protocol has signals shorter than 100µs".to_string())));
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::{Action, Button, Code, Trigger};
+use crate::house::House;
+use crate::http;
+use crate::dio::{DioMessage, DIO_PROTOCOL};
+use crate::dio;
+use crate::dooya::DOOYA_PROTOCOL;
+use crate::dooya;
+use crate::celexon::CELEXON_PROTOCOL;
+use crate::celexon;
+
+/// remotes repeat a frame while the button is held, a press ends after this much silence
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+    heard: Mutex<HashMap<(String, Vec<u8>), Instant>>,
+}
+
+impl Triggers {
+    pub fn new(triggers: Vec<Trigger>) -> Self {
+        Triggers { triggers, heard: Mutex::new(HashMap::new()) }
+    }
+
+    /// actions of the buttons sending this frame, repetitions of a same press only count once
+    pub fn pressed(&self, protocol: &str, payload: &[u8], at: Instant) -> Vec<Action> {
+        let mut heard = self.heard.lock().expect("Can't get lock on heard frames");
+        heard.retain(|_, last| at.saturating_duration_since(*last) < DEBOUNCE);
+        if heard.insert((protocol.to_string(), payload.to_vec()), at).is_some() {
+            return vec![];
+        }
+        self.triggers.iter()
+            .filter(|t| frame(t.code, t.button) == (protocol, payload.to_vec()))
+            .map(|t| t.action.clone())
+            .collect()
+    }
+}
+
+/// what a remote with this code sends when the button is pressed
+pub fn frame(code: Code, button: Button) -> (&'static str, Vec<u8>) {
+    match (code, button) {
+        (Code::Dio { address }, Button::On) | (Code::Dio { address }, Button::Down) =>
+            (DIO_PROTOCOL.name(), DioMessage::new(address, dio::Status::ON).into_iter().collect()),
+        (Code::Dio { address }, _) =>
+            (DIO_PROTOCOL.name(), DioMessage::new(address, dio::Status::OFF).into_iter().collect()),
+        (Code::Celexon, Button::Down) => (CELEXON_PROTOCOL.name(), celexon::Status::DOWN.into_iter().collect()),
+        (Code::Celexon, _) => (CELEXON_PROTOCOL.name(), celexon::Status::UP.into_iter().collect()),
+        (Code::Dooya, Button::Down) => (DOOYA_PROTOCOL.name(), dooya::Status::DOWN.into_iter().collect()),
+        (Code::Dooya, _) => (DOOYA_PROTOCOL.name(), dooya::Status::UP.into_iter().collect()),
+    }
+}
+
+/// webhooks are posted the event that triggered them
+pub fn run<H>(house: &H, action: &Action, event: &serde_json::Value) -> io::Result<()> where H: House + ?Sized {
+    match action {
+        Action::Scene { scene } => house.scene(*scene),
+        Action::Light { light, status } => house.light(*light, *status),
+        Action::Blinds { blinds, status } => house.blinds(*blinds, *status),
+        Action::Screen { screen } => house.screen(*screen),
+        Action::Webhook { webhook } => return post(webhook, &event.to_string()),
+    }
+    Ok(())
+}
+
+fn post(url: &str, body: &str) -> io::Result<()> {
+    let response = http::Client::new(url, None)?.request("POST", "", &[], "application/json", body)?;
+    match response.code() {
+        Some(200..=299) => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::Other, format!("Webhook answered {}", response.status))),
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::house::Scene;
+
+    fn triggers() -> Triggers {
+        let configuration: crate::config::Configuration = r#"
+            radios = []
+            devices = []
+
+            [[triggers]]
+            protocol = "dio"
+            address = 0xbeef
+            button = "on"
+            scene = "cinema"
+
+            [[triggers]]
+            protocol = "dio"
+            address = 0xbeef
+            button = "off"
+            scene = "goodnight"
+        "#.parse().unwrap();
+        Triggers::new(configuration.triggers)
+    }
+
+    #[test]
+    fn map_frames_to_actions() {
+        let triggers = triggers();
+        let now = Instant::now();
+        assert_that!(&triggers.pressed("dio", &[0x27, 0xbe, 0xef, 0x90], now), eq(vec![Action::Scene { scene: Scene::Cinema }]));
+        assert_that!(&triggers.pressed("dio", &[0x27, 0xbe, 0xef, 0x80], now), eq(vec![Action::Scene { scene: Scene::GoodNight }]));
+        assert_that!(&triggers.pressed("dio", &[0x27, 0x13, 0x37, 0x90], now), eq(vec![]));
+    }
+
+    #[test]
+    fn debounce_repeated_frames() {
+        let triggers = triggers();
+        let start = Instant::now();
+        let on = [0x27, 0xbe, 0xef, 0x90];
+        let presses = (0..10).map(|i| triggers.pressed("dio", &on, start + Duration::from_millis(i * 80)).len()).sum::<usize>();
+        assert_that!(&presses, eq(1));
+        let again = triggers.pressed("dio", &on, start + Duration::from_millis(9 * 80) + DEBOUNCE);
+        assert_that!(&again.len(), eq(1));
+    }
+
+    #[test]
+    fn post_webhooks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/remote", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut chunk = [0; 1024];
+            while request.last() != Some(&b'}') {
+                let length = stream.read(&mut chunk).unwrap();
+                request.extend_from_slice(&chunk[..length]);
+            }
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        post(&url, r#"{"protocol":"dio","payload":"27BEEF90"}"#).unwrap();
+        let request = server.join().unwrap();
+        assert_that!(&request.starts_with("POST /hooks/remote HTTP/1.1\r\n"), eq(true));
+        assert_that!(&request.ends_with("\r\n\r\n{\"protocol\":\"dio\",\"payload\":\"27BEEF90\"}"), eq(true));
+    }
+
+    #[test]
+    fn reject_https_webhooks_of_servers_nobody_trusts() {
+        let (certificate, key) = crate::tls::self_signed(&["localhost".to_string()]).unwrap();
+        let certificates = rustls_pemfile::certs(&mut certificate.as_bytes()).unwrap().into_iter().map(rustls::Certificate).collect();
+        let key = rustls::PrivateKey(rustls_pemfile::pkcs8_private_keys(&mut key.as_bytes()).unwrap().remove(0));
+        let config = std::sync::Arc::new(rustls::ServerConfig::builder().with_safe_defaults().with_no_client_auth().with_single_cert(certificates, key).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://localhost:{}/hooks/remote", listener.local_addr().unwrap().port());
+        thread::spawn(move || {
+            let mut connection = rustls::ServerConnection::new(config).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = connection.complete_io(&mut stream);
+        });
+        assert_that!(&post(&url, "{}").is_err(), eq(true));
+    }
+}
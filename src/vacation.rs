@@ -0,0 +1,186 @@
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::Duration;
+use serde::Serialize;
+use crate::config::{Action, Presence, Simulated};
+use crate::clock::{self, Minute};
+use crate::house::{House, LightStatus};
+use crate::trigger;
+use crate::events;
+
+/// minutes skipped by the wall clock are caught up to this limit
+const CATCH_UP: i64 = 60;
+const TICK: Duration = Duration::from_secs(15);
+
+/// splitmix64, good enough to look random and the same for a same seed
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// in 0..n, 0 when n isn't positive
+    fn below(&mut self, n: i64) -> i64 {
+        if n <= 0 { 0 } else { (self.next() % n as u64) as i64 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Planned {
+    /// milliseconds since epoch
+    pub at: u64,
+    pub action: Action,
+}
+
+/// Simulates someone at home, the times change every day and can only be guessed knowing the seed.
+pub struct Vacation {
+    presences: Vec<Presence>,
+    seed: u64,
+    last: Mutex<Option<Minute>>,
+}
+
+impl Vacation {
+    pub fn new(presences: Vec<Presence>, seed: u64) -> Self {
+        Vacation { presences, seed, last: Mutex::new(None) }
+    }
+
+    /// what will be done for the windows starting on the day of the minute, windows may end the next day
+    fn plan(&self, day: Minute) -> Vec<(Minute, Action)> {
+        let midnight = day.midnight();
+        let mut random = Random(self.seed ^ (midnight.0 / 1440) as u64);
+        let mut plan = vec![];
+        for presence in &self.presences {
+            let (after, before) = (presence.after, presence.before);
+            let start = Minute(midnight.0 + after.hour as i64 * 60 + after.minute as i64);
+            let end = Minute(midnight.0 + before.hour as i64 * 60 + before.minute as i64 + if before <= after { 1440 } else { 0 });
+            let at = Minute(start.0 + random.below(end.0 - start.0));
+            match presence.device {
+                Simulated::Light { light } => {
+                    let off = Minute(at.0 + 1 + random.below(end.0 - at.0));
+                    plan.push((at, Action::Light { light, status: LightStatus::ON }));
+                    plan.push((off, Action::Light { light, status: LightStatus::OFF }));
+                }
+                Simulated::Blinds { blinds, status } => plan.push((at, Action::Blinds { blinds, status })),
+            }
+        }
+        plan.sort_by_key(|(minute, _)| *minute);
+        plan
+    }
+
+    /// the actions planned for the night starting on the day of the timestamp
+    pub fn tonight(&self, now: u64) -> Vec<Planned> {
+        self.plan(clock::local(now)).into_iter()
+            .map(|(minute, action)| Planned { at: clock::timestamp(minute), action })
+            .collect()
+    }
+
+    /// the actions due since the previous tick
+    pub fn tick(&self, now: u64) -> Vec<Action> {
+        let current = clock::local(now);
+        let mut last = self.last.lock().expect("Can't get lock on last tick");
+        let from = match *last {
+            Some(l) if l <= current && current.0 - l.0 <= CATCH_UP => l,
+            Some(l) if l > current => return vec![],
+            _ => Minute(current.0 - 1),
+        };
+        *last = Some(current);
+        let yesterday = Minute(current.0 - 1440);
+        self.plan(yesterday).into_iter().chain(self.plan(current))
+            .filter(|(minute, _)| from < *minute && *minute <= current)
+            .map(|(_, action)| action)
+            .collect()
+    }
+}
+
+/// runs the planned actions while the house is on vacation, for ever
+pub fn run<H>(house: &H, vacation: &Vacation) where H: House + ?Sized {
+    loop {
+        let actions = vacation.tick(clock::now());
+        if house.state().vacation {
+            for action in actions {
+                if let Err(e) = trigger::run(house, &action, &serde_json::json!({ "vacation": true })) {
+                    events::error(format!("Can't run {:?}: {}", action, e));
+                }
+            }
+        }
+        sleep(Duration::from_millis(60_000 - clock::now() % 60_000).min(TICK));
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Configuration;
+    use crate::clock::TimeOfDay;
+    use crate::house::{BlindStatus, Room};
+
+    fn vacation(seed: u64) -> Vacation {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[vacation]]
+            light = "LivingRoom"
+            after = "19:00"
+            before = "00:30"
+
+            [[vacation]]
+            blinds = "BedRoom"
+            status = "down"
+            after = "21:00"
+            before = "22:00"
+        "#.parse().unwrap();
+        Vacation::new(configuration.vacation, seed)
+    }
+
+    fn minute(day: u8, time: &str) -> Minute {
+        Minute::new(2019, 8, day, time.parse::<TimeOfDay>().unwrap())
+    }
+
+    #[test]
+    fn plan_actions_within_their_windows() {
+        for seed in 0..100 {
+            let plan = vacation(seed).plan(minute(10, "12:00"));
+            let on = plan.iter().position(|(_, a)| *a == Action::Light { light: Room::LivingRoom, status: LightStatus::ON }).unwrap();
+            let off = plan.iter().position(|(_, a)| *a == Action::Light { light: Room::LivingRoom, status: LightStatus::OFF }).unwrap();
+            let down = plan.iter().find(|(_, a)| *a == Action::Blinds { blinds: Room::BedRoom, status: BlindStatus::DOWN }).unwrap().0;
+            assert_that!(&(minute(10, "19:00") <= plan[on].0 && plan[on].0 < plan[off].0 && plan[off].0 <= minute(11, "00:30")), eq(true));
+            assert_that!(&(minute(10, "21:00") <= down && down < minute(10, "22:00")), eq(true));
+        }
+    }
+
+    #[test]
+    fn plan_the_same_night_for_the_same_seed() {
+        assert_that!(&vacation(42).plan(minute(10, "12:00")), eq(vacation(42).plan(minute(10, "20:00"))));
+        assert_that!(&(vacation(42).plan(minute(10, "12:00")) != vacation(43).plan(minute(10, "12:00"))), eq(true));
+        assert_that!(&(vacation(42).plan(minute(10, "12:00"))[0].0.time() != vacation(42).plan(minute(11, "12:00"))[0].0.time()), eq(true));
+    }
+
+    #[test]
+    fn run_each_planned_action_once() {
+        let vacation = vacation(7);
+        let mut actions = vec![];
+        let mut minute = minute(10, "18:00");
+        while minute < self::minute(11, "02:00") {
+            actions.extend(vacation.tick(clock::timestamp(minute) + 10_000));
+            actions.extend(vacation.tick(clock::timestamp(minute) + 40_000));
+            minute = Minute(minute.0 + 1);
+        }
+        let planned = vacation.plan(self::minute(10, "12:00")).into_iter().map(|(_, a)| a).collect::<Vec<_>>();
+        assert_that!(&actions, eq(planned));
+    }
+
+    #[test]
+    fn show_tonight_plan() {
+        let vacation = vacation(7);
+        let tonight = vacation.tonight(clock::timestamp(minute(10, "12:00")));
+        assert_that!(&tonight.len(), eq(3));
+        assert_that!(&serde_json::to_value(&tonight[0]).unwrap()["at"].is_u64(), eq(true));
+    }
+}
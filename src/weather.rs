@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::clock;
+use crate::config::{Action, Automation, Thermometer};
+use crate::house::Room;
+use crate::rule::Event;
+use crate::radio::Signal;
+
+pub mod oregon;
+pub mod lacrosse;
+
+/// readings are kept for a day
+const RETENTION: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Reading {
+    pub protocol: String,
+    /// rolling code, changes when the batteries are replaced
+    pub id: u8,
+    pub channel: u8,
+    /// in °C
+    pub temperature: Option<f32>,
+    /// in %
+    pub humidity: Option<u8>,
+    pub battery_low: bool,
+}
+
+impl Reading {
+    pub fn sensor(&self) -> String {
+        format!("{}-{}-{:02x}", self.protocol, self.channel, self.id)
+    }
+}
+
+/// readings of every known weather sensor in the signals
+pub fn decode(signals: &[Signal]) -> Vec<Reading> {
+    let mut readings = oregon::decode(signals);
+    readings.append(&mut lacrosse::decode(signals));
+    readings
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Point {
+    /// milliseconds since epoch
+    pub timestamp: u64,
+    pub temperature: Option<f32>,
+    pub humidity: Option<u8>,
+    pub battery_low: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Series {
+    pub sensor: String,
+    pub room: Option<Room>,
+    pub points: VecDeque<Point>,
+}
+
+/// time series of the readings and automations reacting to them
+pub struct Weather {
+    thermometers: Vec<Thermometer>,
+    automations: Vec<Automation>,
+    series: Mutex<Vec<Series>>,
+}
+
+impl Weather {
+    pub fn new(thermometers: Vec<Thermometer>, automations: Vec<Automation>) -> Self {
+        Weather { thermometers, automations, series: Mutex::new(vec![]) }
+    }
+
+    /// stores the reading, returns the actions of the automations whose condition just became true
+    pub fn record(&self, reading: &Reading, timestamp: u64) -> Vec<Action> {
+        let room = self.room(reading);
+        let before = room.and_then(|r| self.temperature(r));
+        {
+            let mut series = self.series.lock().expect("Can't get lock on series");
+            let sensor = reading.sensor();
+            let index = match series.iter().position(|s| s.sensor == sensor) {
+                Some(index) => index,
+                None => {
+                    series.push(Series { sensor, room, points: VecDeque::new() });
+                    series.len() - 1
+                }
+            };
+            let points = &mut series[index].points;
+            points.push_back(Point {
+                timestamp,
+                temperature: reading.temperature,
+                humidity: reading.humidity,
+                battery_low: reading.battery_low,
+            });
+            while points.front().map_or(false, |p| p.timestamp + RETENTION < timestamp) {
+                points.pop_front();
+            }
+        }
+        match (room, reading.temperature) {
+            (Some(room), Some(temperature)) => {
+                let event = Event::Temperature { room, temperature, previous: before };
+                let time = clock::local_time(timestamp);
+                self.automations.iter().filter(|a| a.condition.becomes_true(&event, time)).map(|a| a.action.clone()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// where the thermometer of the reading is
+    pub fn room(&self, reading: &Reading) -> Option<Room> {
+        self.thermometers.iter().find(|t| t.reads(reading)).map(|t| t.room)
+    }
+
+    pub fn series(&self) -> Vec<Series> {
+        self.series.lock().expect("Can't get lock on series").clone()
+    }
+
+    /// latest temperature heard in a room
+    pub fn temperature(&self, room: Room) -> Option<f32> {
+        self.series.lock().expect("Can't get lock on series").iter()
+            .filter(|s| s.room == Some(room))
+            .filter_map(|s| s.points.iter().rev().find_map(|p| p.temperature.map(|t| (p.timestamp, t))))
+            .max_by_key(|(timestamp, _)| *timestamp)
+            .map(|(_, temperature)| temperature)
+    }
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use crate::config::Configuration;
+    use crate::house::BlindStatus;
+
+    fn weather() -> Weather {
+        let configuration: Configuration = r#"
+            radios = []
+            devices = []
+
+            [[thermometers]]
+            room = "LivingRoom"
+            protocol = "oregon"
+            channel = 1
+
+            [[automations]]
+            room = "LivingRoom"
+            above = 26
+            blinds = "LivingRoom"
+            status = "down"
+        "#.parse().unwrap();
+        Weather::new(configuration.thermometers, configuration.automations)
+    }
+
+    fn reading(temperature: f32) -> Reading {
+        Reading { protocol: "oregon".to_string(), id: 0xa5, channel: 1, temperature: Some(temperature), humidity: Some(40), battery_low: false }
+    }
+
+    #[test]
+    fn run_automations_when_their_condition_becomes_true() {
+        let weather = weather();
+        assert_that!(&weather.record(&reading(25.0), 0), eq(vec![]));
+        assert_that!(&weather.record(&reading(26.5), 1), eq(vec![Action::Blinds { blinds: Room::LivingRoom, status: BlindStatus::DOWN }]));
+        assert_that!(&weather.record(&reading(27.0), 2), eq(vec![]));
+        assert_that!(&weather.temperature(Room::LivingRoom), eq(Some(27.0)));
+    }
+
+    #[test]
+    fn keep_a_day_of_readings_per_sensor() {
+        let weather = weather();
+        weather.record(&reading(20.0), 0);
+        weather.record(&Reading { channel: 2, ..reading(18.0) }, 0);
+        weather.record(&reading(21.0), RETENTION + 1);
+        let series = weather.series();
+        assert_that!(&series.len(), eq(2));
+        assert_that!(&series[0].room, eq(Some(Room::LivingRoom)));
+        assert_that!(&series[0].points.iter().map(|p| p.temperature).collect::<Vec<_>>(), eq(vec![Some(21.0)]));
+        assert_that!(&series[1].room, eq(None));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::radio::Signal;
+use crate::radio_protocol::merge;
+use super::Reading;
+
+/// lows between bits last about 1ms, longer ones end a message
+const END: u64 = 2500;
+const NIBBLES: usize = 11;
+
+/// readings of TX3 and TX4 sensors, a short high is a one, a long high is a zero,
+/// temperature and humidity are sent as separate messages
+pub fn decode(signals: &[Signal]) -> Vec<Reading> {
+    let mut readings = vec![];
+    let mut bits = vec![];
+    for (high, micros) in merge(signals).into_iter().chain(Some((false, u64::MAX))) {
+        match (high, micros) {
+            (true, 300..=900) => bits.push(true),
+            (true, 901..=2000) => bits.push(false),
+            (true, _) => bits.clear(),
+            (false, d) if d > END => {
+                if let Some(reading) = message(&bits) {
+                    if !readings.contains(&reading) {
+                        readings.push(reading);
+                    }
+                }
+                bits.clear();
+            }
+            (false, _) => {}
+        }
+    }
+    readings
+}
+
+fn message(bits: &[bool]) -> Option<Reading> {
+    bits.windows(NIBBLES * 4).find_map(|window| {
+        let nibbles = window.chunks(4)
+            .map(|n| n.iter().fold(0, |nibble, &bit| (nibble << 1) | bit as u8))
+            .collect::<Vec<_>>();
+        parse(&nibbles)
+    })
+}
+
+/// 0x0A, type, 7 bits id and a parity bit, 3 digits value, its 2 first digits again then the sum of the previous nibbles
+fn parse(n: &[u8]) -> Option<Reading> {
+    let checksum = n[..10].iter().map(|&n| n as u32).sum::<u32>() & 0xf;
+    if n[0] != 0x0 || n[1] != 0xa || checksum != n[10] as u32 || n[8] != n[5] || n[9] != n[6] || n[5..8].iter().any(|&d| d > 9) {
+        return None;
+    }
+    let (temperature, humidity) = match n[2] {
+        0x0 => (Some((n[5] as i32 * 100 + n[6] as i32 * 10 + n[7] as i32 - 500) as f32 / 10.0), None),
+        0xe => (None, Some(n[5] * 10 + n[6])),
+        _ => return None,
+    };
+    Some(Reading {
+        protocol: "lacrosse".to_string(),
+        id: n[3] << 3 | n[4] >> 1,
+        channel: 0,
+        temperature,
+        humidity,
+        battery_low: false,
+    })
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::time::Duration;
+
+    fn transmit(nibbles: &[u8]) -> Vec<Signal> {
+        let sum = nibbles.iter().map(|&n| n as u32).sum::<u32>() as u8 & 0xf;
+        let mut signals = vec![];
+        for nibble in nibbles.iter().chain(Some(&sum)) {
+            for i in (0..4).rev() {
+                let high = if nibble >> i & 1 == 1 { 550 } else { 1400 };
+                signals.push(Signal::HIGH(Duration::from_micros(high)));
+                signals.push(Signal::LOW(Duration::from_micros(1000)));
+            }
+        }
+        signals.push(Signal::LOW(Duration::from_millis(15)));
+        signals
+    }
+
+    #[test]
+    fn decode_temperature_and_humidity() {
+        let temperature = transmit(&[0x0, 0xa, 0x0, 0x5, 0x6, 0x7, 0x2, 0x3, 0x7, 0x2]);
+        let humidity = transmit(&[0x0, 0xa, 0xe, 0x5, 0x6, 0x4, 0x8, 0x0, 0x4, 0x8]);
+        let signals = [temperature.clone(), temperature, humidity].concat();
+        assert_that!(&decode(&signals), eq(vec![
+            Reading { protocol: "lacrosse".to_string(), id: 0x2b, channel: 0, temperature: Some(22.3), humidity: None, battery_low: false },
+            Reading { protocol: "lacrosse".to_string(), id: 0x2b, channel: 0, temperature: None, humidity: Some(48), battery_low: false },
+        ]));
+    }
+
+    #[test]
+    fn reject_inconsistent_messages() {
+        assert_that!(&decode(&transmit(&[0x0, 0xa, 0x0, 0x5, 0x6, 0x7, 0x2, 0x3, 0x7, 0x3])), eq(vec![]));
+    }
+}
@@ -0,0 +1,179 @@
+use std::iter;
+use crate::radio::Signal;
+use crate::radio_protocol::merge;
+use super::Reading;
+
+/// half of a bit at 1024 bits per second
+const HALF_BIT: u64 = 488;
+/// messages start with a run of ones, partly lost while the receiver settles
+const MIN_PREAMBLE: usize = 8;
+/// 0xA sent least significant bit first
+const SYNC: [bool; 4] = [false, true, false, true];
+
+#[derive(Copy, Clone)]
+enum Version {
+    /// every bit is sent twice, inverted then as is
+    V2,
+    V3,
+}
+
+/// readings of version 2.1 and 3 sensors, manchester encoded with nibbles sent least significant bit first
+pub fn decode(signals: &[Signal]) -> Vec<Reading> {
+    let mut readings = vec![];
+    for run in half_bits(signals) {
+        for phase in 0..2.min(run.len()) {
+            let bits = manchester(&run[phase..]);
+            // which transition is a one depends on the receiver
+            for inverted in [false, true] {
+                let bits = bits.iter().map(|b| b ^ inverted).collect::<Vec<_>>();
+                let mut candidates = vec![message(&bits, Version::V3)];
+                for offset in 0..2.min(bits.len()) {
+                    candidates.push(message(&undouble(&bits[offset..]), Version::V2));
+                }
+                for reading in candidates.into_iter().flatten() {
+                    if !readings.contains(&reading) {
+                        readings.push(reading);
+                    }
+                }
+            }
+        }
+    }
+    readings
+}
+
+/// pulses cut in half bits, runs end with pulses of any other length
+fn half_bits(signals: &[Signal]) -> Vec<Vec<bool>> {
+    let mut runs = vec![vec![]];
+    for (high, micros) in merge(signals) {
+        let halves = (micros + HALF_BIT / 2) / HALF_BIT;
+        match halves {
+            1 | 2 if micros >= HALF_BIT / 2 => runs.last_mut().unwrap().extend(iter::repeat(high).take(halves as usize)),
+            _ => {
+                if !high {
+                    // the last half of the message got merged into the silence after it
+                    runs.last_mut().unwrap().push(false);
+                }
+                runs.push(vec![]);
+            }
+        }
+    }
+    runs.into_iter().filter(|r| r.len() > MIN_PREAMBLE).collect()
+}
+
+/// a high then low half is a one, stops at the first pair without transition
+fn manchester(halves: &[bool]) -> Vec<bool> {
+    halves.chunks_exact(2).take_while(|pair| pair[0] != pair[1]).map(|pair| pair[0]).collect()
+}
+
+fn undouble(bits: &[bool]) -> Vec<bool> {
+    bits.chunks_exact(2).take_while(|pair| pair[0] != pair[1]).map(|pair| pair[1]).collect()
+}
+
+fn message(bits: &[bool], version: Version) -> Option<Reading> {
+    (MIN_PREAMBLE..=bits.len().saturating_sub(SYNC.len())).find_map(|start| {
+        if bits[start - MIN_PREAMBLE..start].iter().all(|&b| b) && bits[start..start + SYNC.len()] == SYNC {
+            let nibbles = bits[start + SYNC.len()..].chunks_exact(4)
+                .map(|n| n.iter().rev().fold(0, |nibble, &bit| (nibble << 1) | bit as u8))
+                .collect::<Vec<_>>();
+            parse(&nibbles, version)
+        } else {
+            None
+        }
+    })
+}
+
+/// sensor id, channel, rolling code, flags, temperature, sign then humidity for hygrometers,
+/// followed by the sum of the previous nibbles
+fn parse(nibbles: &[u8], version: Version) -> Option<Reading> {
+    let length = [15, 12].iter().cloned().find(|&length| {
+        nibbles.len() >= length + 2 &&
+            nibbles[..length].iter().map(|&n| n as u32).sum::<u32>() & 0xff == (nibbles[length] | nibbles[length + 1] << 4) as u32
+    })?;
+    let n = &nibbles[..length];
+    if n[8..11].iter().any(|&digit| digit > 9) {
+        return None;
+    }
+    let tenths = n[10] as i32 * 100 + n[9] as i32 * 10 + n[8] as i32;
+    let temperature = if n[11] == 0 { tenths } else { -tenths } as f32 / 10.0;
+    let channel = match (version, n[4]) {
+        (Version::V2, 4) => 3,
+        (_, channel) => channel,
+    };
+    Some(Reading {
+        protocol: "oregon".to_string(),
+        id: n[5] << 4 | n[6],
+        channel,
+        temperature: Some(temperature),
+        humidity: if length == 15 { Some(n[13] * 10 + n[12]) } else { None },
+        battery_low: n[7] & 0x4 != 0,
+    })
+}
+
+#[cfg(test)]
+mod should {
+    use super::*;
+    use galvanic_assert::matchers::*;
+    use std::time::Duration;
+
+    fn with_checksum(nibbles: &[u8]) -> Vec<u8> {
+        let sum = nibbles.iter().map(|&n| n as u32).sum::<u32>();
+        [nibbles, &[(sum & 0xf) as u8, (sum >> 4 & 0xf) as u8]].concat()
+    }
+
+    fn transmit(nibbles: &[u8], version: Version, inverted: bool) -> Vec<Signal> {
+        let mut bits = vec![true; 24];
+        bits.extend_from_slice(&SYNC);
+        for nibble in with_checksum(nibbles) {
+            bits.extend((0..4).map(|i| nibble >> i & 1 == 1));
+        }
+        if let Version::V2 = version {
+            bits = bits.iter().flat_map(|&b| vec![!b, b]).collect();
+        }
+        let mut signals = vec![Signal::LOW(Duration::from_millis(10))];
+        for half in bits.iter().flat_map(|&b| vec![b, !b]) {
+            let d = Duration::from_micros(HALF_BIT);
+            signals.push(if half ^ inverted { Signal::HIGH(d) } else { Signal::LOW(d) });
+        }
+        signals.push(Signal::LOW(Duration::from_millis(10)));
+        signals
+    }
+
+    #[test]
+    fn decode_v2_hygrometers() {
+        let thgr122n = [0x1, 0xd, 0x2, 0x0, 0x4, 0xa, 0x5, 0x0, 0x4, 0x3, 0x2, 0x0, 0x6, 0x5, 0x0];
+        let signals = [transmit(&thgr122n, Version::V2, false), transmit(&thgr122n, Version::V2, false)].concat();
+        assert_that!(&decode(&signals), eq(vec![Reading {
+            protocol: "oregon".to_string(),
+            id: 0xa5,
+            channel: 3,
+            temperature: Some(23.4),
+            humidity: Some(56),
+            battery_low: false,
+        }]));
+    }
+
+    #[test]
+    fn decode_v3_thermometers_whatever_the_polarity() {
+        let thn132n = [0xe, 0xc, 0x4, 0x0, 0x2, 0x1, 0x7, 0x4, 0x2, 0x5, 0x0, 0x8];
+        for inverted in [false, true] {
+            assert_that!(&decode(&transmit(&thn132n, Version::V3, inverted)), eq(vec![Reading {
+                protocol: "oregon".to_string(),
+                id: 0x17,
+                channel: 2,
+                temperature: Some(-5.2),
+                humidity: None,
+                battery_low: true,
+            }]));
+        }
+    }
+
+    #[test]
+    fn reject_corrupted_messages() {
+        let mut signals = transmit(&[0x1, 0xd, 0x2, 0x0, 0x1, 0xa, 0x5, 0x0, 0x4, 0x3, 0x2, 0x0, 0x6, 0x5, 0x0], Version::V3, false);
+        signals[100] = match signals[100] {
+            Signal::HIGH(d) => Signal::LOW(d),
+            Signal::LOW(d) => Signal::HIGH(d),
+        };
+        assert_that!(&decode(&signals), eq(vec![]));
+    }
+}
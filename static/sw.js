@@ -0,0 +1,34 @@
+// keeps the page and its assets so janet opens without internet, the api always goes to the network
+const CACHE = "janet-v2";
+const ASSETS = ["/", "/css/janet.css", "/img/janet.jpg", "/img/icon.svg", "/manifest.json"];
+
+self.addEventListener("install", event => {
+    event.waitUntil(caches.open(CACHE).then(cache => cache.addAll(ASSETS)).then(() => self.skipWaiting()));
+});
+
+self.addEventListener("activate", event => {
+    event.waitUntil(caches.keys()
+        .then(keys => Promise.all(keys.filter(key => key !== CACHE).map(key => caches.delete(key))))
+        .then(() => self.clients.claim()));
+});
+
+self.addEventListener("fetch", event => {
+    const url = new URL(event.request.url);
+    if (event.request.method !== "GET" || url.origin !== location.origin || url.pathname.startsWith("/api/")) {
+        return;
+    }
+    if (event.request.mode === "navigate") {
+        // the page shows the state of the house, it is only taken from the cache when janet can't be reached
+        event.respondWith(fetch(event.request)
+            .then(response => {
+                if (response.ok && !response.redirected) {
+                    const copy = response.clone();
+                    caches.open(CACHE).then(cache => cache.put("/", copy));
+                }
+                return response;
+            })
+            .catch(() => caches.match("/")));
+        return;
+    }
+    event.respondWith(caches.match(event.request).then(cached => cached || fetch(event.request)));
+});
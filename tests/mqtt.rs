@@ -0,0 +1,187 @@
+#[macro_use]
+extern crate galvanic_assert;
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use galvanic_assert::matchers::*;
+use janet::config::{Broker, Code, Device, Kind};
+use janet::events::{self, HouseEvent};
+use janet::house::{BlindStatus, Command, House, LightStatus, Room, State};
+use janet::mqtt::{Bridge, Client, Packet};
+use janet::radio_protocol::RadioProtocol;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// a house remembering what it was told
+#[derive(Default)]
+struct FakeHouse {
+    state: Mutex<State>,
+}
+
+impl House for FakeHouse {
+    fn light(&self, room: Room, status: LightStatus) {
+        self.state.lock().unwrap().lights.insert(room, status);
+    }
+    fn blinds(&self, room: Room, status: BlindStatus) {
+        self.state.lock().unwrap().blinds.insert(room, status);
+    }
+    fn screen(&self, status: BlindStatus) {
+        self.state.lock().unwrap().screen = Some(status);
+    }
+    fn cinema(&self) {}
+    fn goodmorning(&self) {}
+    fn goodnight(&self) {}
+    fn vacation(&self, enabled: bool) {
+        self.state.lock().unwrap().vacation = enabled;
+    }
+    fn state(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+    fn received(&self, _protocol: &str, _payload: &[u8]) -> Option<Vec<Command>> {
+        Some(vec![])
+    }
+    fn transmit(&self, _radio: Option<&str>, _protocol: &RadioProtocol<Vec<u8>>, _payload: &[u8]) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+/// a broker listening on a free local port
+fn start_broker() -> u16 {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let config: rumqttd::Config = toml::from_str(&format!(r#"
+        id = 0
+
+        [router]
+        id = 0
+        max_connections = 10
+        max_outgoing_packet_count = 200
+        max_segment_size = 1048576
+        max_segment_count = 10
+
+        [v4.1]
+        name = "v4-1"
+        listen = "127.0.0.1:{}"
+        next_connection_delay_ms = 1
+
+        [v4.1.connections]
+        connection_timeout_ms = 60000
+        max_payload_size = 20480
+        max_inflight_count = 100
+        dynamic_filters = true
+    "#, port)).unwrap();
+    thread::spawn(move || rumqttd::Broker::new(config).start().unwrap());
+    let start = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(start.elapsed() < TIMEOUT, "The broker didn't start");
+        thread::sleep(Duration::from_millis(50));
+    }
+    port
+}
+
+fn broker(port: u16, client_id: &str) -> Broker {
+    toml::from_str(&format!("host = \"127.0.0.1\"\nport = {}\nclient_id = \"{}\"", port, client_id)).unwrap()
+}
+
+/// every message published on the topics, as text
+fn listen(client: Arc<Client>, filters: &[&str]) -> Receiver<(String, String)> {
+    client.subscribe(filters.iter().map(|f| f.to_string()).collect()).unwrap();
+    loop {
+        if let Packet::SubAck { .. } = client.receive().unwrap() {
+            break;
+        }
+    }
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        while let Ok(packet) = client.receive() {
+            if let Packet::Publish { topic, payload, .. } = packet {
+                if sender.send((topic, String::from_utf8_lossy(&payload).to_string())).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn wait_for(messages: &Receiver<(String, String)>, topic: &str) -> String {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        if let Ok((t, payload)) = messages.recv_timeout(TIMEOUT) {
+            if t == topic {
+                return payload;
+            }
+        }
+    }
+    panic!("Nothing published on {}", topic)
+}
+
+#[test]
+fn bridge_the_house_with_a_broker() {
+    let port = start_broker();
+    let test = Arc::new(Client::connect(&broker(port, "test"), None).unwrap());
+    let messages = listen(test.clone(), &["janet/#", "homeassistant/#"]);
+
+    let house = Arc::new(FakeHouse::default());
+    house.light(Room::LivingRoom, LightStatus::OFF);
+    let devices = vec![
+        Device { kind: Kind::Light, room: Some(Room::LivingRoom), code: Code::Dio { address: 1 }, radio: "upstairs".to_string(), dimmer: false, travel: None },
+        Device { kind: Kind::Blinds, room: Some(Room::BedRoom), code: Code::Dooya, radio: "upstairs".to_string(), dimmer: false, travel: None },
+    ];
+    let configuration = broker(port, "janet");
+    let bridge = Bridge::new(&configuration, &devices);
+    let h = house.clone();
+    thread::spawn(move || janet::mqtt::run(&*h, &bridge, &configuration));
+
+    let light = wait_for(&messages, "homeassistant/light/janet_light_livingroom/config");
+    let light: serde_json::Value = serde_json::from_str(&light).unwrap();
+    assert_that!(&light["command_topic"], eq(serde_json::json!("janet/light/LivingRoom/set")));
+    assert_that!(&wait_for(&messages, "janet/status"), eq("online".to_string()));
+    assert_that!(&wait_for(&messages, "janet/light/LivingRoom"), eq("OFF".to_string()));
+
+    test.publish("janet/light/LivingRoom/set", b"ON", false).unwrap();
+    let start = Instant::now();
+    while house.state().lights.get(&Room::LivingRoom) != Some(&LightStatus::ON) {
+        assert!(start.elapsed() < TIMEOUT, "The light wasn't switched on");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let mut state = house.state();
+    state.blinds.insert(Room::BedRoom, BlindStatus::DOWN);
+    events::publish(HouseEvent::StateChanged { state });
+    assert_that!(&wait_for(&messages, "janet/blinds/BedRoom"), eq("closed".to_string()));
+}
+
+#[test]
+fn obey_snips_intents() {
+    let port = start_broker();
+    let snips = Arc::new(Client::connect(&broker(port, "snips"), None).unwrap());
+    let messages = listen(snips.clone(), &["hermes/tts/say"]);
+
+    let house = Arc::new(FakeHouse::default());
+    let devices = vec![Device { kind: Kind::Light, room: Some(Room::Kitchen), code: Code::Dio { address: 1 }, radio: "upstairs".to_string(), dimmer: false, travel: None }];
+    let (h, configuration) = (house.clone(), broker(port, "janet"));
+    thread::spawn(move || janet::hermes::run(&*h, &devices, &configuration));
+
+    let intent = serde_json::json!({
+        "sessionId": "42",
+        "siteId": "default",
+        "intent": { "intentName": "xadet:turnlightson" },
+        "slots": [{ "slotName": "room", "value": { "kind": "Custom", "value": "kitchen" } }],
+    });
+    // janet may not have subscribed yet, snips keeps asking until it answers
+    let start = Instant::now();
+    let answer = loop {
+        assert!(start.elapsed() < TIMEOUT, "Janet didn't answer");
+        snips.publish("hermes/intent/xadet:turnlightson", intent.to_string().as_bytes(), false).unwrap();
+        if let Ok((_, answer)) = messages.recv_timeout(Duration::from_millis(200)) {
+            break answer;
+        }
+    };
+    let answer: serde_json::Value = serde_json::from_str(&answer).unwrap();
+    assert_that!(&answer["text"], eq(serde_json::json!("Light of the kitchen switched on")));
+    assert_that!(&answer["sessionId"], eq(serde_json::json!("42")));
+    assert_that!(&house.state().lights.get(&Room::Kitchen).copied(), eq(Some(LightStatus::ON)));
+}